@@ -0,0 +1,38 @@
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    spotify_id TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL
+);
+
+-- Accounts on other providers that can be used to log in as a user
+CREATE TABLE identities (
+    provider TEXT NOT NULL,
+    provider_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    login TEXT NOT NULL,
+    PRIMARY KEY (provider, provider_id),
+    UNIQUE (user_id, provider)
+);
+
+-- Sealed with the key from TOKEN_KEYS that has the id
+CREATE TABLE refresh_tokens (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    key_id INTEGER NOT NULL,
+    refresh_token BLOB NOT NULL,
+    scope TEXT NOT NULL,
+    client TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE bangers (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    track_id TEXT NOT NULL,
+    marked_at INTEGER NOT NULL,
+    progress_ms INTEGER NOT NULL,
+    device TEXT,
+    context TEXT,
+    note TEXT
+);
+
+CREATE INDEX bangers_by_user ON bangers (user_id, marked_at);
@@ -0,0 +1,13 @@
+-- Sessions used to only live in memory, so everyone was logged out on every
+-- deploy. Only a hash of the id handed out in the cookie is kept, the token
+-- cache stays in memory and is rebuilt from the stored refresh token
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY,
+    token_hash BLOB NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    user_agent TEXT
+);
+
+CREATE INDEX sessions_by_user ON sessions (user_id, last_seen);
@@ -0,0 +1,13 @@
+-- Bangers can be filtered by album and artist, so both are kept from when the
+-- track was marked. Bangers marked before this have neither
+ALTER TABLE bangers ADD COLUMN album_id TEXT;
+
+CREATE TABLE banger_artists (
+    banger_id INTEGER NOT NULL REFERENCES bangers (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    artist_id TEXT NOT NULL,
+    PRIMARY KEY (banger_id, position)
+);
+
+CREATE INDEX banger_artists_by_artist ON banger_artists (artist_id);
+CREATE INDEX bangers_by_context ON bangers (user_id, context);
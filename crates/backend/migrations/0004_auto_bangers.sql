@@ -0,0 +1,6 @@
+-- Bangers can also be detected from how the track was listened to, in which
+-- case the detector says how sure it was. Everything before was marked by hand
+ALTER TABLE bangers ADD COLUMN source TEXT NOT NULL DEFAULT 'manual';
+ALTER TABLE bangers ADD COLUMN confidence INTEGER;
+
+CREATE INDEX bangers_by_source ON bangers (user_id, source);
@@ -0,0 +1,17 @@
+-- Users can have the backend listen along, so that bangers are detected and
+-- plays are kept even while no tab is open
+ALTER TABLE users ADD COLUMN listening INTEGER NOT NULL DEFAULT 0;
+
+-- Stretches of time that a track kept playing for, as seen by the listener
+CREATE TABLE plays (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    track_id TEXT NOT NULL,
+    context TEXT,
+    device TEXT,
+    started_at INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    progress_ms INTEGER NOT NULL
+);
+
+CREATE INDEX plays_by_user ON plays (user_id, started_at);
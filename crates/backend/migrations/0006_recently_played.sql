@@ -0,0 +1,8 @@
+-- Plays are backfilled from what spotify remembers the user played, picking
+-- up after the newest play that was backfilled before
+ALTER TABLE users ADD COLUMN recently_played_after INTEGER;
+
+-- When spotify says the track was played, which is unique for every play
+ALTER TABLE plays ADD COLUMN played_at INTEGER;
+
+CREATE UNIQUE INDEX plays_by_played_at ON plays (user_id, played_at);
@@ -1,93 +1,234 @@
 use std::{
-    collections::HashSet,
     env,
     fmt::{self, Display},
-    str::FromStr,
-    sync::{Arc, Mutex},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use axum::{
-    body::Body,
-    extract::Query,
-    http::{status::StatusCode, HeaderValue, Request},
-    response::{IntoResponse, Redirect, Response},
-    routing::get,
-    Extension, Router,
+    headers::UserAgent,
+    http::{status::StatusCode, HeaderValue},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post},
+    Extension, Json, Router, TypedHeader,
+};
+use axum_extra::extract::{
+    cookie::{Cookie, Key, SameSite},
+    CookieJar,
 };
-use base64::display::Base64Display;
 use monostate::MustBe;
-use rand::Rng;
 use reqwest::{header, Method};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spotify_banger_model::{ClientConfig, Features};
+use spotify_banger_web_api::{RetryMetrics, Tokio};
+use tokio::sync::MutexGuard;
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, ServiceBuilderExt};
-use tracing::warn;
+use tracing::{error, warn};
+
+use self::{
+    github::{GithubConfig, GithubProvider},
+    health::Health,
+    listener::Listener,
+    oauth_state::{OAuthStateMetrics, OAuthStates, State},
+    provider::OAuthProviders,
+    sessions::CurrentSession,
+    spotify::{SpotifyProvider, WebApi, SPOTIFY_TOKEN_URL},
+};
+use crate::{
+    config::Config,
+    crypto::TokenCipher,
+    detect::Detector,
+    serde::from_to_str,
+    session::{
+        Session, SessionId, SessionStorage, SessionTokens, SpotifyTokens, TokenClient,
+        SESSION_COOKIE, SESSION_TTL,
+    },
+    shutdown::Workers,
+    storage::{Storage, StorageError},
+    users::UserId,
+};
+
+mod bangers;
+mod github;
+mod health;
+mod listener;
+mod oauth_state;
+mod provider;
+mod sessions;
+mod spotify;
+mod stats;
+
+/// How long to wait for spotify or github to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long any one request to spotify or github may take, so that a hung
+/// one cannot hold up the listener, which polls one user after the other
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The http client that everything talking to spotify or github shares
+fn http_client() -> reqwest::ClientBuilder {
+    reqwest::ClientBuilder::new()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(HTTP_TIMEOUT)
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION"),
+        ))
+}
+
+pub fn create_router(config: &Config, storage: Storage, workers: &mut Workers) -> Router {
+    let cors = CorsLayer::new()
+        .allow_credentials(false)
+        .allow_headers([])
+        .allow_methods([Method::GET])
+        .allow_origin(
+            config
+                .cors_origins
+                .iter()
+                .map(|origin| origin.parse().expect("cors origins are valid headers"))
+                .collect::<Vec<HeaderValue>>(),
+        );
+    let cookie_key = config.cookie_secret.0.clone();
+    let detector = config
+        .auto_bangers
+        .detector()
+        .map(Detector::with_default_rules);
+    let static_dir = Arc::new(config.static_dir.clone());
+    let config = OAuthConfig::new(config);
+    let reqwest = http_client()
+        .https_only(true)
+        .use_native_tls()
+        .build()
+        .unwrap();
+    let transport = WebApi::new(reqwest.clone(), Tokio);
+
+    let listener = Listener::new(
+        config.clone(),
+        reqwest.clone(),
+        transport.clone(),
+        storage.clone(),
+        detector.clone(),
+    );
+    workers.spawn("listener", |stop, heartbeat| listener.run(stop, heartbeat));
 
-use crate::serde::from_to_str;
+    let health = Health {
+        static_dir,
+        heartbeats: workers.heartbeats().into(),
+    };
 
-#[cfg(debug_assertions)]
-const ORIGIN: &str = "http://127.0.0.1:8080/";
+    router(
+        config,
+        reqwest,
+        transport,
+        OAuthStates::default(),
+        SessionStorage::new(storage.clone()),
+        storage,
+        detector,
+        cookie_key,
+        health,
+    )
+    .layer(cors)
+}
 
-#[cfg(not(debug_assertions))]
-const ORIGIN: &str = "https://banger.spotify.dusterthefirst.com/";
+#[allow(clippy::too_many_arguments)] // everything the handlers share
+fn router(
+    config: OAuthConfig,
+    reqwest: reqwest::Client,
+    transport: WebApi,
+    states: OAuthStates,
+    sessions: SessionStorage,
+    storage: Storage,
+    detector: Option<Detector>,
+    cookie_key: Key,
+    health: Health,
+) -> Router {
+    let mut providers = OAuthProviders::default().register(SpotifyProvider {
+        config: config.clone(),
+        transport: transport.clone(),
+    });
 
-pub const SPOTIFY_REDIRECT_URI: &str = const_format::concatcp!(ORIGIN, "api/auth/spotify/redirect");
-pub const GITHUB_REDIRECT_URI: &str = const_format::concatcp!(ORIGIN, "api/auth/github/redirect");
+    if let Some(github) = config.github.clone() {
+        providers = providers.register(GithubProvider {
+            config: github,
+            origin: config.origin.clone(),
+        });
+    }
 
-pub fn create_router() -> Router {
     Router::new()
         .route("/healthy", get(|| async { "OK" }))
-        .route("/auth/spotify", get(spotify))
-        .route("/auth/spotify/redirect", get(spotify_redirect))
-        .route("/auth/github", get(|| async { "TODO" }))
-        .route("/auth/github/redirect", get(|| async { "TODO" }))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/metrics", get(metrics))
+        .route("/config", get(client_config))
+        .route("/auth/:provider", get(provider::authorize))
+        .route("/auth/:provider/redirect", get(provider::redirect))
+        .route("/auth/spotify/token", get(spotify_token))
+        .route("/auth/spotify/pkce", post(spotify_pkce))
+        .route("/logout", post(sessions::logout))
+        .route("/sessions", get(sessions::list))
+        .route("/sessions/:id", delete(sessions::revoke))
+        .route("/bangers", get(bangers::list).post(bangers::mark))
+        .route("/bangers/:id", patch(bangers::edit).delete(bangers::forget))
+        .route("/listening", post(bangers::listen))
+        .route(
+            "/listener",
+            get(listener::settings).put(listener::configure),
+        )
+        .route("/stats", get(stats::stats))
+        .route("/tracks/:track_id/drops", get(stats::drops))
         .layer(
             ServiceBuilder::new()
-                .layer(Extension(OAuthStateStorage::default()))
-                .layer(Extension(OAuthConfig::from_env()))
-                .layer(Extension(
-                    reqwest::ClientBuilder::new()
-                        .https_only(true)
-                        .use_native_tls()
-                        .user_agent(concat!(
-                            env!("CARGO_PKG_NAME"),
-                            "/",
-                            env!("CARGO_PKG_VERSION"),
-                        ))
-                        .build()
-                        .unwrap(),
-                ))
+                .layer(Extension(states))
+                .layer(Extension(providers))
+                .layer(Extension(cookie_key))
+                .layer(Extension(sessions))
+                .layer(Extension(storage))
+                .layer(Extension(detector))
+                .layer(Extension(config))
+                .layer(Extension(reqwest))
+                .layer(Extension(transport))
+                .layer(Extension(health))
                 .override_response_header(
                     header::CACHE_CONTROL,
                     HeaderValue::from_static("no-store"),
-                )
-                .layer(
-                    CorsLayer::new()
-                        .allow_credentials(false)
-                        .allow_headers([])
-                        .allow_methods([Method::GET])
-                        .allow_origin([ORIGIN.parse().unwrap()]),
                 ),
         )
 }
 
 #[derive(Debug, Clone)]
 struct OAuthConfig {
+    /// Where users are sent back to once they are done, ending in a slash
+    origin: Arc<str>,
     spotify_client_secret: Arc<str>,
     spotify_client_id: Arc<str>,
-    // github_client_secret: Arc<str>
+    spotify_redirect_uri: Arc<str>,
+    /// Seals the refresh tokens that are kept in storage
+    token_cipher: TokenCipher,
+    spotify_token_url: Arc<str>,
+    spotify_api_url: Arc<str>,
+    /// GitHub login is optional, as it only serves to recover accounts
+    github: Option<GithubConfig>,
 }
 
 impl OAuthConfig {
-    pub fn from_env() -> Self {
+    pub fn new(config: &Config) -> Self {
         Self {
-            spotify_client_secret: Arc::from(
-                env::var("SPOTIFY_CLIENT_SECRET").expect("SPOTIFY_CLIENT_SECRET env var not set"),
-            ),
+            origin: Arc::from(&*config.origin),
 
-            spotify_client_id: Arc::from(
-                env::var("SPOTIFY_CLIENT_ID").expect("SPOTIFY_CLIENT_ID env var not set"),
-            ),
+            spotify_client_secret: Arc::from(&*config.spotify.client_secret.0),
+            spotify_client_id: Arc::from(&*config.spotify.client_id),
+            spotify_redirect_uri: Arc::from(format!("{}api/auth/spotify/redirect", config.origin)),
+
+            token_cipher: config.token_keys.0.clone(),
+
+            spotify_token_url: Arc::from(SPOTIFY_TOKEN_URL),
+            spotify_api_url: Arc::from(spotify_banger_web_api::SPOTIFY_API_URL),
+
+            github: config
+                .github
+                .as_ref()
+                .map(|github| GithubConfig::new(github, &config.origin)),
         }
     }
 }
@@ -119,158 +260,1257 @@ struct CodeGrantResponse {
     state: State,
 }
 
+#[derive(Debug, Deserialize)]
+struct PkceExchange {
+    code: String,
+    code_verifier: String,
+}
+
 #[derive(Debug, Serialize)]
-struct AccessTokenRequest {
+struct PkceTokenRequest<'s> {
     grant_type: MustBe!("authorization_code"),
     code: String,
-    redirect_uri: &'static str,
+    redirect_uri: &'s str,
+    client_id: &'s str,
+    code_verifier: String,
 }
 
 #[derive(Debug, Deserialize)]
-struct AccessTokenResponse {
+pub struct AccessTokenResponse {
     access_token: String,
+    #[allow(dead_code)]
     token_type: MustBe!("Bearer"),
     scope: String,
-    expires_in: i32,
+    expires_in: u64,
     refresh_token: String,
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
-struct State {
-    state: [u8; 128],
+impl AccessTokenResponse {
+    /// This should be called as soon as an [`AccessTokenResponse`] is procured
+    fn into_tokens(self, client: TokenClient) -> SpotifyTokens {
+        SpotifyTokens {
+            access_token: self.access_token,
+            refresh_token: self.refresh_token,
+            scope: self.scope,
+            expires_at: SystemTime::now() + Duration::from_secs(self.expires_in),
+            client,
+        }
+    }
 }
 
-impl State {
-    pub fn random() -> Self {
-        let mut state = [0_u8; 128];
-        rand::thread_rng().fill(&mut state);
+#[derive(Debug, Serialize)]
+struct RefreshTokenRequest<'t> {
+    grant_type: MustBe!("refresh_token"),
+    refresh_token: &'t str,
+    /// Only sent by public clients, confidential clients authenticate instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'t str>,
+}
 
-        Self { state }
-    }
+#[derive(Debug, Deserialize)]
+struct RefreshTokenResponse {
+    access_token: String,
+    #[allow(dead_code)]
+    token_type: MustBe!("Bearer"),
+    scope: String,
+    expires_in: u64,
+    /// Spotify may rotate the refresh token, after which the old one stops working
+    refresh_token: Option<String>,
 }
 
-impl Display for State {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Base64Display::with_config(&self.state, base64::URL_SAFE).fmt(f)
+impl RefreshTokenResponse {
+    fn apply(self, tokens: &mut SpotifyTokens) {
+        tokens.access_token = self.access_token;
+        tokens.scope = self.scope;
+        tokens.expires_at = SystemTime::now() + Duration::from_secs(self.expires_in);
+
+        if let Some(refresh_token) = self.refresh_token {
+            tokens.refresh_token = refresh_token;
+        }
     }
 }
 
-impl FromStr for State {
-    type Err = base64::DecodeError;
-
-    fn from_str(str: &str) -> Result<Self, Self::Err> {
-        let mut state = [0_u8; 128];
+#[derive(Debug, Serialize)]
+struct AccessToken<'t> {
+    access_token: &'t str,
+    expires_in: u64,
+}
 
-        let len = base64::decode_config_slice(str, base64::URL_SAFE, &mut state)?;
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessTokenError {
+    error: String,
+    error_description: Option<String>,
+}
 
-        if len != state.len() {
-            return Err(base64::DecodeError::InvalidLength);
+impl Display for AccessTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error_description {
+            Some(description) => write!(f, "{} ({description})", self.error),
+            None => write!(f, "{}", self.error),
         }
-
-        Ok(Self { state })
     }
 }
 
-#[derive(Default, Clone)]
-struct OAuthStateStorage {
-    storage: Arc<Mutex<HashSet<State>>>,
+#[derive(Debug)]
+pub enum OAuthError {
+    Request {
+        provider: &'static str,
+        error: reqwest::Error,
+    },
+    Rejected {
+        provider: &'static str,
+        status: StatusCode,
+        error: Option<AccessTokenError>,
+    },
+    Decode {
+        provider: &'static str,
+        error: reqwest::Error,
+    },
+    /// Spotify failed a request made with the tokens it handed out
+    Spotify(spotify_banger_web_api::Error),
+}
+
+impl OAuthError {
+    /// Whether the provider refused the request itself, rather than failing to handle it
+    fn is_rejection(&self) -> bool {
+        match self {
+            OAuthError::Rejected { status, .. } => status.is_client_error(),
+            OAuthError::Spotify(spotify_banger_web_api::Error::Api(error)) => {
+                (400..500).contains(&error.status)
+            }
+            _ => false,
+        }
+    }
 }
 
-impl OAuthStateStorage {
-    pub fn create_state(&self) -> State {
-        let mut storage = self.storage.lock().unwrap();
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        match self {
+            OAuthError::Request { provider, error } => {
+                error!(provider, %error, "failed to reach oauth provider");
+
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("failed to reach {provider}, please try again later"),
+                )
+                    .into_response()
+            }
+            OAuthError::Rejected {
+                provider,
+                status,
+                error,
+            } if status.is_client_error() => {
+                warn!(provider, %status, ?error, "oauth provider rejected request");
 
-        // If state collides, skip it
-        let state = loop {
-            let state = State::random();
+                let error = error
+                    .map(|error| error.to_string())
+                    .unwrap_or_else(|| status.to_string());
 
-            if storage.contains(&state) {
-                warn!(%state, "state collision occurred");
+                (
+                    StatusCode::UNAUTHORIZED,
+                    format!("{provider} rejected authorization: {error}"),
+                )
+                    .into_response()
+            }
+            OAuthError::Rejected {
+                provider,
+                status,
+                error,
+            } => {
+                error!(provider, %status, ?error, "oauth provider failed");
 
-                continue;
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("{provider} encountered an error, please try again later"),
+                )
+                    .into_response()
             }
+            OAuthError::Decode { provider, error } => {
+                error!(provider, %error, "failed to decode oauth provider response");
 
-            break state;
-        };
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("{provider} returned an unexpected response"),
+                )
+                    .into_response()
+            }
+            OAuthError::Spotify(error @ spotify_banger_web_api::Error::RateLimited(_)) => {
+                warn!(%error, "rate limited by spotify");
 
-        storage.insert(state);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "spotify is busy, please try again later",
+                )
+                    .into_response()
+            }
+            OAuthError::Spotify(spotify_banger_web_api::Error::Api(error))
+                if (400..500).contains(&error.status) =>
+            {
+                warn!(%error, "spotify rejected request");
 
-        state
-    }
+                (
+                    StatusCode::UNAUTHORIZED,
+                    format!("spotify rejected authorization: {}", error.message),
+                )
+                    .into_response()
+            }
+            OAuthError::Spotify(error) => {
+                error!(%error, "spotify request failed");
 
-    pub fn validate_state(&self, state: State) -> bool {
-        self.storage.lock().unwrap().remove(&state)
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "spotify encountered an error, please try again later",
+                )
+                    .into_response()
+            }
+        }
     }
 }
 
-const SPOTIFY_AUTH_URL: &str = "https://accounts.spotify.com/authorize";
-const SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
-const SPOTIFY_SCOPE: &str = "user-read-currently-playing";
+#[derive(Debug, Serialize)]
+struct Metrics {
+    oauth_states: OAuthStateMetrics,
+    spotify: RetryMetrics,
+}
+
+async fn metrics(
+    Extension(states): Extension<OAuthStates>,
+    Extension(transport): Extension<WebApi>,
+) -> Json<Metrics> {
+    Json(Metrics {
+        oauth_states: states.metrics(),
+        spotify: transport.metrics(),
+    })
+}
+
+/// Everything the client has to know before it can get going, so that it
+/// does not have to be rebuilt for every deployment
+async fn client_config(
+    Extension(config): Extension<OAuthConfig>,
+    Extension(providers): Extension<OAuthProviders>,
+    Extension(detector): Extension<Option<Detector>>,
+) -> Json<ClientConfig> {
+    Json(ClientConfig {
+        spotify_client_id: config.spotify_client_id.to_string(),
+        providers: providers.names().into_iter().map(str::to_owned).collect(),
+        features: Features {
+            auto_bangers: detector.is_some(),
+        },
+        api_url: format!("{}api", config.origin),
+    })
+}
 
-async fn spotify(
-    Extension(state_storage): Extension<OAuthStateStorage>,
+/// Exchange a code obtained by the client through the PKCE flow, so that the
+/// session can be kept alive on the backend
+#[allow(clippy::too_many_arguments)] // axum extractors
+async fn spotify_pkce(
+    jar: CookieJar,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    Extension(reqwest): Extension<reqwest::Client>,
+    Extension(transport): Extension<WebApi>,
+    Extension(sessions): Extension<SessionStorage>,
+    Extension(storage): Extension<Storage>,
     Extension(config): Extension<OAuthConfig>,
-) -> Redirect {
-    let query = serde_urlencoded::to_string(CodeGrantRequest {
-        response_type: Default::default(),
-        client_id: &config.spotify_client_id,
-        scope: SPOTIFY_SCOPE,
-        redirect_uri: SPOTIFY_REDIRECT_URI,
-        state: state_storage.create_state(),
-        show_dialog: true,
+    Json(exchange): Json<PkceExchange>,
+) -> Response {
+    let response = request_tokens::<AccessTokenResponse>(
+        &reqwest,
+        &config,
+        TokenClient::Public,
+        &PkceTokenRequest {
+            grant_type: Default::default(),
+            code: exchange.code,
+            // The client handles the redirect itself when using PKCE
+            redirect_uri: &config.origin,
+            client_id: &config.spotify_client_id,
+            code_verifier: exchange.code_verifier,
+        },
+    )
+    .await;
+
+    let tokens = match response {
+        Ok(response) => response.into_tokens(TokenClient::Public),
+        Err(error) => return error.into_response(),
+    };
+
+    let me = match spotify::me(&transport, &config, &tokens.access_token).await {
+        Ok(me) => me,
+        Err(error) => return error.into_response(),
+    };
+
+    let user = match spotify_user(&storage, &config, &me.id, &tokens) {
+        Ok(user) => user,
+        Err(error) => return error.into_response(),
+    };
+
+    let access_token = Json(AccessToken {
+        access_token: &tokens.access_token,
+        expires_in: tokens.expires_in().as_secs(),
     })
-    .unwrap();
+    .into_response();
+
+    let user_agent = user_agent.as_ref().map(|TypedHeader(agent)| agent.as_str());
 
-    Redirect::temporary(&format!("{SPOTIFY_AUTH_URL}?{query}"))
+    match start_session(jar, &sessions, user, Some(tokens), user_agent) {
+        Ok(jar) => (jar, access_token).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Find or create the user owning the spotify account, keeping their refresh
+/// token around for when they are not
+fn spotify_user(
+    storage: &Storage,
+    config: &OAuthConfig,
+    spotify_id: &str,
+    tokens: &SpotifyTokens,
+) -> Result<UserId, StorageError> {
+    let user = storage.spotify_user(spotify_id)?;
+
+    storage.store_refresh_token(user, &tokens.persistent(&config.token_cipher, user))?;
+
+    Ok(user)
+}
+
+fn start_session(
+    jar: CookieJar,
+    sessions: &SessionStorage,
+    user: UserId,
+    tokens: Option<SpotifyTokens>,
+    user_agent: Option<&str>,
+) -> Result<CookieJar, StorageError> {
+    // Logging in again replaces whatever session this browser had
+    if let Some(session) = session_id(&jar) {
+        if let Some(session) = sessions.session(session)? {
+            sessions.end_session(session.user, session.key)?;
+        }
+    }
+
+    let session = sessions.create_session(user, tokens, user_agent)?;
+
+    Ok(jar.add(
+        Cookie::build(SESSION_COOKIE, session.to_string())
+            .path("/")
+            .http_only(true)
+            .secure(cfg!(not(debug_assertions)))
+            .same_site(SameSite::Lax)
+            .max_age(time::Duration::seconds(SESSION_TTL.as_secs() as i64))
+            .finish(),
+    ))
 }
 
-async fn spotify_redirect(
-    Query(grant): Query<CodeGrantResponse>,
+async fn spotify_token(
+    CurrentSession(session): CurrentSession,
+    jar: CookieJar,
     Extension(reqwest): Extension<reqwest::Client>,
-    Extension(state_storage): Extension<OAuthStateStorage>,
+    Extension(sessions): Extension<SessionStorage>,
+    Extension(storage): Extension<Storage>,
     Extension(config): Extension<OAuthConfig>,
 ) -> Response {
-    // TODO: html error pages
-    if !state_storage.validate_state(grant.state) {
-        return (
-            StatusCode::BAD_REQUEST,
-            "invalid state, suspected request forgery. did you navigate back to this page?",
-        )
-            .into_response();
+    let tokens = match session_tokens(&config, &sessions, &storage, &session) {
+        Ok(Some(tokens)) => tokens,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "not logged in to spotify").into_response(),
+        Err(error) => return restore_failed(jar, error),
+    };
+
+    let tokens = match fresh_tokens(&reqwest, &config, &sessions, &storage, &session, &tokens).await
+    {
+        Ok(tokens) => tokens,
+        Err(error) => return refresh_failed(jar, error),
+    };
+
+    Json(AccessToken {
+        access_token: &tokens.access_token,
+        expires_in: tokens.expires_in().as_secs(),
+    })
+    .into_response()
+}
+
+/// Lock the tokens of the session, refreshing them first if they are about to expire
+async fn fresh_tokens<'t>(
+    reqwest: &reqwest::Client,
+    config: &OAuthConfig,
+    sessions: &SessionStorage,
+    storage: &Storage,
+    session: &Session,
+    tokens: &'t SessionTokens,
+) -> Result<MutexGuard<'t, SpotifyTokens>, OAuthError> {
+    let mut tokens = tokens.lock().await;
+
+    if tokens.needs_refresh() {
+        let mut refreshed = refresh(reqwest, config, storage, session.user, &mut tokens).await;
+
+        // The listener refreshes on its own, and spotify rotates the refresh
+        // token of public clients every time, so the one in storage may be newer
+        if refreshed.as_ref().is_err_and(OAuthError::is_rejection) {
+            let stored = restore(config, storage, session.user)
+                .filter(|stored| stored.refresh_token != tokens.refresh_token);
+
+            if let Some(stored) = stored {
+                *tokens = stored;
+                refreshed = refresh(reqwest, config, storage, session.user, &mut tokens).await;
+            }
+        }
+
+        if let Err(error) = refreshed {
+            // A refresh token that was rejected once will never be accepted again
+            if error.is_rejection() {
+                if let Err(error) = sessions.end_session(session.user, session.key) {
+                    error!(%error, "failed to end session");
+                }
+            }
+
+            return Err(error);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Trade the refresh token of the user for a new access token, keeping the
+/// refresh token in storage up to date
+async fn refresh(
+    reqwest: &reqwest::Client,
+    config: &OAuthConfig,
+    storage: &Storage,
+    user: UserId,
+    tokens: &mut SpotifyTokens,
+) -> Result<(), OAuthError> {
+    let refreshed = request_tokens::<RefreshTokenResponse>(
+        reqwest,
+        config,
+        tokens.client,
+        &RefreshTokenRequest {
+            grant_type: Default::default(),
+            refresh_token: &tokens.refresh_token,
+            client_id: match tokens.client {
+                TokenClient::Confidential => None,
+                TokenClient::Public => Some(&config.spotify_client_id),
+            },
+        },
+    )
+    .await?;
+
+    refreshed.apply(tokens);
+
+    // The access token is fine either way, only the stored refresh token goes stale
+    if let Err(error) =
+        storage.store_refresh_token(user, &tokens.persistent(&config.token_cipher, user))
+    {
+        error!(%error, "failed to store refreshed token");
     }
 
-    match grant.inner {
-        CodeGrantResponseInner::Failure { error } => (
+    Ok(())
+}
+
+/// The tokens of the user from the refresh token in storage, to be refreshed
+/// before they are used
+fn restore(config: &OAuthConfig, storage: &Storage, user: UserId) -> Option<SpotifyTokens> {
+    let stored = match storage.refresh_token(user) {
+        Ok(stored) => stored?,
+        Err(error) => {
+            error!(%user, %error, "failed to look up refresh token");
+
+            return None;
+        }
+    };
+
+    match config.token_cipher.open(user, &stored.refresh_token) {
+        Ok(refresh_token) => Some(SpotifyTokens {
+            access_token: String::new(),
+            refresh_token,
+            scope: stored.scope,
+            expires_at: UNIX_EPOCH,
+            client: stored.client,
+        }),
+        Err(error) => {
+            warn!(%user, %error, "stored refresh token can not be opened");
+
+            None
+        }
+    }
+}
+
+/// Respond to a failed refresh, making the browser forget the session if it was ended
+fn refresh_failed(jar: CookieJar, error: OAuthError) -> Response {
+    if error.is_rejection() {
+        return (jar.remove(Cookie::named(SESSION_COOKIE)), error).into_response();
+    }
+
+    error.into_response()
+}
+
+#[derive(Debug)]
+enum RestoreError {
+    /// The stored refresh token was sealed with a retired key, or tampered with
+    Unsealable,
+    Storage(StorageError),
+}
+
+/// The spotify tokens of the session. Sessions that were started without
+/// them, like those logged in through github, pick up the refresh token kept
+/// in storage instead
+fn session_tokens(
+    config: &OAuthConfig,
+    sessions: &SessionStorage,
+    storage: &Storage,
+    session: &Session,
+) -> Result<Option<SessionTokens>, RestoreError> {
+    if let Some(tokens) = &session.spotify {
+        return Ok(Some(tokens.clone()));
+    }
+
+    let stored = match storage.refresh_token(session.user) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => return Ok(None),
+        Err(error) => return Err(RestoreError::Storage(error)),
+    };
+
+    let refresh_token = match config
+        .token_cipher
+        .open(session.user, &stored.refresh_token)
+    {
+        Ok(refresh_token) => refresh_token,
+        Err(error) => {
+            warn!(user = %session.user, %error, "stored refresh token can not be opened");
+
+            // Nobody will ever be able to use it, so spotify has to hand out a new one
+            if let Err(error) = sessions.end_session(session.user, session.key) {
+                error!(%error, "failed to end session");
+            }
+            if let Err(error) = storage.forget_refresh_token(session.user) {
+                error!(%error, "failed to forget refresh token");
+            }
+
+            return Err(RestoreError::Unsealable);
+        }
+    };
+
+    Ok(Some(sessions.attach_spotify(
+        session.key,
+        SpotifyTokens {
+            // Gets refreshed before it is ever used
+            access_token: String::new(),
+            refresh_token,
+            scope: stored.scope,
+            expires_at: UNIX_EPOCH,
+            client: stored.client,
+        },
+    )))
+}
+
+/// Respond to a failed restore, sending the user off to log in again if the
+/// refresh token is gone for good
+fn restore_failed(jar: CookieJar, error: RestoreError) -> Response {
+    match error {
+        RestoreError::Unsealable => (
             StatusCode::UNAUTHORIZED,
-            format!("spotify rejected authorization request: {error}"),
+            jar.remove(Cookie::named(SESSION_COOKIE)),
+            "spotify authorization could not be restored, please log in again",
         )
             .into_response(),
-        CodeGrantResponseInner::Success { code } => {
-            let auth = base64::encode(format!(
-                "{}:{}",
-                config.spotify_client_id, config.spotify_client_secret
-            ));
-
-            let response = reqwest
-                .post(SPOTIFY_TOKEN_URL)
-                .header(header::AUTHORIZATION, format!("Basic {auth}"))
-                .form(&AccessTokenRequest {
-                    code,
-                    grant_type: Default::default(),
-                    redirect_uri: SPOTIFY_REDIRECT_URI,
-                })
-                .send()
-                .await;
-
-            // TODO: store and so shizzle with response
-
-            dbg!(response
-                .unwrap()
-                .json::<AccessTokenResponse>()
-                .await
-                .unwrap());
+        RestoreError::Storage(error) => error.into_response(),
+    }
+}
+
+fn session_id(jar: &CookieJar) -> Option<SessionId> {
+    jar.get(SESSION_COOKIE)
+        .and_then(|cookie| cookie.value().parse().ok())
+}
+
+async fn request_tokens<T: DeserializeOwned>(
+    reqwest: &reqwest::Client,
+    config: &OAuthConfig,
+    client: TokenClient,
+    form: &impl Serialize,
+) -> Result<T, OAuthError> {
+    send("spotify", token_request(reqwest, config, client, form)).await
+}
+
+fn token_request(
+    reqwest: &reqwest::Client,
+    config: &OAuthConfig,
+    client: TokenClient,
+    form: &impl Serialize,
+) -> reqwest::RequestBuilder {
+    let mut request = reqwest.post(&*config.spotify_token_url).form(form);
+
+    if client == TokenClient::Confidential {
+        let auth = base64::encode(format!(
+            "{}:{}",
+            config.spotify_client_id, config.spotify_client_secret
+        ));
+
+        request = request.header(header::AUTHORIZATION, format!("Basic {auth}"));
+    }
+
+    request
+}
+
+async fn send<T: DeserializeOwned>(
+    provider: &'static str,
+    request: reqwest::RequestBuilder,
+) -> Result<T, OAuthError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|error| OAuthError::Request { provider, error })?;
+
+    receive(provider, response).await
+}
+
+async fn receive<T: DeserializeOwned>(
+    provider: &'static str,
+    response: reqwest::Response,
+) -> Result<T, OAuthError> {
+    let status = response.status();
+
+    if !status.is_success() {
+        return Err(OAuthError::Rejected {
+            provider,
+            status,
+            error: response.json().await.ok(),
+        });
+    }
 
-            todo!()
+    response
+        .json()
+        .await
+        .map_err(|error| OAuthError::Decode { provider, error })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
+
+    use axum::{
+        body::Body,
+        extract::Form,
+        http::{HeaderMap, Request},
+    };
+    use tower::ServiceExt;
+
+    use super::{oauth_state::state_cookie, spotify::SPOTIFY_SCOPE, *};
+    use crate::{
+        crypto::tests::{KEY_1, KEY_2},
+        storage::RefreshToken,
+    };
+
+    /// Spawn a stand-in for an oauth provider, returning its base url
+    pub(super) async fn serve(app: Router) -> String {
+        let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let addr = server.local_addr();
+
+        tokio::spawn(server);
+
+        format!("http://{addr}")
+    }
+
+    /// Spawn a stand-in for spotify, serving the token endpoint along with the current user
+    async fn serve_token_endpoint(app: Router) -> String {
+        serve(app.route(
+            "/v1/me",
+            get(|| async {
+                Json(serde_json::json!({
+                    "display_name": null,
+                    "external_urls": { "spotify": "https://open.spotify.com/user/spotify%20user" },
+                    "followers": { "href": null, "total": 0 },
+                    "href": "https://api.spotify.com/v1/users/spotify%20user",
+                    "id": "spotify user",
+                    "images": [],
+                    "type": "user",
+                    "uri": "spotify:user:spotify user"
+                }))
+            }),
+        ))
+        .await
+    }
+
+    /// Spawn a stand-in for the spotify token endpoint that always gives the same response
+    async fn token_endpoint(status: StatusCode, body: &'static str) -> String {
+        serve_token_endpoint(Router::new().route(
+            "/api/token",
+            post(
+                move || async move { (status, [(header::CONTENT_TYPE, "application/json")], body) },
+            ),
+        ))
+        .await
+    }
+
+    /// Spawn a stand-in for the spotify token endpoint that only accepts public clients
+    async fn public_token_endpoint() -> String {
+        serve_token_endpoint(Router::new().route(
+            "/api/token",
+            post(
+                |headers: HeaderMap, Form(form): Form<HashMap<String, String>>| async move {
+                    let public = !headers.contains_key(header::AUTHORIZATION)
+                        && form.get("client_id").map(String::as_str) == Some("id")
+                        && (form.contains_key("code_verifier")
+                            || form.contains_key("refresh_token"));
+
+                    if !public {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            Json(serde_json::json!({ "error": "invalid_client" })),
+                        );
+                    }
+
+                    (
+                        StatusCode::OK,
+                        Json(serde_json::json!({
+                            "access_token": "public access",
+                            "token_type": "Bearer",
+                            "scope": "user-read-currently-playing",
+                            "expires_in": 3600,
+                            "refresh_token": "public refresh"
+                        })),
+                    )
+                },
+            ),
+        ))
+        .await
+    }
+
+    pub(super) const ORIGIN: &str = "http://127.0.0.1:8080/";
+
+    pub(super) fn test_config(spotify_url: &str) -> OAuthConfig {
+        OAuthConfig {
+            origin: Arc::from(ORIGIN),
+            spotify_client_secret: Arc::from("secret"),
+            spotify_client_id: Arc::from("id"),
+            spotify_redirect_uri: Arc::from(format!("{ORIGIN}api/auth/spotify/redirect")),
+            token_cipher: TokenCipher::parse(KEY_1).unwrap(),
+            spotify_token_url: Arc::from(format!("{spotify_url}/api/token")),
+            spotify_api_url: Arc::from(format!("{spotify_url}/v1")),
+            github: None,
         }
     }
+
+    /// Sessions kept in a fresh database, along with the database itself
+    pub(super) fn in_memory() -> (SessionStorage, Storage) {
+        let storage = Storage::in_memory();
+
+        (SessionStorage::new(storage.clone()), storage)
+    }
+
+    pub(super) fn test_router(
+        spotify_url: String,
+        sessions: SessionStorage,
+        storage: Storage,
+    ) -> Router {
+        test_router_with(spotify_url, sessions, storage, test_health())
+    }
+
+    /// Nothing to check besides the storage, and no static files to serve
+    pub(super) fn test_health() -> Health {
+        Health {
+            static_dir: Arc::new(PathBuf::from(env!("CARGO_MANIFEST_DIR"))),
+            heartbeats: Arc::new([]),
+        }
+    }
+
+    pub(super) fn test_router_with(
+        spotify_url: String,
+        sessions: SessionStorage,
+        storage: Storage,
+        health: Health,
+    ) -> Router {
+        router(
+            test_config(&spotify_url),
+            reqwest::Client::new(),
+            WebApi::new(reqwest::Client::new(), Tokio),
+            OAuthStates::default(),
+            sessions,
+            storage,
+            None,
+            Key::generate(),
+            health,
+        )
+    }
+
+    /// Start the authorization flow, returning the state and the state cookie
+    async fn authorize(app: &Router) -> (String, String) {
+        authorize_with(app, "spotify").await
+    }
+
+    pub(super) async fn authorize_with(app: &Router, provider: &str) -> (String, String) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(format!("/auth/{provider}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let (_, query) = location.split_once('?').unwrap();
+        let state = serde_urlencoded::from_str::<HashMap<String, String>>(query)
+            .unwrap()
+            .remove("state")
+            .unwrap();
+
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let (cookie, _) = cookie.split_once(';').unwrap();
+
+        (state, cookie.to_owned())
+    }
+
+    /// Return from the authorization page with the given state and cookie
+    async fn redirect(app: &Router, state: &str, cookie: Option<&str>) -> Response {
+        redirect_with(app, "spotify", state, cookie.into_iter().collect()).await
+    }
+
+    pub(super) async fn redirect_with(
+        app: &Router,
+        provider: &str,
+        state: &str,
+        cookies: Vec<&str>,
+    ) -> Response {
+        let query = serde_urlencoded::to_string([("code", "code"), ("state", state)]).unwrap();
+
+        let mut request = Request::get(format!("/auth/{provider}/redirect?{query}"));
+
+        if !cookies.is_empty() {
+            request = request.header(header::COOKIE, cookies.join("; "));
+        }
+
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Walk through the authorization flow, returning the response to the redirect
+    async fn login(token_url: String) -> (Response, SessionStorage, Storage) {
+        let (sessions, storage) = in_memory();
+        let app = test_router(token_url, sessions.clone(), storage.clone());
+
+        let (state, cookie) = authorize(&app).await;
+        let response = redirect(&app, &state, Some(&cookie)).await;
+
+        (response, sessions, storage)
+    }
+
+    pub(super) fn session_cookie(response: &Response) -> Option<Cookie<'static>> {
+        response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|cookie| Cookie::parse(cookie.to_str().unwrap().to_owned()).unwrap())
+            .find(|cookie| cookie.name() == SESSION_COOKIE)
+    }
+
+    #[tokio::test]
+    async fn redirect_requires_state_cookie() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(String::new(), sessions, storage);
+
+        let (state, _) = authorize(&app).await;
+        let response = redirect(&app, &state, None).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn redirect_rejects_state_of_other_browser() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(String::new(), sessions, storage);
+
+        let (_, victim_cookie) = authorize(&app).await;
+        let (attacker_state, _) = authorize(&app).await;
+        let response = redirect(&app, &attacker_state, Some(&victim_cookie)).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn redirect_rejects_forged_state_cookie() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(String::new(), sessions, storage);
+
+        let state = State::random();
+        let forged = format!("{}=0.{state}", state_cookie("spotify"));
+        let response = redirect(&app, &state.to_string(), Some(&forged)).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn token_exchange_creates_session() {
+        let token_url = token_endpoint(
+            StatusCode::OK,
+            r#"{
+                "access_token": "access",
+                "token_type": "Bearer",
+                "scope": "user-read-currently-playing",
+                "expires_in": 3600,
+                "refresh_token": "refresh"
+            }"#,
+        )
+        .await;
+
+        let (response, sessions, storage) = login(token_url).await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], ORIGIN);
+
+        let cookie = session_cookie(&response).expect("session cookie should be set");
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(
+            cookie.max_age(),
+            Some(time::Duration::seconds(SESSION_TTL.as_secs() as i64))
+        );
+
+        let tokens = sessions
+            .spotify_tokens(cookie.value().parse().unwrap())
+            .expect("session should have been created");
+        let tokens = tokens.lock().await;
+
+        assert_eq!(tokens.access_token, "access");
+        assert_eq!(tokens.refresh_token, "refresh");
+        assert!(tokens.expires_at > SystemTime::now());
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        let stored = storage.refresh_token(user).unwrap().unwrap();
+        assert_eq!(
+            TokenCipher::parse(KEY_1)
+                .unwrap()
+                .open(user, &stored.refresh_token)
+                .unwrap(),
+            "refresh"
+        );
+    }
+
+    #[tokio::test]
+    async fn token_exchange_rejected() {
+        let token_url = token_endpoint(
+            StatusCode::BAD_REQUEST,
+            r#"{ "error": "invalid_grant", "error_description": "Invalid authorization code" }"#,
+        )
+        .await;
+
+        let (response, _, _) = login(token_url).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(session_cookie(&response).is_none());
+    }
+
+    #[tokio::test]
+    async fn token_exchange_malformed() {
+        let token_url = token_endpoint(StatusCode::OK, r#"{ "access_token": "#).await;
+
+        let (response, _, _) = login(token_url).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert!(session_cookie(&response).is_none());
+    }
+
+    /// Request an access token using a session whose tokens expire in `expires_in`
+    async fn access_token(
+        token_url: String,
+        expires_in: Duration,
+    ) -> (Response, SessionStorage, SessionId, Storage) {
+        access_token_for(token_url, expires_in, TokenClient::Confidential).await
+    }
+
+    async fn access_token_for(
+        token_url: String,
+        expires_in: Duration,
+        client: TokenClient,
+    ) -> (Response, SessionStorage, SessionId, Storage) {
+        let (sessions, storage) = in_memory();
+        let session = sessions
+            .create_session(
+                storage.spotify_user("spotify user").unwrap(),
+                Some(SpotifyTokens {
+                    access_token: "old access".into(),
+                    refresh_token: "old refresh".into(),
+                    scope: SPOTIFY_SCOPE.into(),
+                    expires_at: SystemTime::now() + expires_in,
+                    client,
+                }),
+                None,
+            )
+            .unwrap();
+
+        let response = test_router(token_url, sessions.clone(), storage.clone())
+            .oneshot(
+                Request::get("/auth/spotify/token")
+                    .header(header::COOKIE, format!("{SESSION_COOKIE}={session}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        (response, sessions, session, storage)
+    }
+
+    pub(super) async fn json_body(response: Response) -> serde_json::Value {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fresh_token_is_not_refreshed() {
+        let token_url = token_endpoint(StatusCode::INTERNAL_SERVER_ERROR, "{}").await;
+
+        let (response, _, _, _) = access_token(token_url, Duration::from_secs(3600)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["access_token"], "old access");
+    }
+
+    #[tokio::test]
+    async fn stale_token_is_refreshed_and_rotated() {
+        let token_url = token_endpoint(
+            StatusCode::OK,
+            r#"{
+                "access_token": "new access",
+                "token_type": "Bearer",
+                "scope": "user-read-currently-playing",
+                "expires_in": 3600,
+                "refresh_token": "new refresh"
+            }"#,
+        )
+        .await;
+
+        let (response, sessions, session, storage) = access_token(token_url, Duration::ZERO).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = json_body(response).await;
+        assert_eq!(body["access_token"], "new access");
+        assert!(body["expires_in"].as_u64().unwrap() > 3500);
+
+        let tokens = sessions.spotify_tokens(session).unwrap();
+        let tokens = tokens.lock().await;
+        assert_eq!(tokens.refresh_token, "new refresh");
+
+        let user = sessions.session(session).unwrap().unwrap().user;
+        let stored = storage.refresh_token(user).unwrap().unwrap();
+        assert_eq!(
+            TokenCipher::parse(KEY_1)
+                .unwrap()
+                .open(user, &stored.refresh_token)
+                .unwrap(),
+            "new refresh"
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_without_rotation_keeps_refresh_token() {
+        let token_url = token_endpoint(
+            StatusCode::OK,
+            r#"{
+                "access_token": "new access",
+                "token_type": "Bearer",
+                "scope": "user-read-currently-playing",
+                "expires_in": 3600
+            }"#,
+        )
+        .await;
+
+        let (response, sessions, session, _) = access_token(token_url, Duration::ZERO).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let tokens = sessions.spotify_tokens(session).unwrap();
+        let tokens = tokens.lock().await;
+        assert_eq!(tokens.access_token, "new access");
+        assert_eq!(tokens.refresh_token, "old refresh");
+    }
+
+    #[tokio::test]
+    async fn revoked_refresh_token_ends_session() {
+        let token_url = token_endpoint(
+            StatusCode::BAD_REQUEST,
+            r#"{ "error": "invalid_grant", "error_description": "Refresh token revoked" }"#,
+        )
+        .await;
+
+        let (response, sessions, session, _) = access_token(token_url, Duration::ZERO).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(sessions.spotify_tokens(session).is_none());
+    }
+
+    /// Request an access token using a session without spotify tokens, for a
+    /// user whose refresh token was stored sealed with `key`
+    async fn restored_access_token(
+        token_url: String,
+        key: &str,
+    ) -> (Response, SessionStorage, SessionId, Storage) {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+
+        storage
+            .store_refresh_token(
+                user,
+                &RefreshToken {
+                    refresh_token: TokenCipher::parse(key)
+                        .unwrap()
+                        .seal(user, "stored refresh"),
+                    scope: SPOTIFY_SCOPE.into(),
+                    client: TokenClient::Confidential,
+                },
+            )
+            .unwrap();
+
+        let session = sessions.create_session(user, None, None).unwrap();
+
+        let response = test_router(token_url, sessions.clone(), storage.clone())
+            .oneshot(
+                Request::get("/auth/spotify/token")
+                    .header(header::COOKIE, format!("{SESSION_COOKIE}={session}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        (response, sessions, session, storage)
+    }
+
+    #[tokio::test]
+    async fn stored_refresh_token_is_restored() {
+        let token_url = token_endpoint(
+            StatusCode::OK,
+            r#"{
+                "access_token": "restored access",
+                "token_type": "Bearer",
+                "scope": "user-read-currently-playing",
+                "expires_in": 3600
+            }"#,
+        )
+        .await;
+
+        let (response, _, _, _) = restored_access_token(token_url, KEY_1).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["access_token"], "restored access");
+    }
+
+    #[tokio::test]
+    async fn unsealable_refresh_token_forces_login() {
+        let token_url = token_endpoint(StatusCode::INTERNAL_SERVER_ERROR, "{}").await;
+
+        let (response, sessions, session, storage) = restored_access_token(token_url, KEY_2).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let cookie = session_cookie(&response).expect("session cookie should be removed");
+        assert_eq!(cookie.value(), "");
+        assert!(sessions.session(session).unwrap().is_none());
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        assert_eq!(storage.refresh_token(user).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn token_requires_session() {
+        let (sessions, storage) = in_memory();
+        let response = test_router(String::new(), sessions, storage)
+            .oneshot(
+                Request::get("/auth/spotify/token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn pkce_exchange_creates_public_session() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(
+            public_token_endpoint().await,
+            sessions.clone(),
+            storage.clone(),
+        );
+
+        let response = app
+            .oneshot(
+                Request::post("/auth/spotify/pkce")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::USER_AGENT, "Firefox")
+                    .body(Body::from(
+                        r#"{ "code": "code", "code_verifier": "verifier" }"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let cookie = session_cookie(&response).expect("session cookie should be set");
+        assert_eq!(json_body(response).await["access_token"], "public access");
+
+        let tokens = sessions
+            .spotify_tokens(cookie.value().parse().unwrap())
+            .expect("session should have been created");
+        let tokens = tokens.lock().await;
+
+        assert_eq!(tokens.refresh_token, "public refresh");
+        assert_eq!(tokens.client, TokenClient::Public);
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        assert_eq!(
+            storage.sessions(user).unwrap()[0].user_agent.as_deref(),
+            Some("Firefox")
+        );
+    }
+
+    #[tokio::test]
+    async fn public_session_refreshes_without_secret() {
+        let (response, _, _, _) = access_token_for(
+            public_token_endpoint().await,
+            Duration::ZERO,
+            TokenClient::Public,
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["access_token"], "public access");
+    }
+
+    #[tokio::test]
+    async fn confidential_session_refreshes_with_secret() {
+        let (response, _, _, _) = access_token_for(
+            public_token_endpoint().await,
+            Duration::ZERO,
+            TokenClient::Confidential,
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn client_config_describes_deployment() {
+        let (sessions, storage) = in_memory();
+
+        let response = test_router(String::new(), sessions, storage)
+            .oneshot(Request::get("/config").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await,
+            serde_json::json!({
+                "spotify_client_id": "id",
+                "providers": ["spotify"],
+                "features": { "auto_bangers": false },
+                "api_url": format!("{ORIGIN}api"),
+            })
+        );
+    }
 }
@@ -0,0 +1,654 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::CookieJar;
+use spotify_banger_model::{
+    BangerEdit, BangerId, BangerPage, BangerQuery, BangerSource, NewBanger, Playback, PlayingItem,
+};
+
+use super::{
+    fresh_tokens, refresh_failed, restore_failed, session_tokens,
+    sessions::CurrentSession,
+    spotify::{self, WebApi},
+    OAuthConfig,
+};
+use crate::{
+    bangers::{Cursor, Listing, Mark},
+    detect::{Detector, Observation},
+    session::SessionStorage,
+    storage::Storage,
+};
+
+/// Mark whatever the user is listening to right now as a banger
+#[allow(clippy::too_many_arguments)] // axum extractors
+pub async fn mark(
+    CurrentSession(session): CurrentSession,
+    jar: CookieJar,
+    Extension(reqwest): Extension<reqwest::Client>,
+    Extension(transport): Extension<WebApi>,
+    Extension(sessions): Extension<SessionStorage>,
+    Extension(storage): Extension<Storage>,
+    Extension(config): Extension<OAuthConfig>,
+    Json(new): Json<NewBanger>,
+) -> Response {
+    let tokens = match session_tokens(&config, &sessions, &storage, &session) {
+        Ok(Some(tokens)) => tokens,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "not logged in to spotify").into_response(),
+        Err(error) => return restore_failed(jar, error),
+    };
+
+    let access_token =
+        match fresh_tokens(&reqwest, &config, &sessions, &storage, &session, &tokens).await {
+            Ok(tokens) => tokens.access_token.clone(),
+            Err(error) => return refresh_failed(jar, error),
+        };
+
+    let playback = match spotify::playback(&transport, &config, &access_token).await {
+        Ok(playback) => playback,
+        Err(error) => return error.into_response(),
+    };
+
+    let playback = match playback {
+        Some(playback) => playback,
+        None => return (StatusCode::CONFLICT, "nothing is playing").into_response(),
+    };
+
+    let track = match playback.playing.item {
+        Some(PlayingItem::Track(track)) => track,
+        Some(PlayingItem::Episode(_)) => {
+            return (StatusCode::CONFLICT, "podcasts can not be bangers").into_response()
+        }
+        None => return (StatusCode::CONFLICT, "nothing is playing").into_response(),
+    };
+
+    let track_id = match track.id {
+        Some(track_id) => track_id,
+        None => return (StatusCode::CONFLICT, "local files can not be bangers").into_response(),
+    };
+
+    let banger = storage.record_banger(
+        session.user,
+        Mark {
+            track_id,
+            album_id: track.album.id,
+            artist_ids: track
+                .artists
+                .into_iter()
+                .filter_map(|artist| artist.id)
+                .collect(),
+            marked_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            progress_ms: playback.playing.progress_ms.unwrap_or_default(),
+            device: Some(playback.device.name),
+            context: playback.playing.context.map(|context| context.uri),
+            note: note(new.note),
+            source: BangerSource::Manual,
+            confidence: None,
+        },
+    );
+
+    match banger {
+        Ok(banger) => (StatusCode::CREATED, Json(banger)).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Blank notes are no notes at all
+fn note(note: Option<String>) -> Option<String> {
+    note.filter(|note| !note.trim().is_empty())
+}
+
+/// A page of the bangers of the logged in user
+pub async fn list(
+    CurrentSession(session): CurrentSession,
+    Query(query): Query<BangerQuery>,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    let listing = match Listing::new(query) {
+        Ok(listing) => listing,
+        Err(error) => return (StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    // One more than asked for tells whether there is a next page
+    let mut bangers = match storage.bangers(
+        session.user,
+        &Listing {
+            limit: listing.limit + 1,
+            ..listing.clone()
+        },
+    ) {
+        Ok(bangers) => bangers,
+        Err(error) => return error.into_response(),
+    };
+
+    let next_cursor = if bangers.len() > listing.limit as usize {
+        bangers.truncate(listing.limit as usize);
+
+        bangers
+            .last()
+            .map(|last| Cursor::after(listing.sort, last).to_string())
+    } else {
+        None
+    };
+
+    Json(BangerPage {
+        bangers: bangers.into_iter().map(|listed| listed.banger).collect(),
+        next_cursor,
+    })
+    .into_response()
+}
+
+/// Change the note on one of the bangers of the logged in user
+pub async fn edit(
+    CurrentSession(session): CurrentSession,
+    Path(id): Path<BangerId>,
+    Extension(storage): Extension<Storage>,
+    Json(edit): Json<BangerEdit>,
+) -> Response {
+    match storage.edit_banger(session.user, id, note(edit.note).as_deref()) {
+        Ok(Some(banger)) => Json(banger).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "unknown banger").into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Forget one of the bangers of the logged in user
+pub async fn forget(
+    CurrentSession(session): CurrentSession,
+    Path(id): Path<BangerId>,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    match storage.forget_banger(session.user, id) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "unknown banger").into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Let the detector know what the logged in user is listening to, recording
+/// a banger if it recognises one
+pub async fn listen(
+    CurrentSession(session): CurrentSession,
+    Extension(detector): Extension<Option<Detector>>,
+    Extension(storage): Extension<Storage>,
+    Json(playback): Json<Playback>,
+) -> Response {
+    let detector = match detector {
+        Some(detector) => detector,
+        None => {
+            return (StatusCode::NOT_FOUND, "automatic banger detection is off").into_response()
+        }
+    };
+
+    // The listener already tells the detector what they listen to, which a
+    // second feed would only muddle
+    match storage.is_listening(session.user) {
+        Ok(true) => return StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => {}
+        Err(error) => return error.into_response(),
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let mark = match Observation::new(playback, now) {
+        Some(observation) => detector.observe(session.user, observation),
+        None => None,
+    };
+
+    match mark.map(|mark| storage.record_banger(session.user, mark)) {
+        Some(Ok(banger)) => (StatusCode::CREATED, Json(banger)).into_response(),
+        Some(Err(error)) => error.into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing, Router,
+    };
+    use axum_extra::extract::cookie::Key;
+    use tower::ServiceExt;
+
+    use spotify_banger_web_api::Tokio;
+
+    use super::*;
+    use crate::{
+        api::{
+            oauth_state::OAuthStates,
+            router,
+            tests::{in_memory, json_body, serve, test_config, test_health},
+        },
+        detect::DetectorConfig,
+        session::{SpotifyTokens, TokenClient, SESSION_COOKIE},
+        users::UserId,
+    };
+
+    const PLAYBACK: &str = r#"{
+        "device": {
+            "id": "device",
+            "is_active": true,
+            "name": "Web Player (Firefox)",
+            "type": "Computer",
+            "volume_percent": 100
+        },
+        "timestamp": 1657843200000,
+        "context": {
+            "type": "playlist",
+            "href": "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M",
+            "external_urls": { "spotify": "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M" },
+            "uri": "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"
+        },
+        "progress_ms": 96000,
+        "is_playing": true,
+        "item": {
+            "type": "track",
+            "album": {
+                "album_type": "album",
+                "artists": [],
+                "external_urls": { "spotify": "https://open.spotify.com/album/2noRn2Aes5aoNVsU6iWThc" },
+                "href": "https://api.spotify.com/v1/albums/2noRn2Aes5aoNVsU6iWThc",
+                "id": "2noRn2Aes5aoNVsU6iWThc",
+                "images": [],
+                "name": "Discovery",
+                "release_date": "2001-03-12",
+                "type": "album",
+                "uri": "spotify:album:2noRn2Aes5aoNVsU6iWThc"
+            },
+            "artists": [
+                {
+                    "external_urls": { "spotify": "https://open.spotify.com/artist/4tZwfgrHOc3mvqYlEYSvVi" },
+                    "href": "https://api.spotify.com/v1/artists/4tZwfgrHOc3mvqYlEYSvVi",
+                    "id": "4tZwfgrHOc3mvqYlEYSvVi",
+                    "name": "Daft Punk",
+                    "type": "artist",
+                    "uri": "spotify:artist:4tZwfgrHOc3mvqYlEYSvVi"
+                }
+            ],
+            "duration_ms": 320357,
+            "explicit": false,
+            "external_urls": { "spotify": "https://open.spotify.com/track/0DiWol3AO6WpXZgp0goxAV" },
+            "href": "https://api.spotify.com/v1/tracks/0DiWol3AO6WpXZgp0goxAV",
+            "id": "0DiWol3AO6WpXZgp0goxAV",
+            "is_local": false,
+            "name": "One More Time",
+            "preview_url": null,
+            "uri": "spotify:track:0DiWol3AO6WpXZgp0goxAV"
+        },
+        "currently_playing_type": "track"
+    }"#;
+
+    /// Spawn a stand-in for the spotify player endpoint that always gives the same response
+    async fn player_endpoint(status: StatusCode, body: &'static str) -> String {
+        serve(Router::new().route(
+            "/v1/me/player",
+            routing::get(move || async move {
+                (status, [(header::CONTENT_TYPE, "application/json")], body)
+            }),
+        ))
+        .await
+    }
+
+    /// An app with a user who is logged in to spotify, along with their session cookie
+    fn logged_in(spotify_url: &str) -> (Router, String, Storage, UserId) {
+        logged_in_with(spotify_url, None)
+    }
+
+    /// The same, with the detector watching what they listen to
+    fn logged_in_with(
+        spotify_url: &str,
+        detector: Option<Detector>,
+    ) -> (Router, String, Storage, UserId) {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+        let session = sessions
+            .create_session(
+                user,
+                Some(SpotifyTokens {
+                    access_token: "access".into(),
+                    refresh_token: "refresh".into(),
+                    scope: spotify::SPOTIFY_SCOPE.into(),
+                    expires_at: SystemTime::now() + Duration::from_secs(3600),
+                    client: TokenClient::Confidential,
+                }),
+                None,
+            )
+            .unwrap();
+
+        let app = router(
+            test_config(spotify_url),
+            reqwest::Client::new(),
+            WebApi::new(reqwest::Client::new(), Tokio),
+            OAuthStates::default(),
+            sessions,
+            storage.clone(),
+            detector,
+            Key::generate(),
+            test_health(),
+        );
+
+        (app, format!("{SESSION_COOKIE}={session}"), storage, user)
+    }
+
+    async fn send(app: &Router, cookie: &str, request: Request<serde_json::Value>) -> Response {
+        let (mut parts, body) = request.into_parts();
+        parts
+            .headers
+            .insert(header::COOKIE, cookie.parse().unwrap());
+        parts
+            .headers
+            .insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+
+        app.clone()
+            .oneshot(Request::from_parts(parts, Body::from(body.to_string())))
+            .await
+            .unwrap()
+    }
+
+    fn get(uri: &str) -> Request<serde_json::Value> {
+        Request::get(uri).body(serde_json::Value::Null).unwrap()
+    }
+
+    /// Mark a banger as a user who is logged in to spotify, then list their bangers
+    async fn mark(spotify_url: String, note: &str) -> (Response, Response) {
+        let (app, cookie, _, _) = logged_in(&spotify_url);
+
+        let response = send(
+            &app,
+            &cookie,
+            Request::post("/bangers")
+                .body(serde_json::json!({ "note": note }))
+                .unwrap(),
+        )
+        .await;
+
+        let list = send(&app, &cookie, get("/bangers")).await;
+
+        (response, list)
+    }
+
+    #[tokio::test]
+    async fn playing_track_is_marked() {
+        let (response, list) = mark(player_endpoint(StatusCode::OK, PLAYBACK).await, "drop").await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let banger = json_body(response).await;
+        assert_eq!(banger["track_id"], "0DiWol3AO6WpXZgp0goxAV");
+        assert_eq!(banger["progress_ms"], 96000);
+        assert_eq!(banger["device"], "Web Player (Firefox)");
+        assert_eq!(banger["context"], "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M");
+        assert_eq!(banger["note"], "drop");
+        assert_eq!(banger["album_id"], "2noRn2Aes5aoNVsU6iWThc");
+        assert_eq!(
+            banger["artist_ids"],
+            serde_json::json!(["4tZwfgrHOc3mvqYlEYSvVi"])
+        );
+
+        assert_eq!(
+            json_body(list).await,
+            serde_json::json!({ "bangers": [banger], "next_cursor": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn blank_note_is_dropped() {
+        let (response, _) = mark(player_endpoint(StatusCode::OK, PLAYBACK).await, "  ").await;
+
+        assert_eq!(json_body(response).await["note"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn nothing_playing_is_not_marked() {
+        let (response, list) = mark(player_endpoint(StatusCode::NO_CONTENT, "").await, "").await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(json_body(list).await["bangers"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn bangers_are_listed_in_pages() {
+        let (app, cookie, storage, user) = logged_in("");
+
+        for marked_at in 0..5 {
+            storage
+                .record_banger(
+                    user,
+                    Mark::manual(&marked_at.to_string(), "artist", marked_at),
+                )
+                .unwrap();
+        }
+
+        let mut listed = Vec::new();
+        let mut uri = "/bangers?limit=2&from=1".to_owned();
+
+        loop {
+            let page = json_body(send(&app, &cookie, get(&uri)).await).await;
+
+            for banger in page["bangers"].as_array().unwrap() {
+                listed.push(banger["track_id"].as_str().unwrap().to_owned());
+            }
+
+            match page["next_cursor"].as_str() {
+                Some(cursor) => uri = format!("/bangers?limit=2&from=1&cursor={cursor}"),
+                None => break,
+            }
+        }
+
+        assert_eq!(listed, ["4", "3", "2", "1"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_cursor_is_refused() {
+        let (app, cookie, _, _) = logged_in("");
+
+        let response = send(&app, &cookie, get("/bangers?cursor=nonsense")).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn note_is_edited() {
+        let (app, cookie, storage, user) = logged_in("");
+        let banger = storage
+            .record_banger(user, Mark::manual("track", "artist", 0))
+            .unwrap();
+
+        let edit = |note: &str| {
+            Request::patch(format!("/bangers/{}", banger.id.0))
+                .body(serde_json::json!({ "note": note }))
+                .unwrap()
+        };
+
+        let response = send(&app, &cookie, edit("the drop")).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["note"], "the drop");
+
+        let response = send(&app, &cookie, edit(" ")).await;
+
+        assert_eq!(json_body(response).await["note"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn banger_is_forgotten() {
+        let (app, cookie, storage, user) = logged_in("");
+        let banger = storage
+            .record_banger(user, Mark::manual("track", "artist", 0))
+            .unwrap();
+        let forget = || {
+            Request::delete(format!("/bangers/{}", banger.id.0))
+                .body(serde_json::Value::Null)
+                .unwrap()
+        };
+
+        let response = send(&app, &cookie, forget()).await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            json_body(send(&app, &cookie, get("/bangers")).await).await["bangers"],
+            serde_json::json!([])
+        );
+
+        let response = send(&app, &cookie, forget()).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn bangers_of_others_are_off_limits() {
+        let (app, cookie, storage, _) = logged_in("");
+        let other = storage.spotify_user("other spotify user").unwrap();
+        let banger = storage
+            .record_banger(other, Mark::manual("track", "artist", 0))
+            .unwrap();
+
+        let response = send(
+            &app,
+            &cookie,
+            Request::patch(format!("/bangers/{}", banger.id.0))
+                .body(serde_json::json!({ "note": "mine now" }))
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = send(
+            &app,
+            &cookie,
+            Request::delete(format!("/bangers/{}", banger.id.0))
+                .body(serde_json::Value::Null)
+                .unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            storage
+                .edit_banger(other, banger.id, None)
+                .unwrap()
+                .map(|banger| banger.track_id),
+            Some("track".into())
+        );
+    }
+
+    fn playback(progress_ms: u64) -> serde_json::Value {
+        let mut playback = serde_json::from_str::<serde_json::Value>(PLAYBACK).unwrap();
+        playback["progress_ms"] = progress_ms.into();
+
+        playback
+    }
+
+    #[tokio::test]
+    async fn detection_is_optional() {
+        let (app, cookie, _, _) = logged_in("");
+
+        let response = send(
+            &app,
+            &cookie,
+            Request::post("/listening").body(playback(96000)).unwrap(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn detected_bangers_are_kept_apart() {
+        let detector = Detector::with_default_rules(DetectorConfig::default());
+        let (app, cookie, _, _) = logged_in_with("", Some(detector));
+
+        let listen = |progress_ms| {
+            send(
+                &app,
+                &cookie,
+                Request::post("/listening")
+                    .body(playback(progress_ms))
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(listen(96000).await.status(), StatusCode::NO_CONTENT);
+
+        // Sought back to hear it again
+        let response = listen(80000).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let banger = json_body(response).await;
+        assert_eq!(banger["track_id"], "0DiWol3AO6WpXZgp0goxAV");
+        assert_eq!(banger["progress_ms"], 80000);
+        assert_eq!(banger["source"], "auto");
+        assert_eq!(banger["confidence"], 60);
+
+        let list = |source| send(&app, &cookie, get(&format!("/bangers?source={source}")));
+
+        let manual = json_body(list("manual").await).await;
+        let auto = json_body(list("auto").await).await;
+
+        assert_eq!(manual["bangers"].as_array().unwrap().len(), 0);
+        assert_eq!(auto["bangers"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn listened_along_users_are_left_to_the_listener() {
+        let detector = Detector::with_default_rules(DetectorConfig::default());
+        let (app, cookie, storage, user) = logged_in_with("", Some(detector));
+        storage.set_listening(user, true).unwrap();
+
+        for progress_ms in [96000, 80000] {
+            let response = send(
+                &app,
+                &cookie,
+                Request::post("/listening")
+                    .body(playback(progress_ms))
+                    .unwrap(),
+            )
+            .await;
+
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        }
+    }
+
+    #[tokio::test]
+    async fn marking_requires_session() {
+        let (sessions, storage) = in_memory();
+        let app = router(
+            test_config(""),
+            reqwest::Client::new(),
+            WebApi::new(reqwest::Client::new(), Tokio),
+            OAuthStates::default(),
+            sessions,
+            storage,
+            None,
+            Key::generate(),
+            test_health(),
+        );
+
+        let response = app
+            .oneshot(
+                Request::post("/bangers")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,417 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use reqwest::header;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::{
+    provider::{Login, OAuthProvider},
+    send, session_id, start_session, AccessTokenError, OAuthError,
+};
+use crate::{
+    config::OAuthClient,
+    users::{GithubIdentity, LinkError, User},
+};
+
+const GITHUB_AUTH_URL: &str = "https://github.com/login/oauth/authorize";
+const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GITHUB_API_URL: &str = "https://api.github.com";
+const GITHUB_SCOPE: &str = "read:user";
+
+#[derive(Debug, Clone)]
+pub struct GithubConfig {
+    pub client_id: Arc<str>,
+    pub client_secret: Arc<str>,
+    pub redirect_uri: Arc<str>,
+    pub token_url: Arc<str>,
+    pub api_url: Arc<str>,
+}
+
+impl GithubConfig {
+    pub fn new(client: &OAuthClient, origin: &str) -> Self {
+        Self {
+            client_id: Arc::from(&*client.client_id),
+            client_secret: Arc::from(&*client.client_secret.0),
+            redirect_uri: Arc::from(format!("{origin}api/auth/github/redirect")),
+            token_url: Arc::from(GITHUB_TOKEN_URL),
+            api_url: Arc::from(GITHUB_API_URL),
+        }
+    }
+}
+
+/// GitHub reports failed exchanges with a successful status code
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AccessTokenResponse {
+    Success { access_token: String },
+    Failure(AccessTokenError),
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubUser {
+    id: u64,
+    login: String,
+}
+
+/// Links github accounts to users, so that they can log in with github once
+/// they lose access to their spotify account
+pub struct GithubProvider {
+    pub config: GithubConfig,
+    /// Where users are sent back to once they are done
+    pub origin: Arc<str>,
+}
+
+#[async_trait]
+impl OAuthProvider for GithubProvider {
+    const NAME: &'static str = "github";
+
+    type TokenResponse = AccessTokenResponse;
+    type Identity = GithubIdentity;
+
+    fn authorize_url(&self) -> &str {
+        GITHUB_AUTH_URL
+    }
+
+    fn token_url(&self) -> &str {
+        &self.config.token_url
+    }
+
+    fn scope(&self) -> &str {
+        GITHUB_SCOPE
+    }
+
+    fn client_id(&self) -> &str {
+        &self.config.client_id
+    }
+
+    fn client_secret(&self) -> &str {
+        &self.config.client_secret
+    }
+
+    fn redirect_uri(&self) -> &str {
+        &self.config.redirect_uri
+    }
+
+    async fn identity(
+        &self,
+        reqwest: &reqwest::Client,
+        tokens: &AccessTokenResponse,
+    ) -> Result<GithubIdentity, OAuthError> {
+        let access_token = match tokens {
+            AccessTokenResponse::Success { access_token } => access_token,
+            AccessTokenResponse::Failure(error) => {
+                return Err(OAuthError::Rejected {
+                    provider: Self::NAME,
+                    status: StatusCode::BAD_REQUEST,
+                    error: Some(error.clone()),
+                })
+            }
+        };
+
+        let user = send::<GithubUser>(
+            Self::NAME,
+            reqwest
+                .get(format!("{}/user", self.config.api_url))
+                .header(header::ACCEPT, "application/vnd.github+json")
+                .bearer_auth(access_token),
+        )
+        .await?;
+
+        Ok(GithubIdentity {
+            id: user.id,
+            login: user.login,
+        })
+    }
+
+    /// Link the github account to the logged in user, or log in as the user
+    /// that the github account is linked to
+    async fn login(
+        &self,
+        Login {
+            jar,
+            sessions,
+            storage,
+            user_agent,
+        }: Login,
+        _: AccessTokenResponse,
+        identity: GithubIdentity,
+    ) -> Response {
+        let session = match session_id(&jar).map(|session| sessions.session(session)) {
+            Some(Ok(session)) => session,
+            Some(Err(error)) => return error.into_response(),
+            None => None,
+        };
+
+        if let Some(session) = session {
+            return match storage.link_github(session.user, &identity) {
+                Ok(()) => {
+                    if let Ok(Some(User {
+                        spotify_id,
+                        github: Some(github),
+                        ..
+                    })) = storage.user(session.user)
+                    {
+                        info!(%spotify_id, github = %github.login, "linked github account");
+                    }
+
+                    Redirect::to(&self.origin).into_response()
+                }
+                Err(LinkError::AlreadyLinked) => (
+                    StatusCode::CONFLICT,
+                    "this github account is already linked to another spotify account",
+                )
+                    .into_response(),
+                Err(LinkError::UnknownUser) => {
+                    (StatusCode::UNAUTHORIZED, "not logged in").into_response()
+                }
+                Err(LinkError::Storage(error)) => error.into_response(),
+            };
+        }
+
+        match storage.github_user(identity.id) {
+            Ok(Some(user)) => {
+                match start_session(jar, &sessions, user, None, user_agent.as_deref()) {
+                    Ok(jar) => (jar, Redirect::to(&self.origin)).into_response(),
+                    Err(error) => error.into_response(),
+                }
+            }
+            Ok(None) => {
+                warn!(login = %identity.login, "login with unlinked github account");
+
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "this github account is not linked, log in with spotify to link it",
+                )
+                    .into_response()
+            }
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::Request,
+        routing::{get, post},
+        Json, Router,
+    };
+    use axum_extra::extract::cookie::Key;
+    use tower::ServiceExt;
+
+    use spotify_banger_web_api::Tokio;
+
+    use super::*;
+    use crate::{
+        api::{
+            oauth_state::OAuthStates,
+            router,
+            spotify::WebApi,
+            tests::{
+                authorize_with, in_memory, redirect_with, serve, session_cookie, test_config,
+                test_health,
+            },
+        },
+        session::{SessionStorage, SESSION_COOKIE},
+        storage::Storage,
+        users::UserId,
+    };
+
+    /// Spawn a stand-in for github whose token endpoint always gives the same response
+    async fn github_endpoint(token: serde_json::Value) -> String {
+        serve(
+            Router::new()
+                .route(
+                    "/login/oauth/access_token",
+                    post(move || async move { Json(token) }),
+                )
+                .route(
+                    "/user",
+                    get(|| async { Json(serde_json::json!({ "id": 42, "login": "octocat" })) }),
+                ),
+        )
+        .await
+    }
+
+    async fn working_github_endpoint() -> String {
+        github_endpoint(serde_json::json!({
+            "access_token": "github access",
+            "token_type": "bearer",
+            "scope": "read:user"
+        }))
+        .await
+    }
+
+    fn test_router(
+        github_url: Option<String>,
+        sessions: SessionStorage,
+        storage: Storage,
+    ) -> Router {
+        let mut config = test_config("");
+        config.github = github_url.map(|github_url| GithubConfig {
+            client_id: Arc::from("github id"),
+            client_secret: Arc::from("github secret"),
+            redirect_uri: Arc::from("http://127.0.0.1:8080/api/auth/github/redirect"),
+            token_url: Arc::from(format!("{github_url}/login/oauth/access_token")),
+            api_url: Arc::from(github_url),
+        });
+
+        router(
+            config,
+            reqwest::Client::new(),
+            WebApi::new(reqwest::Client::new(), Tokio),
+            OAuthStates::default(),
+            sessions,
+            storage,
+            None,
+            Key::generate(),
+            test_health(),
+        )
+    }
+
+    /// Walk through the github authorization flow, optionally with a session cookie
+    async fn login(app: &Router, session: Option<&str>) -> Response {
+        let (state, cookie) = authorize_with(app, "github").await;
+
+        let mut cookies = vec![cookie.as_str()];
+        cookies.extend(session);
+
+        redirect_with(app, "github", &state, cookies).await
+    }
+
+    fn session(sessions: &SessionStorage, user: UserId) -> String {
+        let session = sessions.create_session(user, None, None).unwrap();
+
+        format!("{SESSION_COOKIE}={session}")
+    }
+
+    #[tokio::test]
+    async fn unconfigured_github_is_not_found() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(None, sessions, storage);
+
+        let response = app
+            .oneshot(Request::get("/auth/github").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn logged_in_user_links_github() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(
+            Some(working_github_endpoint().await),
+            sessions.clone(),
+            storage.clone(),
+        );
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        let response = login(&app, Some(&session(&sessions, user))).await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(storage.github_user(42).unwrap(), Some(user));
+        assert_eq!(
+            storage.user(user).unwrap().unwrap().github,
+            Some(GithubIdentity {
+                id: 42,
+                login: "octocat".into()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn linked_github_recovers_user() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(
+            Some(working_github_endpoint().await),
+            sessions.clone(),
+            storage.clone(),
+        );
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        login(&app, Some(&session(&sessions, user))).await;
+
+        let response = login(&app, None).await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let cookie = session_cookie(&response).expect("session cookie should be set");
+        let session = sessions
+            .session(cookie.value().parse().unwrap())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session.user, user);
+        assert!(session.spotify.is_none());
+    }
+
+    #[tokio::test]
+    async fn unlinked_github_cannot_log_in() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(Some(working_github_endpoint().await), sessions, storage);
+
+        let response = login(&app, None).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(session_cookie(&response).is_none());
+    }
+
+    #[tokio::test]
+    async fn github_cannot_be_linked_twice() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(
+            Some(working_github_endpoint().await),
+            sessions.clone(),
+            storage.clone(),
+        );
+
+        let owner = storage.spotify_user("spotify user").unwrap();
+        let other = storage.spotify_user("other spotify user").unwrap();
+
+        login(&app, Some(&session(&sessions, owner))).await;
+        let response = login(&app, Some(&session(&sessions, other))).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(storage.github_user(42).unwrap(), Some(owner));
+    }
+
+    #[tokio::test]
+    async fn rejected_github_code() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(
+            Some(
+                github_endpoint(serde_json::json!({
+                    "error": "bad_verification_code",
+                    "error_description": "The code passed is incorrect or expired."
+                }))
+                .await,
+            ),
+            sessions,
+            storage.clone(),
+        );
+
+        let response = login(&app, None).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(storage.github_user(42).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn github_state_is_not_spotify_state() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(Some(working_github_endpoint().await), sessions, storage);
+
+        let (state, cookie) = authorize_with(&app, "spotify").await;
+        let response = redirect_with(&app, "github", &state, vec![&cookie]).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
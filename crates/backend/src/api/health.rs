@@ -0,0 +1,284 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use super::OAuthConfig;
+use crate::{shutdown::Heartbeat, storage::Storage};
+
+/// How long spotify gets to answer before it counts as unreachable
+const SPOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the readiness checks look at, besides the storage and spotify
+#[derive(Debug, Clone)]
+pub struct Health {
+    pub static_dir: Arc<PathBuf>,
+    /// The heartbeats of the background workers, by name
+    pub heartbeats: Arc<[(&'static str, Heartbeat)]>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadyQuery {
+    /// Also make sure that spotify can be reached, which is left out by
+    /// default so that spotify having a bad day does not take the backend
+    /// out of rotation along with it
+    #[serde(default)]
+    spotify: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    status: Status,
+    latency_ms: f64,
+    /// What went wrong, if anything did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn new(started: Instant, result: Result<(), String>) -> Self {
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        match result {
+            Ok(()) => Self {
+                status: Status::Ok,
+                latency_ms,
+                error: None,
+            },
+            Err(error) => Self {
+                status: Status::Failed,
+                latency_ms,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Live {
+    status: Status,
+}
+
+#[derive(Debug, Serialize)]
+struct Ready {
+    status: Status,
+    checks: BTreeMap<String, Check>,
+}
+
+/// Whether the process is up at all, which is all it takes to not be restarted
+pub async fn live() -> Response {
+    Json(Live { status: Status::Ok }).into_response()
+}
+
+/// Whether the backend is in a state to serve users, going through everything
+/// it depends on
+pub async fn ready(
+    Query(query): Query<ReadyQuery>,
+    Extension(health): Extension<Health>,
+    Extension(storage): Extension<Storage>,
+    Extension(config): Extension<OAuthConfig>,
+    Extension(reqwest): Extension<reqwest::Client>,
+) -> Response {
+    let mut checks = BTreeMap::new();
+
+    let started = Instant::now();
+    let result = static_assets(&health.static_dir);
+    checks.insert("static_assets".to_owned(), Check::new(started, result));
+
+    let started = Instant::now();
+    let result = storage.check().map_err(|error| error.to_string());
+    checks.insert("storage".to_owned(), Check::new(started, result));
+
+    for (name, heartbeat) in health.heartbeats.iter() {
+        let started = Instant::now();
+        let result = match heartbeat.overdue() {
+            Some(overdue) => Err(format!("heartbeat is overdue by {overdue:?}")),
+            None => Ok(()),
+        };
+        checks.insert(format!("worker.{name}"), Check::new(started, result));
+    }
+
+    if query.spotify {
+        let started = Instant::now();
+        let result = reqwest
+            .get(&*config.spotify_token_url)
+            .timeout(SPOTIFY_TIMEOUT)
+            .send()
+            .await
+            // Any answer at all means spotify is there
+            .map(drop)
+            .map_err(|error| error.to_string());
+        checks.insert("spotify".to_owned(), Check::new(started, result));
+    }
+
+    let (code, status) = if checks.values().all(|check| check.status == Status::Ok) {
+        (StatusCode::OK, Status::Ok)
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Status::Failed)
+    };
+
+    (code, Json(Ready { status, checks })).into_response()
+}
+
+fn static_assets(static_dir: &Path) -> Result<(), String> {
+    let index = static_dir.join("index.html");
+
+    if index.is_file() {
+        Ok(())
+    } else {
+        Err(format!("{} is missing", index.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{body::Body, http::Request, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::api::tests::{in_memory, json_body, serve, test_router, test_router_with};
+
+    /// A static directory with just the index in it, unique to the test
+    fn static_dir(test: &str) -> Arc<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("banger-{test}-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "<!DOCTYPE html>").unwrap();
+
+        Arc::new(dir)
+    }
+
+    async fn get(app: Router, uri: &str) -> Response {
+        app.oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn live_is_ok() {
+        let (sessions, storage) = in_memory();
+
+        let response = get(
+            test_router(String::new(), sessions, storage),
+            "/health/live",
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn ready_lists_every_check() {
+        let (sessions, storage) = in_memory();
+        let health = Health {
+            static_dir: static_dir("ready"),
+            heartbeats: Arc::new([("listener", Heartbeat::late(Duration::ZERO))]),
+        };
+        health.heartbeats[0].1.beat(Duration::from_secs(10));
+
+        let response = get(
+            test_router_with(String::new(), sessions, storage, health),
+            "/health/ready",
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = json_body(response).await;
+        assert_eq!(body["status"], "ok");
+
+        let checks = body["checks"].as_object().unwrap();
+        assert_eq!(
+            checks.keys().collect::<Vec<_>>(),
+            ["static_assets", "storage", "worker.listener"]
+        );
+        for check in checks.values() {
+            assert_eq!(check["status"], "ok");
+            assert!(check["latency_ms"].as_f64().unwrap() >= 0.0);
+            assert!(check.get("error").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn ready_fails_on_missing_assets_and_stuck_workers() {
+        let (sessions, storage) = in_memory();
+        let health = Health {
+            static_dir: Arc::new(std::env::temp_dir().join("banger-nowhere")),
+            heartbeats: Arc::new([("listener", Heartbeat::late(Duration::from_secs(60)))]),
+        };
+
+        let response = get(
+            test_router_with(String::new(), sessions, storage, health),
+            "/health/ready",
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = json_body(response).await;
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["checks"]["storage"]["status"], "ok");
+        assert_eq!(body["checks"]["static_assets"]["status"], "failed");
+        assert!(body["checks"]["static_assets"]["error"]
+            .as_str()
+            .unwrap()
+            .contains("index.html"));
+        assert_eq!(body["checks"]["worker.listener"]["status"], "failed");
+    }
+
+    #[tokio::test]
+    async fn spotify_is_only_checked_when_asked() {
+        let reachable = serve(Router::new()).await;
+
+        let ready = |spotify_url: String, uri: &'static str| async move {
+            let (sessions, storage) = in_memory();
+            let health = Health {
+                static_dir: static_dir("spotify"),
+                heartbeats: Arc::new([]),
+            };
+
+            json_body(
+                get(
+                    test_router_with(spotify_url, sessions, storage, health),
+                    uri,
+                )
+                .await,
+            )
+            .await
+        };
+
+        let body = ready(reachable.clone(), "/health/ready").await;
+        assert!(body["checks"].get("spotify").is_none());
+
+        let body = ready(reachable, "/health/ready?spotify=true").await;
+        assert_eq!(body["checks"]["spotify"]["status"], "ok");
+
+        let body = ready(
+            "http://127.0.0.1:1".to_owned(),
+            "/health/ready?spotify=true",
+        )
+        .await;
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["checks"]["spotify"]["status"], "failed");
+    }
+}
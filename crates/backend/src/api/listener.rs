@@ -0,0 +1,1217 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use rand::Rng;
+use spotify_banger_model::{ListenerSettings, Playback, PlayingItem};
+use spotify_banger_web_api::Error as WebApiError;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info, warn};
+
+use super::{
+    refresh, restore,
+    sessions::CurrentSession,
+    spotify::{self, WebApi},
+    OAuthConfig, HTTP_TIMEOUT,
+};
+use crate::{
+    clock::{Clock, SystemClock},
+    detect::{Detector, Observation},
+    plays::{Play, PlayId},
+    session::SpotifyTokens,
+    shutdown::{Heartbeat, Stop},
+    storage::Storage,
+    users::UserId,
+};
+
+/// How often to poll each user while everything is going well
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// How far polls stray from the schedule, so that users polled together
+/// drift apart instead of all hitting spotify at once
+const JITTER: Duration = Duration::from_secs(2);
+/// The longest to ever wait between two polls of a user that keep failing
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+/// How often to look for users who opted in or out
+const USERS_INTERVAL: Duration = Duration::from_secs(60);
+/// How long to wait after spotify rate limited us without saying for how long
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// How often to backfill the plays that polling missed, which has to be well
+/// within the last 50 tracks that spotify remembers
+const BACKFILL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// The most pages of recently played tracks to backfill at once
+const BACKFILL_PAGES: usize = 5;
+/// The scope needed to look at recently played tracks, which users who
+/// authorized before it was asked for do not have
+const RECENTLY_PLAYED_SCOPE: &str = "user-read-recently-played";
+/// How long spotify gets to answer, retries included, before the request
+/// counts as failed, which keeps the heartbeat well within its grace
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// A track that is this much behind where it was last seen was started over
+const RESTART_MARGIN_MS: u64 = 5_000;
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Some polling interval, give or take the jitter
+fn jittered(interval: Duration) -> Duration {
+    let jitter = rand::thread_rng().gen_range(0.0..=2.0) * JITTER.as_secs_f64();
+
+    (interval + Duration::from_secs_f64(jitter)).saturating_sub(JITTER)
+}
+
+/// Anywhere within the interval, for users who are yet to be polled
+fn spread(interval: Duration) -> Duration {
+    interval.mul_f64(rand::thread_rng().gen_range(0.0..1.0))
+}
+
+/// How long to wait before polling a user again after their polls failed
+/// this many times in a row
+fn backoff(failures: u32) -> Duration {
+    POLL_INTERVAL
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(MAX_BACKOFF)
+}
+
+/// The track a user is playing, as last seen by the listener
+#[derive(Debug)]
+struct Playing {
+    play: PlayId,
+    track_id: String,
+    progress_ms: u64,
+}
+
+#[derive(Debug)]
+struct Listened {
+    /// Restored from storage whenever there are none
+    tokens: Option<SpotifyTokens>,
+    next_poll: SystemTime,
+    next_backfill: SystemTime,
+    failures: u32,
+    playing: Option<Playing>,
+}
+
+/// The outcome of a single poll of a user
+#[derive(Debug)]
+enum Poll {
+    Playing(Box<Playback>),
+    Nothing,
+    RateLimited(Duration),
+    Failed,
+}
+
+/// Polls what every user who opted in is listening to, recording their plays
+/// and passing them on to the detector, so that nobody has to keep a tab open
+#[derive(Debug)]
+pub struct Listener {
+    config: OAuthConfig,
+    reqwest: reqwest::Client,
+    transport: WebApi,
+    storage: Storage,
+    detector: Option<Detector>,
+    clock: Arc<dyn Clock>,
+    users: HashMap<UserId, Listened>,
+    users_checked_at: Option<SystemTime>,
+    /// Spotify asked everyone to back off until then
+    paused_until: Option<SystemTime>,
+    /// Beaten before every request, while running
+    heartbeat: Option<Heartbeat>,
+}
+
+impl Listener {
+    pub fn new(
+        config: OAuthConfig,
+        reqwest: reqwest::Client,
+        transport: WebApi,
+        storage: Storage,
+        detector: Option<Detector>,
+    ) -> Self {
+        Self::with_clock(config, reqwest, transport, storage, detector, SystemClock)
+    }
+
+    fn with_clock(
+        config: OAuthConfig,
+        reqwest: reqwest::Client,
+        transport: WebApi,
+        storage: Storage,
+        detector: Option<Detector>,
+        clock: impl Clock,
+    ) -> Self {
+        Self {
+            config,
+            reqwest,
+            transport,
+            storage,
+            detector,
+            clock: Arc::new(clock),
+            users: HashMap::new(),
+            users_checked_at: None,
+            paused_until: None,
+            heartbeat: None,
+        }
+    }
+
+    /// Keep listening until told to stop, which only happens in between
+    /// ticks so that no play is left half recorded
+    pub async fn run(mut self, mut stop: Stop, heartbeat: Heartbeat) {
+        info!("listening along with users who opted in");
+
+        self.heartbeat = Some(heartbeat.clone());
+
+        loop {
+            let wait = self.tick().await;
+            heartbeat.beat(wait);
+
+            tokio::select! {
+                () = sleep(wait) => {}
+                () = stop.requested() => break,
+            }
+        }
+
+        info!("stopped listening along");
+    }
+
+    /// Poll every user that is due, giving back how long to wait until the
+    /// next one is
+    async fn tick(&mut self) -> Duration {
+        let now = self.clock.now();
+
+        if self
+            .users_checked_at
+            .is_none_or(|checked_at| checked_at + USERS_INTERVAL <= now)
+        {
+            self.check_users(now);
+        }
+
+        if let Some(paused_until) = self.paused_until {
+            if paused_until > now {
+                return paused_until.duration_since(now).unwrap_or_default();
+            }
+
+            self.paused_until = None;
+        }
+
+        let mut due = self
+            .users
+            .iter()
+            .filter(|(_, listened)| listened.next_poll <= now)
+            .map(|(&user, listened)| (listened.next_poll, user))
+            .collect::<Vec<_>>();
+        due.sort_by_key(|&(next_poll, user)| (next_poll, user.0));
+
+        for (_, user) in due {
+            if let Poll::RateLimited(retry_after) = self.poll(user).await {
+                warn!(?retry_after, "rate limited by spotify, pausing all polls");
+
+                self.paused_until = Some(self.clock.now() + retry_after);
+
+                return retry_after;
+            }
+        }
+
+        let next_poll = self
+            .users
+            .values()
+            .map(|listened| listened.next_poll)
+            .chain(
+                self.users_checked_at
+                    .map(|checked_at| checked_at + USERS_INTERVAL),
+            )
+            .min();
+
+        next_poll
+            .and_then(|next_poll| next_poll.duration_since(self.clock.now()).ok())
+            .unwrap_or_default()
+            .min(USERS_INTERVAL)
+    }
+
+    /// Start listening to users who opted in, and stop listening to those who opted out
+    fn check_users(&mut self, now: SystemTime) {
+        let listeners = match self.storage.listeners() {
+            Ok(listeners) => listeners,
+            Err(error) => {
+                error!(%error, "failed to look up users to listen along with");
+
+                return;
+            }
+        };
+
+        self.users_checked_at = Some(now);
+        self.users.retain(|user, _| listeners.contains(user));
+
+        for user in listeners {
+            self.users.entry(user).or_insert_with(|| {
+                debug!(%user, "listening along");
+
+                Listened {
+                    tokens: None,
+                    // Spread out over the first interval instead of all polling at
+                    // once, which spreads out their first backfills as well
+                    next_poll: now + spread(POLL_INTERVAL),
+                    // Catch up on whatever played while nobody was listening
+                    next_backfill: now,
+                    failures: 0,
+                    playing: None,
+                }
+            });
+        }
+    }
+
+    async fn poll(&mut self, user: UserId) -> Poll {
+        let poll = self.fetch(user).await;
+        // Polls before this one may have taken a while
+        let now = self.clock.now();
+
+        let listened = match self.users.get_mut(&user) {
+            Some(listened) => listened,
+            None => return poll,
+        };
+
+        match &poll {
+            Poll::Playing(_) | Poll::Nothing => {
+                listened.failures = 0;
+                listened.next_poll = now + jittered(POLL_INTERVAL);
+            }
+            // Everyone waits out the rate limit, this user goes first after it
+            Poll::RateLimited(retry_after) => listened.next_poll = now + *retry_after,
+            Poll::Failed => {
+                listened.failures += 1;
+                listened.next_poll = now + jittered(backoff(listened.failures));
+            }
+        }
+
+        match &poll {
+            Poll::Playing(playback) => self.listened(user, (**playback).clone(), now),
+            Poll::Nothing => listened.playing = None,
+            Poll::RateLimited(_) | Poll::Failed => return poll,
+        }
+
+        match self.users.get_mut(&user) {
+            Some(listened) if listened.next_backfill <= now => {
+                listened.next_backfill = now + BACKFILL_INTERVAL;
+            }
+            _ => return poll,
+        }
+
+        match self.backfill(user).await {
+            Some(retry_after) => {
+                if let Some(listened) = self.users.get_mut(&user) {
+                    listened.next_backfill = now + retry_after;
+                }
+
+                Poll::RateLimited(retry_after)
+            }
+            None => poll,
+        }
+    }
+
+    /// Fill in the plays that polling missed from the tracks spotify remembers
+    /// the user played, giving back how long to wait if spotify rate limited us
+    async fn backfill(&mut self, user: UserId) -> Option<Duration> {
+        let access_token = self.access_token(user).await?;
+
+        let scoped = self.users[&user].tokens.as_ref().is_some_and(|tokens| {
+            tokens
+                .scope
+                .split(' ')
+                .any(|scope| scope == RECENTLY_PLAYED_SCOPE)
+        });
+        if !scoped {
+            debug!(%user, "not allowed to look at recently played tracks");
+
+            return None;
+        }
+
+        let mut after = match self.storage.recently_played_after(user) {
+            Ok(after) => after,
+            Err(error) => {
+                error!(%user, %error, "failed to look up where backfilling left off");
+
+                return None;
+            }
+        };
+
+        let spotify = spotify::web_api(&self.transport, &self.config, &access_token);
+
+        for _ in 0..BACKFILL_PAGES {
+            let recently_played = match self.timed(spotify.recently_played(after, 50)).await {
+                Some(Ok(recently_played)) => recently_played,
+                None => {
+                    warn!(%user, "timed out fetching recently played tracks");
+
+                    return None;
+                }
+                Some(Err(WebApiError::RateLimited(retry_after))) => {
+                    return Some(retry_after.unwrap_or(DEFAULT_RETRY_AFTER))
+                }
+                Some(Err(error)) => {
+                    warn!(%user, %error, "failed to fetch recently played tracks");
+
+                    return None;
+                }
+            };
+
+            // Missing when there was nothing new
+            let cursor = match recently_played
+                .cursors
+                .and_then(|cursors| cursors.after?.parse::<u64>().ok())
+            {
+                Some(cursor) => cursor,
+                None => break,
+            };
+
+            let plays = recently_played
+                .items
+                .into_iter()
+                .filter_map(Play::from_history)
+                .collect::<Vec<_>>();
+
+            match self.storage.record_recently_played(user, &plays, cursor) {
+                Ok(0) => {}
+                Ok(backfilled) => info!(%user, backfilled, "backfilled missed plays"),
+                Err(error) => {
+                    error!(%user, %error, "failed to backfill plays");
+
+                    return None;
+                }
+            }
+
+            if recently_played.next.is_none() {
+                break;
+            }
+
+            after = Some(cursor);
+        }
+
+        None
+    }
+
+    /// Ask spotify what the user is listening to, with tokens that are fresh
+    async fn fetch(&mut self, user: UserId) -> Poll {
+        let access_token = match self.access_token(user).await {
+            Some(access_token) => access_token,
+            None => return Poll::Failed,
+        };
+
+        let spotify = spotify::web_api(&self.transport, &self.config, &access_token);
+        let playback = match self.timed(spotify.playback()).await {
+            Some(playback) => playback,
+            None => {
+                warn!(%user, "timed out fetching playback");
+
+                return Poll::Failed;
+            }
+        };
+
+        match playback {
+            Ok(Some(playback)) => Poll::Playing(Box::new(playback)),
+            Ok(None) => Poll::Nothing,
+            Err(WebApiError::RateLimited(retry_after)) => {
+                Poll::RateLimited(retry_after.unwrap_or(DEFAULT_RETRY_AFTER))
+            }
+            Err(error) => {
+                warn!(%user, %error, "failed to fetch playback");
+
+                if error.status() == Some(401) {
+                    // Restored and refreshed on the next poll
+                    if let Some(listened) = self.users.get_mut(&user) {
+                        listened.tokens = None;
+                    }
+                }
+
+                Poll::Failed
+            }
+        }
+    }
+
+    /// Give spotify only so long to answer, beating the heartbeat first so that
+    /// a tick polling many users in a row does not look stuck. Token refreshes
+    /// are only bounded by the timeout of the http client, as cutting them off
+    /// any sooner makes it more likely to lose a rotated refresh token
+    async fn timed<T>(&self, request: impl Future<Output = T>) -> Option<T> {
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.beat(REQUEST_TIMEOUT);
+        }
+
+        timeout(REQUEST_TIMEOUT, request).await.ok()
+    }
+
+    /// The access token of the user, refreshing it first if needed
+    async fn access_token(&mut self, user: UserId) -> Option<String> {
+        let listened = self.users.get_mut(&user)?;
+
+        if listened.tokens.is_none() {
+            listened.tokens = restore(&self.config, &self.storage, user);
+        }
+
+        let tokens = listened.tokens.as_mut()?;
+
+        if tokens.needs_refresh() {
+            if let Some(heartbeat) = &self.heartbeat {
+                heartbeat.beat(HTTP_TIMEOUT);
+            }
+
+            if let Err(error) =
+                refresh(&self.reqwest, &self.config, &self.storage, user, tokens).await
+            {
+                warn!(%user, ?error, "failed to refresh tokens");
+
+                // Someone else may have stored a newer refresh token in the meantime
+                listened.tokens = None;
+
+                return None;
+            }
+        }
+
+        Some(tokens.access_token.clone())
+    }
+
+    /// Record the play and let the detector have a look at it
+    fn listened(&mut self, user: UserId, playback: Playback, now: SystemTime) {
+        let listened = match self.users.get_mut(&user) {
+            Some(listened) => listened,
+            None => return,
+        };
+
+        let track_id = match &playback.playing.item {
+            Some(PlayingItem::Track(track)) => track.id.clone(),
+            _ => None,
+        };
+        let track_id = match track_id {
+            Some(track_id) => track_id,
+            None => {
+                listened.playing = None;
+
+                return;
+            }
+        };
+
+        let now_ms = unix_millis(now);
+        let progress_ms = playback.playing.progress_ms.unwrap_or_default();
+
+        let still_playing = listened.playing.as_ref().filter(|playing| {
+            playing.track_id == track_id && playing.progress_ms <= progress_ms + RESTART_MARGIN_MS
+        });
+
+        match still_playing {
+            Some(playing) => {
+                if let Err(error) = self.storage.extend_play(playing.play, now_ms, progress_ms) {
+                    error!(%user, %error, "failed to extend play");
+                }
+            }
+            None => {
+                let play = Play {
+                    track_id: track_id.clone(),
+                    context: playback
+                        .playing
+                        .context
+                        .as_ref()
+                        .map(|context| context.uri.clone()),
+                    device: Some(playback.device.name.clone()),
+                    started_at: now_ms.saturating_sub(progress_ms),
+                    last_seen: now_ms,
+                    progress_ms,
+                    played_at: None,
+                };
+
+                match self.storage.start_play(user, &play) {
+                    Ok(play) => {
+                        listened.playing = Some(Playing {
+                            play,
+                            track_id,
+                            progress_ms,
+                        })
+                    }
+                    Err(error) => error!(%user, %error, "failed to start play"),
+                }
+            }
+        }
+
+        if let Some(playing) = &mut listened.playing {
+            playing.progress_ms = progress_ms;
+        }
+
+        let detector = match &self.detector {
+            Some(detector) => detector,
+            None => return,
+        };
+
+        if let Some(mark) = Observation::new(playback, now_ms)
+            .and_then(|observation| detector.observe(user, observation))
+        {
+            match self.storage.record_banger(user, mark) {
+                Ok(banger) => info!(%user, track = banger.track_id, "detected banger"),
+                Err(error) => error!(%user, %error, "failed to record detected banger"),
+            }
+        }
+    }
+}
+
+/// Whether the backend listens along with the logged in user
+pub async fn settings(
+    CurrentSession(session): CurrentSession,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    match storage.is_listening(session.user) {
+        Ok(enabled) => Json(ListenerSettings { enabled }).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Have the backend listen along with the logged in user, or stop doing so
+pub async fn configure(
+    CurrentSession(session): CurrentSession,
+    Extension(storage): Extension<Storage>,
+    Json(settings): Json<ListenerSettings>,
+) -> Response {
+    match storage.set_listening(session.user, settings.enabled) {
+        Ok(()) => Json(settings).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Reverse, sync::Mutex};
+
+    use axum::{
+        body::Body,
+        extract::{Form, Query},
+        http::{header, Request, StatusCode},
+        routing, Router,
+    };
+    use spotify_banger_web_api::{RetryPolicy, Tokio};
+    use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        api::{
+            http_client,
+            spotify::SPOTIFY_SCOPE,
+            tests::{json_body, serve, test_config, test_router},
+        },
+        clock::MockClock,
+        detect::DetectorConfig,
+        session::{SessionStorage, TokenClient, SESSION_COOKIE},
+    };
+
+    /// A stand-in for spotify that plays back the scripted responses of the
+    /// player endpoint one after the other, repeating the last one
+    #[derive(Debug, Clone, Default)]
+    struct FakeSpotify {
+        script: Arc<Mutex<Vec<(StatusCode, String)>>>,
+        player_requests: Arc<Mutex<Vec<String>>>,
+        refreshes: Arc<Mutex<u32>>,
+        /// The recently played tracks, as listed after any cursor
+        recently_played: Arc<Mutex<Vec<(u64, &'static str)>>>,
+        /// The `after` cursor of every request for recently played tracks
+        backfills: Arc<Mutex<Vec<Option<u64>>>>,
+    }
+
+    impl FakeSpotify {
+        async fn serve(&self) -> String {
+            let player = self.clone();
+            let token = self.clone();
+            let recently_played = self.clone();
+
+            serve(
+                Router::new()
+                    .route(
+                        "/v1/me/player",
+                        routing::get(move |headers: axum::http::HeaderMap| async move {
+                            let authorization = headers
+                                .get(header::AUTHORIZATION)
+                                .and_then(|value| value.to_str().ok())
+                                .unwrap_or_default()
+                                .to_owned();
+                            player.player_requests.lock().unwrap().push(authorization);
+
+                            let mut script = player.script.lock().unwrap();
+                            let (status, body) = if script.len() > 1 {
+                                script.remove(0)
+                            } else {
+                                script[0].clone()
+                            };
+
+                            let mut response =
+                                (status, [(header::CONTENT_TYPE, "application/json")], body)
+                                    .into_response();
+                            if status == StatusCode::TOO_MANY_REQUESTS {
+                                response
+                                    .headers_mut()
+                                    .insert(header::RETRY_AFTER, "1".parse().unwrap());
+                            }
+
+                            response
+                        }),
+                    )
+                    .route(
+                        "/v1/me/player/recently-played",
+                        routing::get(
+                            move |Query(query): Query<HashMap<String, u64>>| async move {
+                                let after = query.get("after").copied();
+                                recently_played.backfills.lock().unwrap().push(after);
+
+                                let mut listed = recently_played
+                                    .recently_played
+                                    .lock()
+                                    .unwrap()
+                                    .iter()
+                                    .copied()
+                                    .filter(|(played_at, _)| *played_at > after.unwrap_or_default())
+                                    .collect::<Vec<_>>();
+                                listed.sort_by_key(|&(played_at, _)| Reverse(played_at));
+
+                                let items = listed
+                                    .iter()
+                                    .map(|&(played_at, track_id)| {
+                                        serde_json::json!({
+                                            "track": track(track_id),
+                                            "played_at": OffsetDateTime::from_unix_timestamp_nanos(
+                                                i128::from(played_at) * 1_000_000
+                                            )
+                                            .unwrap()
+                                            .format(&Rfc3339)
+                                            .unwrap(),
+                                            "context": null
+                                        })
+                                    })
+                                    .collect::<Vec<_>>();
+
+                                let newest = listed.first().map(|&(played_at, _)| played_at);
+
+                                Json(serde_json::json!({
+                                    "href": "https://api.spotify.com/v1/me/player/recently-played",
+                                    "limit": 50,
+                                    "next": null,
+                                    "cursors": newest.map(|newest| serde_json::json!({
+                                        "after": newest.to_string(),
+                                        "before": newest.to_string()
+                                    })),
+                                    "items": items
+                                }))
+                            },
+                        ),
+                    )
+                    .route(
+                        "/api/token",
+                        routing::post(
+                            move |Form(form): Form<HashMap<String, String>>| async move {
+                                assert_eq!(form["grant_type"], "refresh_token");
+                                *token.refreshes.lock().unwrap() += 1;
+
+                                Json(serde_json::json!({
+                                    "access_token": "fresh access",
+                                    "token_type": "Bearer",
+                                    "scope": SPOTIFY_SCOPE,
+                                    "expires_in": 3600
+                                }))
+                            },
+                        ),
+                    ),
+            )
+            .await
+        }
+
+        fn script(&self, responses: impl IntoIterator<Item = (StatusCode, String)>) {
+            *self.script.lock().unwrap() = responses.into_iter().collect();
+        }
+
+        fn polls(&self) -> usize {
+            self.player_requests.lock().unwrap().len()
+        }
+    }
+
+    fn track(track_id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "album": {
+                "album_type": "album",
+                "artists": [],
+                "external_urls": { "spotify": "https://open.spotify.com/album/album" },
+                "href": null,
+                "id": "album",
+                "images": [],
+                "name": "Album",
+                "release_date": null,
+                "type": "album",
+                "uri": "spotify:album:album"
+            },
+            "artists": [],
+            "duration_ms": 300000,
+            "explicit": false,
+            "external_urls": { "spotify": "https://open.spotify.com/track/track" },
+            "href": null,
+            "id": track_id,
+            "is_local": false,
+            "name": "Track",
+            "preview_url": null,
+            "uri": format!("spotify:track:{track_id}")
+        })
+    }
+
+    fn playing(track_id: &str, progress_ms: u64) -> (StatusCode, String) {
+        let mut item = track(track_id);
+        item["type"] = "track".into();
+
+        (
+            StatusCode::OK,
+            serde_json::json!({
+                "device": {
+                    "id": "device",
+                    "is_active": true,
+                    "name": "Phone",
+                    "type": "Smartphone",
+                    "volume_percent": 100
+                },
+                "repeat_state": "off",
+                "timestamp": 1657843200000u64,
+                "context": null,
+                "progress_ms": progress_ms,
+                "is_playing": true,
+                "item": item,
+                "currently_playing_type": "track"
+            })
+            .to_string(),
+        )
+    }
+
+    fn nothing() -> (StatusCode, String) {
+        (StatusCode::NO_CONTENT, String::new())
+    }
+
+    struct Setup {
+        listener: Listener,
+        spotify: FakeSpotify,
+        storage: Storage,
+        clock: MockClock,
+        user: UserId,
+    }
+
+    /// A listener for a user who opted in, against a fake spotify
+    async fn setup(detector: Option<Detector>) -> Setup {
+        let spotify = FakeSpotify::default();
+        let config = test_config(&spotify.serve().await);
+        let storage = Storage::in_memory();
+        let clock = MockClock::new();
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        let tokens = SpotifyTokens {
+            access_token: String::new(),
+            refresh_token: "refresh".into(),
+            scope: "user-read-playback-state".into(),
+            expires_at: UNIX_EPOCH,
+            client: TokenClient::Confidential,
+        };
+        storage
+            .store_refresh_token(user, &tokens.persistent(&config.token_cipher, user))
+            .unwrap();
+        storage.set_listening(user, true).unwrap();
+
+        let listener = Listener::with_clock(
+            config,
+            reqwest::Client::new(),
+            // Failures are passed on right away, for the listener to deal with
+            WebApi::new(reqwest::Client::new(), Tokio).with_policy(RetryPolicy {
+                max_retries: 0,
+                ..Default::default()
+            }),
+            storage.clone(),
+            detector,
+            clock.clone(),
+        );
+
+        Setup {
+            listener,
+            spotify,
+            storage,
+            clock,
+            user,
+        }
+    }
+
+    /// Move the clock to the next poll and run it
+    async fn next_tick(setup: &mut Setup) -> Duration {
+        let wait = setup.listener.tick().await;
+        setup.clock.advance(wait);
+
+        setup.listener.tick().await
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        for _ in 0..100 {
+            let interval = jittered(POLL_INTERVAL);
+
+            assert!(interval >= POLL_INTERVAL - JITTER);
+            assert!(interval <= POLL_INTERVAL + JITTER);
+        }
+    }
+
+    #[test]
+    fn first_polls_are_spread_over_interval() {
+        let first_polls = (0..100).map(|_| spread(POLL_INTERVAL)).collect::<Vec<_>>();
+
+        assert!(first_polls.iter().all(|&wait| wait < POLL_INTERVAL));
+        assert!(first_polls.iter().any(|&wait| wait < POLL_INTERVAL / 4));
+        assert!(first_polls.iter().any(|&wait| wait > POLL_INTERVAL * 3 / 4));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(backoff(0), POLL_INTERVAL);
+        assert_eq!(backoff(3), POLL_INTERVAL * 8);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn plays_are_recorded() {
+        let mut setup = setup(None).await;
+        setup.spotify.script([
+            playing("first", 10_000),
+            playing("first", 20_000),
+            playing("second", 1_000),
+            playing("second", 11_000),
+            // Started over
+            playing("second", 500),
+        ]);
+
+        for _ in 0..5 {
+            next_tick(&mut setup).await;
+        }
+
+        assert_eq!(setup.spotify.polls(), 5);
+        assert_eq!(*setup.spotify.refreshes.lock().unwrap(), 1);
+        assert!(setup
+            .spotify
+            .player_requests
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|authorization| authorization == "Bearer fresh access"));
+
+        let plays = setup
+            .storage
+            .plays(setup.user)
+            .unwrap()
+            .into_iter()
+            .map(|play| (play.track_id, play.progress_ms, play.device))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            plays,
+            [
+                ("second".into(), 500, Some("Phone".into())),
+                ("second".into(), 11_000, Some("Phone".into())),
+                ("first".into(), 20_000, Some("Phone".into())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn users_who_opted_out_are_left_alone() {
+        let mut setup = setup(None).await;
+        setup.spotify.script([nothing()]);
+        setup.storage.set_listening(setup.user, false).unwrap();
+
+        assert_eq!(next_tick(&mut setup).await, USERS_INTERVAL);
+        assert_eq!(setup.spotify.polls(), 0);
+    }
+
+    #[tokio::test]
+    async fn polls_beat_the_heartbeat() {
+        let mut setup = setup(None).await;
+        setup.spotify.script([nothing()]);
+
+        let heartbeat = Heartbeat::late(Duration::from_secs(60));
+        setup.listener.heartbeat = Some(heartbeat.clone());
+
+        // Only the first tick after the users check polls anyone
+        setup.listener.tick().await;
+        assert!(heartbeat.overdue().is_some());
+
+        next_tick(&mut setup).await;
+
+        assert_eq!(setup.spotify.polls(), 1);
+        assert_eq!(heartbeat.overdue(), None);
+    }
+
+    #[tokio::test]
+    async fn failing_polls_back_off() {
+        let mut setup = setup(None).await;
+        setup
+            .spotify
+            .script([(StatusCode::INTERNAL_SERVER_ERROR, String::new())]);
+
+        next_tick(&mut setup).await;
+
+        for failures in 1..=3 {
+            let listened = &setup.listener.users[&setup.user];
+            let wait = listened
+                .next_poll
+                .duration_since(setup.clock.now())
+                .unwrap();
+
+            assert_eq!(listened.failures, failures);
+            assert!(wait >= backoff(failures) - JITTER);
+            assert!(wait <= backoff(failures) + JITTER);
+
+            setup.clock.advance(wait);
+            setup.listener.tick().await;
+        }
+
+        assert_eq!(setup.spotify.polls(), 4);
+
+        setup.spotify.script([nothing()]);
+        setup.clock.advance(MAX_BACKOFF);
+        setup.listener.tick().await;
+
+        assert_eq!(setup.listener.users[&setup.user].failures, 0);
+    }
+
+    #[tokio::test]
+    async fn missed_plays_are_backfilled() {
+        let mut setup = setup(None).await;
+        setup.spotify.script([nothing()]);
+        *setup.spotify.recently_played.lock().unwrap() =
+            vec![(1657843000000, "missed"), (1657843300000, "also missed")];
+
+        next_tick(&mut setup).await;
+        next_tick(&mut setup).await;
+
+        setup
+            .spotify
+            .recently_played
+            .lock()
+            .unwrap()
+            .push((1657843600000, "new"));
+        setup.clock.advance(BACKFILL_INTERVAL);
+        setup.listener.tick().await;
+
+        assert_eq!(
+            *setup.spotify.backfills.lock().unwrap(),
+            [None, Some(1657843300000)]
+        );
+
+        let plays = setup
+            .storage
+            .plays(setup.user)
+            .unwrap()
+            .into_iter()
+            .map(|play| (play.track_id, play.started_at, play.played_at))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            plays,
+            [
+                ("new".into(), 1657843300000, Some(1657843600000)),
+                ("also missed".into(), 1657843000000, Some(1657843300000)),
+                ("missed".into(), 1657842700000, Some(1657843000000)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limits_pause_everyone() {
+        let mut setup = setup(None).await;
+        let other = setup.storage.spotify_user("other spotify user").unwrap();
+        let tokens = SpotifyTokens {
+            access_token: String::new(),
+            refresh_token: "other refresh".into(),
+            scope: "user-read-playback-state".into(),
+            expires_at: UNIX_EPOCH,
+            client: TokenClient::Confidential,
+        };
+        setup
+            .storage
+            .store_refresh_token(
+                other,
+                &tokens.persistent(&setup.listener.config.token_cipher, other),
+            )
+            .unwrap();
+        setup.storage.set_listening(other, true).unwrap();
+
+        setup
+            .spotify
+            .script([(StatusCode::TOO_MANY_REQUESTS, String::new()), nothing()]);
+
+        // Both are due, but only the first gets polled
+        setup.listener.tick().await;
+        setup.clock.advance(POLL_INTERVAL);
+        let wait = setup.listener.tick().await;
+
+        assert_eq!(setup.spotify.polls(), 1);
+        assert_eq!(wait, Duration::from_secs(1));
+
+        setup.clock.advance(Duration::from_millis(500));
+        setup.listener.tick().await;
+        assert_eq!(setup.spotify.polls(), 1);
+
+        // The transport holds back the access token for as long as well
+        setup.clock.advance(Duration::from_millis(500));
+        setup.listener.tick().await;
+        assert_eq!(setup.spotify.polls(), 3);
+    }
+
+    #[tokio::test]
+    async fn detected_bangers_are_recorded() {
+        let detector = Detector::with_default_rules(DetectorConfig::default());
+        let mut setup = setup(Some(detector)).await;
+        setup.spotify.script([
+            playing("other", 30_000),
+            // Skipped right to the good part
+            playing("track", 95_000),
+        ]);
+
+        next_tick(&mut setup).await;
+        next_tick(&mut setup).await;
+
+        let bangers = setup
+            .storage
+            .bangers(setup.user, &crate::bangers::Listing::everything())
+            .unwrap();
+
+        assert_eq!(bangers.len(), 1);
+        assert_eq!(bangers[0].banger.track_id, "track");
+        assert_eq!(bangers[0].banger.progress_ms, 95_000);
+    }
+
+    #[tokio::test]
+    async fn session_refreshes_after_listener_rotated_token() {
+        // Accepts nothing but the latest refresh token, rotating it on every
+        // refresh like spotify does for public clients
+        let latest = Arc::new(Mutex::new(0_u32));
+        let spotify_url = serve(Router::new().route(
+            "/api/token",
+            routing::post(
+                move |Form(form): Form<HashMap<String, String>>| async move {
+                    let mut latest = latest.lock().unwrap();
+
+                    if form["refresh_token"] != format!("refresh {latest}") {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            Json(serde_json::json!({ "error": "invalid_grant" })),
+                        );
+                    }
+
+                    *latest += 1;
+
+                    (
+                        StatusCode::OK,
+                        Json(serde_json::json!({
+                            "access_token": format!("access {latest}"),
+                            "token_type": "Bearer",
+                            "scope": SPOTIFY_SCOPE,
+                            "expires_in": 3600,
+                            "refresh_token": format!("refresh {latest}")
+                        })),
+                    )
+                },
+            ),
+        ))
+        .await;
+        let config = test_config(&spotify_url);
+        let storage = Storage::in_memory();
+        let sessions = SessionStorage::new(storage.clone());
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        let tokens = SpotifyTokens {
+            access_token: String::new(),
+            refresh_token: "refresh 0".into(),
+            scope: SPOTIFY_SCOPE.into(),
+            expires_at: UNIX_EPOCH,
+            client: TokenClient::Public,
+        };
+        storage
+            .store_refresh_token(user, &tokens.persistent(&config.token_cipher, user))
+            .unwrap();
+        storage.set_listening(user, true).unwrap();
+        let session = sessions.create_session(user, Some(tokens), None).unwrap();
+
+        let mut listener = Listener::new(
+            config.clone(),
+            reqwest::Client::new(),
+            WebApi::new(reqwest::Client::new(), Tokio),
+            storage.clone(),
+            None,
+        );
+        listener.check_users(SystemTime::now());
+        assert_eq!(listener.access_token(user).await.unwrap(), "access 1");
+
+        let response = test_router(spotify_url, sessions, storage.clone())
+            .oneshot(
+                Request::get("/auth/spotify/token")
+                    .header(header::COOKIE, format!("{SESSION_COOKIE}={session}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["access_token"], "access 2");
+
+        let stored = storage.refresh_token(user).unwrap().unwrap();
+        assert_eq!(
+            config
+                .token_cipher
+                .open(user, &stored.refresh_token)
+                .unwrap(),
+            "refresh 2"
+        );
+    }
+
+    #[tokio::test]
+    async fn hanging_token_requests_fail_the_poll() {
+        let spotify_url = serve(Router::new().route(
+            "/api/token",
+            routing::post(std::future::pending::<StatusCode>),
+        ))
+        .await;
+        let config = test_config(&spotify_url);
+        let storage = Storage::in_memory();
+
+        let user = storage.spotify_user("spotify user").unwrap();
+        let tokens = SpotifyTokens {
+            access_token: String::new(),
+            refresh_token: "refresh".into(),
+            scope: SPOTIFY_SCOPE.into(),
+            expires_at: UNIX_EPOCH,
+            client: TokenClient::Confidential,
+        };
+        storage
+            .store_refresh_token(user, &tokens.persistent(&config.token_cipher, user))
+            .unwrap();
+        storage.set_listening(user, true).unwrap();
+
+        // The same client as everywhere else, with less patience
+        let reqwest = http_client()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let mut listener = Listener::new(
+            config,
+            reqwest.clone(),
+            WebApi::new(reqwest, Tokio),
+            storage,
+            None,
+        );
+        listener.check_users(SystemTime::now());
+
+        let poll = timeout(Duration::from_secs(10), listener.poll(user))
+            .await
+            .expect("poll should give up on the token request");
+
+        assert!(matches!(poll, Poll::Failed));
+        assert_eq!(listener.users[&user].failures, 1);
+    }
+}
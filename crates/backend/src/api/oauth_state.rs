@@ -0,0 +1,260 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use base64::display::Base64Display;
+use rand::Rng;
+use serde::Serialize;
+
+use crate::clock::{Clock, SystemClock};
+
+/// The name of the cookie holding the state of an authorization flow with the provider
+pub fn state_cookie(provider: &str) -> String {
+    format!("{provider}_oauth_state")
+}
+
+/// How long a user has to make it through the authorization page
+const STATE_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct State {
+    state: [u8; 128],
+}
+
+impl State {
+    pub fn random() -> Self {
+        let mut state = [0_u8; 128];
+        rand::thread_rng().fill(&mut state);
+
+        Self { state }
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Base64Display::with_config(&self.state, base64::URL_SAFE).fmt(f)
+    }
+}
+
+impl FromStr for State {
+    type Err = base64::DecodeError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let mut state = [0_u8; 128];
+
+        let len = base64::decode_config_slice(str, base64::URL_SAFE, &mut state)?;
+
+        if len != state.len() {
+            return Err(base64::DecodeError::InvalidLength);
+        }
+
+        Ok(Self { state })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OAuthStateMetrics {
+    pub issued: u64,
+    pub validated: u64,
+    pub rejected: u64,
+    pub expired: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    issued: AtomicU64,
+    validated: AtomicU64,
+    rejected: AtomicU64,
+    expired: AtomicU64,
+}
+
+/// Binds oauth states to the browser that started the authorization flow.
+///
+/// The state lives in a cookie that is signed by the [`SignedCookieJar`], so
+/// any instance holding the signing key can validate it without having to
+/// share storage.
+///
+/// [`SignedCookieJar`]: axum_extra::extract::SignedCookieJar
+#[derive(Debug, Clone)]
+pub struct OAuthStates {
+    clock: Arc<dyn Clock>,
+    ttl: Duration,
+    counters: Arc<Counters>,
+}
+
+impl Default for OAuthStates {
+    fn default() -> Self {
+        Self::new(SystemClock, STATE_TTL)
+    }
+}
+
+impl OAuthStates {
+    pub fn new(clock: impl Clock, ttl: Duration) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ttl,
+            counters: Default::default(),
+        }
+    }
+
+    /// Create a new state along with the cookie that has to be signed and
+    /// given to the browser
+    pub fn issue(&self, provider: &str) -> (State, Cookie<'static>) {
+        let state = State::random();
+        let issued_at = self.unix_now();
+
+        let cookie = Cookie::build(state_cookie(provider), format!("{issued_at}.{state}"))
+            .path(format!("/api/auth/{provider}"))
+            .http_only(true)
+            .secure(cfg!(not(debug_assertions)))
+            .same_site(SameSite::Lax)
+            .max_age(time::Duration::seconds(self.ttl.as_secs() as i64))
+            .finish();
+
+        self.counters.issued.fetch_add(1, Ordering::Relaxed);
+
+        (state, cookie)
+    }
+
+    /// Check the state returned by the authorization page against the state
+    /// cookie, whose signature must already have been verified
+    pub fn validate(&self, cookie: Option<&Cookie>, state: State) -> bool {
+        let (issued_at, cookie_state) = match cookie.and_then(|cookie| parse(cookie.value())) {
+            Some(cookie) => cookie,
+            None => {
+                self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+
+                return false;
+            }
+        };
+
+        if cookie_state != state {
+            self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+
+            return false;
+        }
+
+        if self.unix_now().saturating_sub(issued_at) > self.ttl.as_secs() {
+            self.counters.expired.fetch_add(1, Ordering::Relaxed);
+
+            return false;
+        }
+
+        self.counters.validated.fetch_add(1, Ordering::Relaxed);
+
+        true
+    }
+
+    /// A cookie that can be used to remove the state cookie from the browser
+    pub fn removal(&self, provider: &str) -> Cookie<'static> {
+        Cookie::build(state_cookie(provider), "")
+            .path(format!("/api/auth/{provider}"))
+            .finish()
+    }
+
+    pub fn metrics(&self) -> OAuthStateMetrics {
+        OAuthStateMetrics {
+            issued: self.counters.issued.load(Ordering::Relaxed),
+            validated: self.counters.validated.load(Ordering::Relaxed),
+            rejected: self.counters.rejected.load(Ordering::Relaxed),
+            expired: self.counters.expired.load(Ordering::Relaxed),
+        }
+    }
+
+    fn unix_now(&self) -> u64 {
+        self.clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+fn parse(value: &str) -> Option<(u64, State)> {
+    let (issued_at, state) = value.split_once('.')?;
+
+    Some((issued_at.parse().ok()?, state.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn states() -> (OAuthStates, MockClock) {
+        let clock = MockClock::new();
+
+        (OAuthStates::new(clock.clone(), TTL), clock)
+    }
+
+    #[test]
+    fn state_round_trips_through_string() {
+        let state = State::random();
+
+        assert_eq!(state.to_string().parse::<State>(), Ok(state));
+    }
+
+    #[test]
+    fn cookie_validates_its_state() {
+        let (states, _) = states();
+
+        let (state, cookie) = states.issue("spotify");
+
+        assert_eq!(cookie.name(), "spotify_oauth_state");
+        assert_eq!(cookie.path(), Some("/api/auth/spotify"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+
+        assert!(states.validate(Some(&cookie), state));
+        assert_eq!(
+            states.metrics(),
+            OAuthStateMetrics {
+                issued: 1,
+                validated: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn cookie_rejects_other_states() {
+        let (states, _) = states();
+
+        let (_, cookie) = states.issue("spotify");
+        let (other, _) = states.issue("spotify");
+
+        assert!(!states.validate(Some(&cookie), other));
+        assert!(!states.validate(None, other));
+        assert!(!states.validate(
+            Some(&Cookie::new(state_cookie("spotify"), "garbage")),
+            other
+        ));
+
+        assert_eq!(states.metrics().rejected, 3);
+    }
+
+    #[test]
+    fn cookie_expires_after_ttl() {
+        let (states, clock) = states();
+
+        let (fresh, fresh_cookie) = states.issue("spotify");
+        let (stale, stale_cookie) = states.issue("spotify");
+
+        clock.advance(TTL);
+        assert!(states.validate(Some(&fresh_cookie), fresh));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!states.validate(Some(&stale_cookie), stale));
+
+        assert_eq!(states.metrics().expired, 1);
+    }
+}
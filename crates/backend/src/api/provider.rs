@@ -0,0 +1,279 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, Query},
+    headers::UserAgent,
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Extension, TypedHeader,
+};
+use axum_extra::extract::{CookieJar, SignedCookieJar};
+use monostate::MustBe;
+use reqwest::header;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    oauth_state::{state_cookie, OAuthStates, State},
+    send, CodeGrantRequest, CodeGrantResponse, CodeGrantResponseInner, OAuthError,
+};
+use crate::{session::SessionStorage, storage::Storage};
+
+/// An oauth provider that users can authorize through `/auth/:provider`,
+/// returning to `/auth/:provider/redirect`
+#[async_trait]
+pub trait OAuthProvider: Send + Sync + 'static {
+    /// Identifies the provider in its routes and cookies
+    const NAME: &'static str;
+
+    type TokenResponse: DeserializeOwned + Send + Sync;
+    type Identity: Send;
+
+    fn authorize_url(&self) -> &str;
+    fn token_url(&self) -> &str;
+    fn scope(&self) -> &str;
+    fn client_id(&self) -> &str;
+    fn client_secret(&self) -> &str;
+    fn redirect_uri(&self) -> &str;
+
+    /// Whether to ask the user for consent even if they authorized before
+    fn show_dialog(&self) -> bool {
+        false
+    }
+
+    /// The request exchanging an authorization code for tokens, sending the
+    /// client credentials in the form by default
+    fn token_request(&self, reqwest: &reqwest::Client, code: String) -> reqwest::RequestBuilder {
+        reqwest
+            .post(self.token_url())
+            .header(header::ACCEPT, "application/json")
+            .form(&TokenRequest {
+                grant_type: Default::default(),
+                code,
+                redirect_uri: self.redirect_uri(),
+                client_id: self.client_id(),
+                client_secret: self.client_secret(),
+            })
+    }
+
+    /// Find out who the tokens belong to
+    async fn identity(
+        &self,
+        reqwest: &reqwest::Client,
+        tokens: &Self::TokenResponse,
+    ) -> Result<Self::Identity, OAuthError>;
+
+    /// Act on a completed authorization, usually by starting a session
+    async fn login(
+        &self,
+        login: Login,
+        tokens: Self::TokenResponse,
+        identity: Self::Identity,
+    ) -> Response;
+}
+
+#[derive(Debug, Serialize)]
+struct TokenRequest<'s> {
+    grant_type: MustBe!("authorization_code"),
+    code: String,
+    redirect_uri: &'s str,
+    client_id: &'s str,
+    client_secret: &'s str,
+}
+
+/// What a provider has to work with when a user finishes authorizing
+pub struct Login {
+    pub jar: CookieJar,
+    pub sessions: SessionStorage,
+    pub storage: Storage,
+    /// Kept with the session so that the user can tell their devices apart
+    pub user_agent: Option<String>,
+}
+
+/// The object safe part of [`OAuthProvider`], so that providers with
+/// different token and identity types can share a registry
+#[async_trait]
+trait DynProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn authorization_url(&self, state: State) -> String;
+
+    async fn complete(&self, reqwest: &reqwest::Client, code: String, login: Login) -> Response;
+}
+
+#[async_trait]
+impl<P: OAuthProvider> DynProvider for P {
+    fn name(&self) -> &'static str {
+        P::NAME
+    }
+
+    fn authorization_url(&self, state: State) -> String {
+        let query = serde_urlencoded::to_string(CodeGrantRequest {
+            response_type: Default::default(),
+            client_id: self.client_id(),
+            scope: self.scope(),
+            redirect_uri: self.redirect_uri(),
+            state,
+            show_dialog: self.show_dialog(),
+        })
+        .unwrap();
+
+        format!("{}?{query}", self.authorize_url())
+    }
+
+    async fn complete(&self, reqwest: &reqwest::Client, code: String, login: Login) -> Response {
+        let tokens =
+            match send::<P::TokenResponse>(P::NAME, self.token_request(reqwest, code)).await {
+                Ok(tokens) => tokens,
+                Err(error) => return error.into_response(),
+            };
+
+        let identity = match self.identity(reqwest, &tokens).await {
+            Ok(identity) => identity,
+            Err(error) => return error.into_response(),
+        };
+
+        self.login(login, tokens, identity).await
+    }
+}
+
+/// The providers that users can authorize with
+#[derive(Clone, Default)]
+pub struct OAuthProviders {
+    providers: HashMap<&'static str, Arc<dyn DynProvider>>,
+}
+
+impl OAuthProviders {
+    pub fn register<P: OAuthProvider>(mut self, provider: P) -> Self {
+        self.providers.insert(P::NAME, Arc::new(provider));
+
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn DynProvider> {
+        self.providers.get(name).map(Arc::as_ref)
+    }
+
+    /// The names of the registered providers, in alphabetical order
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = self.providers.keys().copied().collect::<Vec<_>>();
+        names.sort_unstable();
+
+        names
+    }
+}
+
+fn unknown_provider(provider: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        format!("unknown oauth provider {provider}"),
+    )
+        .into_response()
+}
+
+pub async fn authorize(
+    Path(provider): Path<String>,
+    jar: SignedCookieJar,
+    Extension(states): Extension<OAuthStates>,
+    Extension(providers): Extension<OAuthProviders>,
+) -> Response {
+    let provider = match providers.get(&provider) {
+        Some(provider) => provider,
+        None => return unknown_provider(&provider),
+    };
+
+    let (state, cookie) = states.issue(provider.name());
+
+    (
+        jar.add(cookie),
+        Redirect::temporary(&provider.authorization_url(state)),
+    )
+        .into_response()
+}
+
+#[allow(clippy::too_many_arguments)] // axum extractors
+pub async fn redirect(
+    Path(provider): Path<String>,
+    Query(grant): Query<CodeGrantResponse>,
+    state_jar: SignedCookieJar,
+    jar: CookieJar,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    Extension(reqwest): Extension<reqwest::Client>,
+    Extension(states): Extension<OAuthStates>,
+    Extension(providers): Extension<OAuthProviders>,
+    Extension(sessions): Extension<SessionStorage>,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    let provider = match providers.get(&provider) {
+        Some(provider) => provider,
+        None => return unknown_provider(&provider),
+    };
+
+    let valid = states.validate(
+        state_jar.get(&state_cookie(provider.name())).as_ref(),
+        grant.state,
+    );
+
+    // States are single use, whatever the outcome
+    let state_jar = state_jar.remove(states.removal(provider.name()));
+
+    // TODO: html error pages
+    if !valid {
+        return (
+            StatusCode::BAD_REQUEST,
+            state_jar,
+            "invalid state, suspected request forgery. did you navigate back to this page?",
+        )
+            .into_response();
+    }
+
+    match grant.inner {
+        CodeGrantResponseInner::Failure { error } => (
+            StatusCode::UNAUTHORIZED,
+            state_jar,
+            format!(
+                "{} rejected authorization request: {error}",
+                provider.name()
+            ),
+        )
+            .into_response(),
+        CodeGrantResponseInner::Success { code } => {
+            let login = Login {
+                jar,
+                sessions,
+                storage,
+                user_agent: user_agent.map(|TypedHeader(agent)| agent.to_string()),
+            };
+
+            (state_jar, provider.complete(&reqwest, code, login).await).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::api::tests::{in_memory, redirect_with, test_router};
+
+    #[tokio::test]
+    async fn unknown_provider_is_not_found() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(String::new(), sessions, storage);
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/auth/myspace").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let state = State::random().to_string();
+        let response = redirect_with(&app, "myspace", &state, Vec::new()).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, Path, RequestParts},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use spotify_banger_model::{SessionInfo, SessionKey};
+
+use super::session_id;
+use crate::{
+    session::{Session, SessionStorage, SESSION_COOKIE},
+    storage::Storage,
+};
+
+/// The session the request was made with, turning away requests without one
+pub struct CurrentSession(pub Session);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for CurrentSession {
+    type Rejection = Response;
+
+    async fn from_request(request: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let jar = match CookieJar::from_request(request).await {
+            Ok(jar) => jar,
+            Err(infallible) => match infallible {},
+        };
+
+        let Extension(sessions) = Extension::<SessionStorage>::from_request(request)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let id = match session_id(&jar) {
+            Some(id) => id,
+            None => return Err((StatusCode::UNAUTHORIZED, "not logged in").into_response()),
+        };
+
+        match sessions.session(id) {
+            Ok(Some(session)) => Ok(Self(session)),
+            // Ended elsewhere, so the browser might as well forget about it
+            Ok(None) => Err((
+                StatusCode::UNAUTHORIZED,
+                jar.remove(Cookie::named(SESSION_COOKIE)),
+                "not logged in",
+            )
+                .into_response()),
+            Err(error) => Err(error.into_response()),
+        }
+    }
+}
+
+/// End the session the request was made with, if there is one
+pub async fn logout(jar: CookieJar, Extension(sessions): Extension<SessionStorage>) -> Response {
+    if let Some(id) = session_id(&jar) {
+        let ended = sessions.session(id).and_then(|session| match session {
+            Some(session) => sessions.end_session(session.user, session.key),
+            None => Ok(false),
+        });
+
+        if let Err(error) = ended {
+            return error.into_response();
+        }
+    }
+
+    (
+        StatusCode::NO_CONTENT,
+        jar.remove(Cookie::named(SESSION_COOKIE)),
+    )
+        .into_response()
+}
+
+/// The sessions of the logged in user, on every device they logged in on
+pub async fn list(
+    CurrentSession(session): CurrentSession,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    match storage.sessions(session.user) {
+        Ok(sessions) => Json(
+            sessions
+                .into_iter()
+                .map(|stored| SessionInfo {
+                    id: stored.key,
+                    created_at: stored.created_at,
+                    last_seen: stored.last_seen,
+                    user_agent: stored.user_agent,
+                    current: stored.key == session.key,
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+/// End one of the sessions of the logged in user, logging out whichever
+/// device it belongs to
+pub async fn revoke(
+    CurrentSession(session): CurrentSession,
+    Path(key): Path<SessionKey>,
+    jar: CookieJar,
+    Extension(sessions): Extension<SessionStorage>,
+) -> Response {
+    match sessions.end_session(session.user, key) {
+        Ok(true) if key == session.key => (
+            StatusCode::NO_CONTENT,
+            jar.remove(Cookie::named(SESSION_COOKIE)),
+        )
+            .into_response(),
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "unknown session").into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        api::tests::{in_memory, json_body, session_cookie, test_router},
+        clock::MockClock,
+        session::{SessionId, SESSION_TTL},
+        storage::Storage,
+        users::UserId,
+    };
+
+    fn login(sessions: &SessionStorage, user: UserId, user_agent: Option<&str>) -> SessionId {
+        sessions.create_session(user, None, user_agent).unwrap()
+    }
+
+    /// Send the request with the cookie of the session
+    async fn send(app: &Router, request: Request<()>, session: SessionId) -> Response {
+        let (mut parts, ()) = request.into_parts();
+        parts.headers.insert(
+            header::COOKIE,
+            format!("{SESSION_COOKIE}={session}").parse().unwrap(),
+        );
+
+        app.clone()
+            .oneshot(Request::from_parts(parts, Body::empty()))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn logout_ends_session() {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+        let session = login(&sessions, user, None);
+        let app = test_router(String::new(), sessions.clone(), storage);
+
+        let response = send(&app, Request::post("/logout").body(()).unwrap(), session).await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(session_cookie(&response).unwrap().value(), "");
+        assert!(sessions.session(session).unwrap().is_none());
+
+        let response = send(&app, Request::get("/bangers").body(()).unwrap(), session).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(session_cookie(&response).unwrap().value(), "");
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let storage = Storage::in_memory();
+        let clock = MockClock::new();
+        let sessions = SessionStorage::with_clock(storage.clone(), clock.clone());
+        let user = storage.spotify_user("spotify user").unwrap();
+        let session = login(&sessions, user, None);
+
+        clock.advance(SESSION_TTL - Duration::from_secs(60));
+        assert!(sessions.session(session).unwrap().is_some());
+
+        // Counted from when it was last used
+        clock.advance(Duration::from_secs(60));
+        assert!(sessions.session(session).unwrap().is_some());
+
+        clock.advance(SESSION_TTL);
+        assert!(sessions.session(session).unwrap().is_none());
+        assert!(storage.sessions(user).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sessions_of_user_are_listed() {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+        let other = storage.spotify_user("other spotify user").unwrap();
+
+        login(&sessions, user, Some("Phone"));
+        let laptop = login(&sessions, user, Some("Laptop"));
+        login(&sessions, other, Some("Stranger"));
+
+        let app = test_router(String::new(), sessions, storage);
+        let response = send(&app, Request::get("/sessions").body(()).unwrap(), laptop).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let listed = json_body(response).await;
+        let mut listed = listed
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|session| {
+                (
+                    session["user_agent"].as_str().unwrap(),
+                    session["current"].as_bool().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        listed.sort();
+
+        assert_eq!(listed, [("Laptop", true), ("Phone", false)]);
+    }
+
+    #[tokio::test]
+    async fn session_on_other_device_is_revoked() {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+        let other = storage.spotify_user("other spotify user").unwrap();
+
+        let phone = login(&sessions, user, None);
+        let laptop = login(&sessions, user, None);
+        let stranger = login(&sessions, other, None);
+
+        let phone_key = sessions.session(phone).unwrap().unwrap().key;
+        let stranger_key = sessions.session(stranger).unwrap().unwrap().key;
+
+        let app = test_router(String::new(), sessions.clone(), storage);
+        let revoke = |key: SessionKey| {
+            Request::delete(format!("/sessions/{}", key.0))
+                .body(())
+                .unwrap()
+        };
+
+        let response = send(&app, revoke(phone_key), laptop).await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(session_cookie(&response).is_none());
+        assert!(sessions.session(phone).unwrap().is_none());
+        assert!(sessions.session(laptop).unwrap().is_some());
+
+        let response = send(&app, revoke(stranger_key), laptop).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(sessions.session(stranger).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn sessions_require_session() {
+        let (sessions, storage) = in_memory();
+        let app = test_router(String::new(), sessions, storage);
+
+        let response = app
+            .oneshot(Request::get("/sessions").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,264 @@
+use async_trait::async_trait;
+use axum::response::{IntoResponse, Redirect, Response};
+use monostate::MustBe;
+use serde::Serialize;
+use spotify_banger_model::{Me, Playback};
+use spotify_banger_web_api::{Retry, Spotify, Tokio};
+
+use super::{
+    provider::{Login, OAuthProvider},
+    spotify_user, start_session, token_request, AccessTokenResponse, OAuthConfig, OAuthError,
+};
+use crate::session::TokenClient;
+
+pub const SPOTIFY_AUTH_URL: &str = "https://accounts.spotify.com/authorize";
+pub const SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+pub const SPOTIFY_SCOPE: &str =
+    "user-read-currently-playing user-read-playback-state user-modify-playback-state user-read-recently-played";
+
+#[derive(Debug, Serialize)]
+struct AccessTokenRequest<'s> {
+    grant_type: MustBe!("authorization_code"),
+    code: String,
+    redirect_uri: &'s str,
+}
+
+/// Gets requests to the Web API through, retrying them and keeping every
+/// access token under the rate limit
+///
+/// Shared by everything that talks to spotify, so that they all count towards
+/// the same limits
+pub type WebApi = Retry<reqwest::Client, Tokio>;
+
+/// Talk to spotify on behalf of the owner of the access token
+pub fn web_api(transport: &WebApi, config: &OAuthConfig, access_token: &str) -> Spotify<WebApi> {
+    Spotify::new(transport.clone(), access_token).with_base_url(&*config.spotify_api_url)
+}
+
+/// Find out who the access token belongs to
+pub async fn me(
+    transport: &WebApi,
+    config: &OAuthConfig,
+    access_token: &str,
+) -> Result<Me, OAuthError> {
+    web_api(transport, config, access_token)
+        .me()
+        .await
+        .map_err(OAuthError::Spotify)
+}
+
+/// What the user is currently listening to, if anything
+pub async fn playback(
+    transport: &WebApi,
+    config: &OAuthConfig,
+    access_token: &str,
+) -> Result<Option<Playback>, OAuthError> {
+    web_api(transport, config, access_token)
+        .playback()
+        .await
+        .map_err(OAuthError::Spotify)
+}
+
+/// Logs users in with their spotify account, keeping the tokens around so
+/// the client can use them
+pub struct SpotifyProvider {
+    pub config: OAuthConfig,
+    pub transport: WebApi,
+}
+
+#[async_trait]
+impl OAuthProvider for SpotifyProvider {
+    const NAME: &'static str = "spotify";
+
+    type TokenResponse = AccessTokenResponse;
+    type Identity = Me;
+
+    fn authorize_url(&self) -> &str {
+        SPOTIFY_AUTH_URL
+    }
+
+    fn token_url(&self) -> &str {
+        &self.config.spotify_token_url
+    }
+
+    fn scope(&self) -> &str {
+        SPOTIFY_SCOPE
+    }
+
+    fn client_id(&self) -> &str {
+        &self.config.spotify_client_id
+    }
+
+    fn client_secret(&self) -> &str {
+        &self.config.spotify_client_secret
+    }
+
+    fn redirect_uri(&self) -> &str {
+        &self.config.spotify_redirect_uri
+    }
+
+    fn show_dialog(&self) -> bool {
+        true
+    }
+
+    /// Spotify wants the client credentials in the authorization header
+    fn token_request(&self, reqwest: &reqwest::Client, code: String) -> reqwest::RequestBuilder {
+        token_request(
+            reqwest,
+            &self.config,
+            TokenClient::Confidential,
+            &AccessTokenRequest {
+                code,
+                grant_type: Default::default(),
+                redirect_uri: self.redirect_uri(),
+            },
+        )
+    }
+
+    async fn identity(
+        &self,
+        _: &reqwest::Client,
+        tokens: &AccessTokenResponse,
+    ) -> Result<Me, OAuthError> {
+        me(&self.transport, &self.config, &tokens.access_token).await
+    }
+
+    async fn login(
+        &self,
+        Login {
+            jar,
+            sessions,
+            storage,
+            user_agent,
+        }: Login,
+        tokens: AccessTokenResponse,
+        identity: Me,
+    ) -> Response {
+        let tokens = tokens.into_tokens(TokenClient::Confidential);
+
+        let user = match spotify_user(&storage, &self.config, &identity.id, &tokens) {
+            Ok(user) => user,
+            Err(error) => return error.into_response(),
+        };
+
+        match start_session(jar, &sessions, user, Some(tokens), user_agent.as_deref()) {
+            Ok(jar) => (jar, Redirect::to(&self.config.origin)).into_response(),
+            Err(error) => error.into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use axum::{
+        http::{header, HeaderMap, HeaderValue, StatusCode},
+        routing::get,
+        Router,
+    };
+    use spotify_banger_web_api::{Error, RetryMetrics, RetryPolicy};
+
+    use super::*;
+    use crate::api::tests::{serve, test_config};
+
+    type Script = Arc<Mutex<VecDeque<(StatusCode, Option<&'static str>)>>>;
+
+    /// A stand-in for spotify that answers polls of the playback state with
+    /// the scripted statuses and `Retry-After` headers, then with No Content
+    async fn scripted(
+        responses: impl IntoIterator<Item = (StatusCode, Option<&'static str>)>,
+    ) -> (OAuthConfig, Script) {
+        let script = Script::new(Mutex::new(responses.into_iter().collect()));
+        let player = script.clone();
+
+        let url = serve(Router::new().route(
+            "/v1/me/player",
+            get(move || async move {
+                let (status, retry_after) = player
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or((StatusCode::NO_CONTENT, None));
+
+                let mut headers = HeaderMap::new();
+                if let Some(retry_after) = retry_after {
+                    headers.insert(header::RETRY_AFTER, HeaderValue::from_static(retry_after));
+                }
+
+                (status, headers)
+            }),
+        ))
+        .await;
+
+        (test_config(&url), script)
+    }
+
+    fn transport() -> WebApi {
+        WebApi::new(reqwest::Client::new(), Tokio).with_policy(RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn rate_limits_and_outages_are_retried() {
+        let (config, script) = scripted([
+            (StatusCode::TOO_MANY_REQUESTS, Some("1")),
+            (StatusCode::SERVICE_UNAVAILABLE, None),
+            (StatusCode::SERVICE_UNAVAILABLE, Some("0")),
+        ])
+        .await;
+        let transport = transport();
+
+        let started_at = Instant::now();
+        let playback = playback(&transport, &config, "access").await;
+
+        assert!(matches!(playback, Ok(None)));
+        assert!(started_at.elapsed() >= Duration::from_secs(1));
+        assert!(script.lock().unwrap().is_empty());
+        assert_eq!(
+            transport.metrics(),
+            RetryMetrics {
+                sent: 4,
+                retries: 3,
+                rate_limited: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn outages_are_passed_on_once_retries_run_out() {
+        let (config, script) = scripted([(StatusCode::SERVICE_UNAVAILABLE, None); 5]).await;
+        let transport = transport();
+
+        let playback = playback(&transport, &config, "access").await;
+
+        assert!(matches!(
+            playback,
+            Err(OAuthError::Spotify(Error::Api(ref error))) if error.status == 503
+        ));
+        assert_eq!(script.lock().unwrap().len(), 1);
+        assert_eq!(transport.metrics().gave_up, 1);
+    }
+
+    #[tokio::test]
+    async fn long_rate_limits_are_left_to_the_caller() {
+        let (config, _) = scripted([(StatusCode::TOO_MANY_REQUESTS, Some("120"))]).await;
+        let transport = transport();
+
+        let playback = playback(&transport, &config, "access").await;
+
+        assert!(matches!(
+            playback,
+            Err(OAuthError::Spotify(Error::RateLimited(Some(retry_after))))
+                if retry_after == Duration::from_secs(120)
+        ));
+        assert_eq!(transport.metrics().retries, 0);
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::SystemTime;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use spotify_banger_model::{BangerSource, StatsQuery, TrackDrops};
+
+use super::sessions::CurrentSession;
+use crate::{
+    bangers::Listing,
+    drops,
+    stats::{self, MAX_UTC_OFFSET},
+    storage::Storage,
+};
+
+/// How often and when the tracks and artists of the logged in user hit
+pub async fn stats(
+    CurrentSession(session): CurrentSession,
+    Query(query): Query<StatsQuery>,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    let utc_offset = query.utc_offset.unwrap_or_default();
+
+    if utc_offset.abs() > MAX_UTC_OFFSET {
+        return (StatusCode::BAD_REQUEST, "utc offset is out of range").into_response();
+    }
+
+    let bangers = match storage.bangers(session.user, &Listing::everything()) {
+        Ok(bangers) => bangers,
+        Err(error) => return error.into_response(),
+    };
+
+    let bangers = bangers
+        .into_iter()
+        .map(|listed| listed.banger)
+        .collect::<Vec<_>>();
+
+    Json(stats::stats(&bangers, utc_offset, SystemTime::now())).into_response()
+}
+
+/// Where in the track the logged in user keeps marking bangers
+pub async fn drops(
+    CurrentSession(session): CurrentSession,
+    Path(track_id): Path<String>,
+    Extension(storage): Extension<Storage>,
+) -> Response {
+    // Detected bangers are where playback was when the rules fired, which
+    // says nothing about where the drop is
+    let listing = Listing {
+        track: Some(track_id.clone()),
+        source: Some(BangerSource::Manual),
+        ..Listing::everything()
+    };
+
+    match storage.bangers(session.user, &listing) {
+        Ok(bangers) => Json(TrackDrops {
+            track_id,
+            drops: drops::drops(bangers.into_iter().map(|listed| listed.banger.progress_ms)),
+        })
+        .into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request},
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        api::tests::{in_memory, json_body, test_router},
+        bangers::Mark,
+        session::SESSION_COOKIE,
+    };
+
+    fn get(uri: &str, cookie: &str) -> Request<Body> {
+        Request::get(uri)
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stats_count_bangers_of_user() {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+        let other = storage.spotify_user("other spotify user").unwrap();
+
+        storage
+            .record_banger(user, Mark::manual("track", "artist", 1))
+            .unwrap();
+        storage
+            .record_banger(user, Mark::manual("track", "artist", 2))
+            .unwrap();
+        storage
+            .record_banger(other, Mark::manual("track", "artist", 3))
+            .unwrap();
+
+        let session = sessions.create_session(user, None, None).unwrap();
+        let cookie = format!("{SESSION_COOKIE}={session}");
+        let app = test_router(String::new(), sessions, storage);
+
+        let response = app
+            .clone()
+            .oneshot(get("/stats?utc_offset=-300", &cookie))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let stats = json_body(response).await;
+        assert_eq!(stats["tracks"][0]["track_id"], "track");
+        assert_eq!(stats["tracks"][0]["hits"]["count"], 2);
+        assert_eq!(stats["artists"][0]["hits"]["last_hit"], 2);
+
+        let response = app
+            .oneshot(get("/stats?utc_offset=1000", &cookie))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn drops_of_track_are_found() {
+        let (sessions, storage) = in_memory();
+        let user = storage.spotify_user("spotify user").unwrap();
+
+        for progress_ms in [96_000, 97_000, 212_000] {
+            storage
+                .record_banger(
+                    user,
+                    Mark {
+                        progress_ms,
+                        ..Mark::manual("track", "artist", progress_ms)
+                    },
+                )
+                .unwrap();
+        }
+        storage
+            .record_banger(
+                user,
+                Mark {
+                    progress_ms: 96_500,
+                    ..Mark::manual("other", "artist", 96_500)
+                },
+            )
+            .unwrap();
+        // Replayed over and over, which the detector marks at the start
+        for marked_at in [300_000, 600_000, 900_000] {
+            let replayed = Mark {
+                progress_ms: 3_000,
+                source: BangerSource::Auto,
+                confidence: Some(70),
+                ..Mark::manual("track", "artist", marked_at)
+            };
+
+            storage.record_banger(user, replayed).unwrap();
+        }
+
+        let session = sessions.create_session(user, None, None).unwrap();
+        let cookie = format!("{SESSION_COOKIE}={session}");
+        let app = test_router(String::new(), sessions, storage);
+
+        let response = app
+            .oneshot(get("/tracks/track/drops", &cookie))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await,
+            serde_json::json!({
+                "track_id": "track",
+                "drops": [{ "progress_ms": 96000, "hits": 2 }]
+            })
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use base64::display::Base64Display;
+use spotify_banger_model::{Banger, BangerId, BangerQuery, BangerSort, BangerSource};
+
+/// How many bangers to list when the query does not say
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Everything about a banger except for its id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mark {
+    pub track_id: String,
+    pub album_id: Option<String>,
+    pub artist_ids: Vec<String>,
+    pub marked_at: u64,
+    pub progress_ms: u64,
+    pub device: Option<String>,
+    pub context: Option<String>,
+    pub note: Option<String>,
+    pub source: BangerSource,
+    pub confidence: Option<u8>,
+}
+
+/// A banger as it comes out of a listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listed {
+    pub banger: Banger,
+    /// How often the track was marked among the bangers being listed
+    pub times: u64,
+}
+
+/// The last banger of a page, so that the next page starts right after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub sort: BangerSort,
+    pub times: u64,
+    pub marked_at: u64,
+    pub id: BangerId,
+}
+
+impl Cursor {
+    pub fn after(sort: BangerSort, listed: &Listed) -> Self {
+        Self {
+            sort,
+            times: listed.times,
+            marked_at: listed.banger.marked_at,
+            id: listed.banger.id,
+        }
+    }
+}
+
+impl Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sort = match self.sort {
+            BangerSort::Recent => 'r',
+            BangerSort::Count => 'c',
+        };
+        let cursor = format!("{sort}.{}.{}.{}", self.times, self.marked_at, self.id.0);
+
+        Base64Display::with_config(cursor.as_bytes(), base64::URL_SAFE_NO_PAD).fmt(f)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = ();
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let cursor = base64::decode_config(str, base64::URL_SAFE_NO_PAD).map_err(|_| ())?;
+        let cursor = String::from_utf8(cursor).map_err(|_| ())?;
+
+        match cursor.split('.').collect::<Vec<_>>()[..] {
+            [sort, times, marked_at, id] => Ok(Self {
+                sort: match sort {
+                    "r" => BangerSort::Recent,
+                    "c" => BangerSort::Count,
+                    _ => return Err(()),
+                },
+                times: times.parse().map_err(|_| ())?,
+                marked_at: marked_at.parse().map_err(|_| ())?,
+                id: BangerId(id.parse().map_err(|_| ())?),
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Which bangers to list and where to start, checked and filled in from a [`BangerQuery`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub track: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub context: Option<String>,
+    pub source: Option<BangerSource>,
+    pub sort: BangerSort,
+    pub after: Option<Cursor>,
+    pub limit: u32,
+}
+
+impl Listing {
+    pub fn new(query: BangerQuery) -> Result<Self, &'static str> {
+        let after = match query.cursor {
+            Some(cursor) => match cursor.parse::<Cursor>() {
+                Ok(cursor) if cursor.sort == query.sort => Some(cursor),
+                Ok(_) => return Err("cursor belongs to a listing in another order"),
+                Err(()) => return Err("invalid cursor"),
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            from: query.from,
+            to: query.to,
+            track: query.track,
+            artist: query.artist,
+            album: query.album,
+            context: query.context,
+            source: query.source,
+            sort: query.sort,
+            after,
+            limit: query
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+
+    /// Every banger, most recently marked first
+    pub fn everything() -> Self {
+        Self {
+            from: None,
+            to: None,
+            track: None,
+            artist: None,
+            album: None,
+            context: None,
+            source: None,
+            sort: BangerSort::Recent,
+            after: None,
+            limit: u32::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Mark {
+    /// A banger marked by hand, at the start of the track
+    pub(crate) fn manual(track_id: &str, artist_id: &str, marked_at: u64) -> Self {
+        Self {
+            track_id: track_id.into(),
+            album_id: Some(format!("{track_id} album")),
+            artist_ids: vec![artist_id.into()],
+            marked_at,
+            progress_ms: 0,
+            device: None,
+            context: None,
+            note: None,
+            source: BangerSource::Manual,
+            confidence: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn listing(query: BangerQuery) -> Listing {
+        Listing::new(query).unwrap()
+    }
+
+    fn track_ids(listed: Vec<Listed>) -> Vec<String> {
+        listed
+            .into_iter()
+            .map(|listed| listed.banger.track_id)
+            .collect()
+    }
+
+    #[test]
+    fn bangers_are_kept_per_user() {
+        let storage = Storage::in_memory();
+        let user = storage.spotify_user("user").unwrap();
+        let other = storage.spotify_user("other").unwrap();
+
+        let first = storage
+            .record_banger(user, Mark::manual("first", "a", 1))
+            .unwrap();
+        let second = storage
+            .record_banger(user, Mark::manual("second", "b", 2))
+            .unwrap();
+        storage
+            .record_banger(other, Mark::manual("other", "a", 3))
+            .unwrap();
+
+        let listed = storage
+            .bangers(user, &listing(BangerQuery::default()))
+            .unwrap()
+            .into_iter()
+            .map(|listed| listed.banger)
+            .collect::<Vec<_>>();
+
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.artist_ids, ["a"]);
+        assert_eq!(listed, vec![second, first]);
+        assert_eq!(
+            storage
+                .bangers(other, &listing(BangerQuery::default()))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn bangers_are_filtered() {
+        let storage = Storage::in_memory();
+        let user = storage.spotify_user("user").unwrap();
+
+        storage
+            .record_banger(user, Mark::manual("first", "a", 1))
+            .unwrap();
+        storage
+            .record_banger(user, Mark::manual("second", "b", 2))
+            .unwrap();
+        storage
+            .record_banger(
+                user,
+                Mark {
+                    context: Some("spotify:playlist:party".into()),
+                    ..Mark::manual("third", "a", 3)
+                },
+            )
+            .unwrap();
+
+        let list = |query| track_ids(storage.bangers(user, &listing(query)).unwrap());
+
+        assert_eq!(
+            list(BangerQuery {
+                from: Some(2),
+                to: Some(3),
+                ..Default::default()
+            }),
+            ["second"]
+        );
+        assert_eq!(
+            list(BangerQuery {
+                artist: Some("a".into()),
+                ..Default::default()
+            }),
+            ["third", "first"]
+        );
+        assert_eq!(
+            list(BangerQuery {
+                album: Some("second album".into()),
+                ..Default::default()
+            }),
+            ["second"]
+        );
+        assert_eq!(
+            list(BangerQuery {
+                context: Some("spotify:playlist:party".into()),
+                ..Default::default()
+            }),
+            ["third"]
+        );
+    }
+
+    #[test]
+    fn pages_continue_after_cursor() {
+        let storage = Storage::in_memory();
+        let user = storage.spotify_user("user").unwrap();
+
+        for (track_id, marked_at) in [("once", 1), ("twice", 2), ("twice", 3), ("thrice", 4)] {
+            storage
+                .record_banger(user, Mark::manual(track_id, "a", marked_at))
+                .unwrap();
+        }
+        storage
+            .record_banger(user, Mark::manual("thrice", "a", 5))
+            .unwrap();
+        storage
+            .record_banger(user, Mark::manual("thrice", "a", 0))
+            .unwrap();
+
+        for sort in [BangerSort::Recent, BangerSort::Count] {
+            let everything = track_ids(
+                storage
+                    .bangers(
+                        user,
+                        &listing(BangerQuery {
+                            sort,
+                            ..Default::default()
+                        }),
+                    )
+                    .unwrap(),
+            );
+
+            let mut paged = Vec::new();
+            let mut cursor = None;
+
+            loop {
+                let page = storage
+                    .bangers(
+                        user,
+                        &listing(BangerQuery {
+                            cursor: cursor.take(),
+                            limit: Some(2),
+                            sort,
+                            ..Default::default()
+                        }),
+                    )
+                    .unwrap();
+
+                cursor = page
+                    .last()
+                    .map(|last| Cursor::after(sort, last).to_string());
+                paged.extend(track_ids(page));
+
+                if cursor.is_none() {
+                    break;
+                }
+            }
+
+            assert_eq!(paged, everything);
+
+            if sort == BangerSort::Count {
+                assert_eq!(
+                    everything,
+                    ["thrice", "thrice", "thrice", "twice", "twice", "once"]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = Cursor {
+            sort: BangerSort::Count,
+            times: 3,
+            marked_at: 1657843200000,
+            id: BangerId(7),
+        };
+
+        assert_eq!(cursor.to_string().parse(), Ok(cursor));
+        assert_eq!("nonsense".parse::<Cursor>(), Err(()));
+    }
+
+    #[test]
+    fn cursor_must_match_sort() {
+        let cursor = Cursor {
+            sort: BangerSort::Recent,
+            times: 1,
+            marked_at: 0,
+            id: BangerId(1),
+        };
+
+        assert!(Listing::new(BangerQuery {
+            cursor: Some(cursor.to_string()),
+            sort: BangerSort::Count,
+            ..Default::default()
+        })
+        .is_err());
+        assert_eq!(
+            listing(BangerQuery {
+                limit: Some(1000),
+                ..Default::default()
+            })
+            .limit,
+            MAX_PAGE_SIZE
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::{fmt::Debug, time::SystemTime};
+
+/// A source of the current time, so that time dependent logic can be tested
+/// without sleeping
+pub trait Clock: Debug + Send + Sync + 'static {
+    fn now(&self) -> SystemTime;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(test)]
+pub use mock::MockClock;
+
+#[cfg(test)]
+mod mock {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use super::Clock;
+
+    /// A clock that only moves when told to
+    #[derive(Debug, Clone)]
+    pub struct MockClock {
+        now: Arc<Mutex<SystemTime>>,
+    }
+
+    impl MockClock {
+        pub fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(SystemTime::now())),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> SystemTime {
+            *self.now.lock().unwrap()
+        }
+    }
+}
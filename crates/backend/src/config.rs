@@ -0,0 +1,1033 @@
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Debug, Display},
+    fs, io,
+    net::SocketAddr,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum_extra::extract::cookie::Key;
+use reqwest::Url;
+use serde::{Deserialize, Serialize, Serializer};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    crypto::TokenCipher,
+    detect::{DetectorConfig, Repeat, Replay, SeekBack, SkipInto},
+};
+
+/// The configuration file that is read, unless `BANGER_CONFIG` points elsewhere
+const CONFIG_PATH: &str = "banger.toml";
+
+#[cfg(debug_assertions)]
+const DEFAULT_ORIGIN: Option<&str> = Some("http://127.0.0.1:8080/");
+/// Deployments have to say where they are served from
+#[cfg(not(debug_assertions))]
+const DEFAULT_ORIGIN: Option<&str> = None;
+
+const DEFAULT_BIND: &str = "127.0.0.1:9000";
+const DEFAULT_STATIC_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../client/dist");
+const DEFAULT_DATABASE_PATH: &str = "banger.sqlite";
+const DEFAULT_LOG_FILTER: &str =
+    "info,tower_http=debug,spotify_banger_backend=trace,spotify_banger_model=trace";
+/// Leaves a second of the five that fly gives between SIGINT and SIGKILL
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(4);
+
+const REDACTED: &str = "<redacted>";
+
+/// The configuration file, any key of which can be left out
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    origin: Option<String>,
+    bind: Option<String>,
+    static_dir: Option<String>,
+    database_path: Option<String>,
+    log_filter: Option<String>,
+    cors_origins: Option<Vec<String>>,
+    cookie_secret: Option<String>,
+    token_keys: Option<String>,
+    runtime: Option<String>,
+    worker_threads: Option<usize>,
+    shutdown_timeout: Option<u64>,
+    #[serde(default)]
+    spotify: ClientFile,
+    #[serde(default)]
+    github: ClientFile,
+    #[serde(default)]
+    auto_bangers: AutoBangersFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientFile {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AutoBangersFile {
+    enabled: Option<bool>,
+    min_confidence: Option<u8>,
+    window: Option<u64>,
+    cooldown: Option<u64>,
+    #[serde(default)]
+    replay: ReplayFile,
+    #[serde(default)]
+    seek_back: SeekBackFile,
+    #[serde(default)]
+    skip_into: SkipIntoFile,
+    #[serde(default)]
+    repeat: RepeatFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReplayFile {
+    within: Option<u64>,
+    start_ms: Option<u64>,
+    confidence: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SeekBackFile {
+    min_jump_ms: Option<u64>,
+    confidence: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkipIntoFile {
+    min_offset_ms: Option<u64>,
+    confidence: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RepeatFile {
+    confidence: Option<u8>,
+}
+
+impl File {
+    /// Read the configuration file, which only has to exist if it was asked for
+    fn read(path: &Path, required: bool) -> Result<Self, ConfigError> {
+        let file = match fs::read_to_string(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Self::default())
+            }
+            Err(error) => {
+                return Err(ConfigError::File {
+                    path: path.to_owned(),
+                    error: error.to_string(),
+                })
+            }
+        };
+
+        toml::from_str(&file).map_err(|error| ConfigError::File {
+            path: path.to_owned(),
+            error: error.to_string(),
+        })
+    }
+}
+
+/// Everything the backend is configured with
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    /// Where the client is served from, ending in a slash
+    pub origin: String,
+    pub bind: SocketAddr,
+    pub static_dir: PathBuf,
+    pub database_path: PathBuf,
+    pub log_filter: String,
+    /// The origins that browsers may call the api from
+    pub cors_origins: Vec<String>,
+    /// Signs the session cookies
+    pub cookie_secret: Secret<Key>,
+    /// Seals the refresh tokens that are kept in storage
+    pub token_keys: Secret<TokenCipher>,
+    pub runtime: Runtime,
+    /// Only ever set for the multi threaded runtime, which otherwise starts a
+    /// thread for every core
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker_threads: Option<usize>,
+    /// How long open connections and background workers get to wrap up
+    /// once the process is asked to stop, in seconds
+    #[serde(serialize_with = "serialize_secs")]
+    pub shutdown_timeout: Duration,
+    pub spotify: OAuthClient,
+    /// GitHub login is optional, as it only serves to recover accounts
+    pub github: Option<OAuthClient>,
+    pub auto_bangers: AutoBangers,
+}
+
+/// Picking out the bangers that users did not mark from how they listen
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AutoBangers {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub detector: DetectorConfig,
+}
+
+impl AutoBangers {
+    /// How to detect bangers, unless it is disabled
+    pub fn detector(&self) -> Option<DetectorConfig> {
+        self.enabled.then_some(self.detector)
+    }
+}
+
+/// Which tokio runtime to run on. Storage is queried right on the runtime,
+/// blocking whatever thread it happens on, so the multi threaded one keeps the
+/// rest going while a query is slow, at the cost of a thread for every core
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Runtime {
+    CurrentThread,
+    MultiThread,
+}
+
+pub(crate) fn serialize_secs<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthClient {
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+}
+
+/// A value that is left out whenever the configuration is shown
+#[derive(Clone)]
+pub struct Secret<T>(pub T);
+
+impl<T> Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// A value of the configuration that is missing or invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid {
+    /// The key in the configuration file
+    pub key: &'static str,
+    /// The environment variable that overrides the key
+    pub var: &'static str,
+    pub problem: String,
+}
+
+impl Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}) {}", self.key, self.var, self.problem)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    File { path: PathBuf, error: String },
+    /// Every value that is missing or invalid
+    Invalid(Vec<Invalid>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::File { path, error } => {
+                write!(f, "failed to read {}: {error}", path.display())
+            }
+            ConfigError::Invalid(invalid) => {
+                write!(f, "invalid configuration:")?;
+
+                for invalid in invalid {
+                    write!(f, "\n  {invalid}")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Picks values from the environment over those from the file, keeping track
+/// of everything that is wrong with them along the way
+struct Loader<E> {
+    env: E,
+    invalid: Vec<Invalid>,
+}
+
+impl<E: Fn(&str) -> Option<String>> Loader<E> {
+    fn value(&self, var: &str, file: Option<String>) -> Option<String> {
+        (self.env)(var).filter(|value| !value.is_empty()).or(file)
+    }
+
+    fn parse<T>(
+        &mut self,
+        key: &'static str,
+        var: &'static str,
+        value: Option<String>,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        match parse(&value?) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.invalid.push(Invalid {
+                    key,
+                    var,
+                    problem: format!("is invalid: {error}"),
+                });
+
+                None
+            }
+        }
+    }
+
+    fn required<T>(
+        &mut self,
+        key: &'static str,
+        var: &'static str,
+        value: Option<String>,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        if value.is_none() {
+            self.invalid(key, var, "is missing");
+        }
+
+        self.parse(key, var, value, parse)
+    }
+
+    /// Note a problem with a value that parsing it alone does not catch
+    fn invalid(&mut self, key: &'static str, var: &'static str, problem: &str) {
+        self.invalid.push(Invalid {
+            key,
+            var,
+            problem: problem.to_owned(),
+        });
+    }
+
+    /// A value that can be left out, parsed from the environment or the file
+    fn setting<T: ToString, U>(
+        &mut self,
+        key: &'static str,
+        var: &'static str,
+        file: Option<T>,
+        parse: impl FnOnce(&str) -> Result<U, String>,
+    ) -> Option<U> {
+        let value = self.value(var, file.map(|value| value.to_string()));
+
+        self.parse(key, var, value, parse)
+    }
+
+    fn client(&self, vars: [&str; 2], file: ClientFile) -> (Option<String>, Option<String>) {
+        (
+            self.value(vars[0], file.client_id),
+            self.value(vars[1], file.client_secret),
+        )
+    }
+}
+
+/// Make sure the origin is somewhere browsers can go, ending it with a slash
+fn parse_origin(origin: &str) -> Result<String, String> {
+    let url = parse_url(origin)?;
+
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err("has a query or fragment".to_owned());
+    }
+
+    let mut origin = url.to_string();
+    if !origin.ends_with('/') {
+        origin.push('/');
+    }
+
+    Ok(origin)
+}
+
+/// Reduce the url to the scheme, host and port that browsers send along as
+/// the origin of a request
+fn parse_cors_origin(origin: &str) -> Result<String, String> {
+    Ok(parse_url(origin)?.origin().ascii_serialization())
+}
+
+fn parse_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|error| error.to_string())?;
+
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err("is not an http or https url".to_owned());
+    }
+
+    Ok(url)
+}
+
+fn parse_runtime(runtime: &str) -> Result<Runtime, String> {
+    match runtime {
+        "current_thread" => Ok(Runtime::CurrentThread),
+        "multi_thread" => Ok(Runtime::MultiThread),
+        _ => Err("is neither current_thread nor multi_thread".to_owned()),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err("is neither true nor false".to_owned()),
+    }
+}
+
+fn parse_secs(secs: &str) -> Result<Duration, String> {
+    secs.parse()
+        .map(Duration::from_secs)
+        .map_err(|error| error.to_string())
+}
+
+fn parse_millis(millis: &str) -> Result<u64, String> {
+    millis
+        .parse()
+        .map_err(|error: ParseIntError| error.to_string())
+}
+
+/// A confidence from 0 to 1, written as a percentage
+fn parse_confidence(percent: &str) -> Result<f64, String> {
+    match percent.parse::<u8>() {
+        Ok(percent) if percent <= 100 => Ok(f64::from(percent) / 100.0),
+        Ok(_) => Err("is over 100".to_owned()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn parse_cookie_secret(secret: &str) -> Result<Key, String> {
+    let secret = base64::decode(secret).map_err(|error| format!("is not valid base64: {error}"))?;
+
+    Key::try_from(secret.as_slice()).map_err(|_| "is shorter than 64 bytes".to_owned())
+}
+
+impl Config {
+    /// Read the configuration file, if there is one, and let the environment
+    /// override it
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match env::var_os("BANGER_CONFIG") {
+            Some(path) => File::read(Path::new(&path), true)?,
+            None => File::read(Path::new(CONFIG_PATH), false)?,
+        };
+
+        Self::from_sources(file, |var| env::var(var).ok())
+    }
+
+    fn from_sources(file: File, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut loader = Loader {
+            env,
+            invalid: Vec::new(),
+        };
+
+        let origin = loader
+            .value("ORIGIN", file.origin)
+            .or_else(|| DEFAULT_ORIGIN.map(str::to_owned));
+        let origin = loader.required("origin", "ORIGIN", origin, parse_origin);
+
+        let bind = loader
+            .value("BIND", file.bind)
+            .unwrap_or_else(|| DEFAULT_BIND.to_owned());
+        let bind = loader.parse("bind", "BIND", Some(bind), |bind| {
+            bind.parse::<SocketAddr>()
+                .map_err(|error| error.to_string())
+        });
+
+        let static_dir = loader
+            .value("STATIC_FILES", file.static_dir)
+            .unwrap_or_else(|| DEFAULT_STATIC_DIR.to_owned());
+
+        let database_path = loader
+            .value("DATABASE_PATH", file.database_path)
+            .unwrap_or_else(|| DEFAULT_DATABASE_PATH.to_owned());
+
+        let log_filter = loader
+            .value("RUST_LOG", file.log_filter)
+            .unwrap_or_else(|| DEFAULT_LOG_FILTER.to_owned());
+        let log_filter = loader.parse("log_filter", "RUST_LOG", Some(log_filter), |filter| {
+            EnvFilter::try_new(filter)
+                .map(|_| filter.to_owned())
+                .map_err(|error| error.to_string())
+        });
+
+        let cors_origins = match (loader.env)("CORS_ORIGINS").filter(|value| !value.is_empty()) {
+            Some(origins) => Some(
+                origins
+                    .split(',')
+                    .map(str::trim)
+                    .map(str::to_owned)
+                    .collect(),
+            ),
+            None => file.cors_origins,
+        };
+        let cors_origins = match cors_origins {
+            Some(origins) => {
+                let parsed = origins
+                    .into_iter()
+                    .map(|origin| {
+                        loader.parse("cors_origins", "CORS_ORIGINS", Some(origin), |origin| {
+                            parse_cors_origin(origin).map_err(|error| format!("{origin:?} {error}"))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                parsed.into_iter().collect::<Option<Vec<_>>>()
+            }
+            // Only the client itself, unless told otherwise
+            None => origin
+                .as_deref()
+                .map(|origin| parse_cors_origin(origin).into_iter().collect()),
+        };
+
+        let cookie_secret = loader.value("COOKIE_SECRET", file.cookie_secret);
+        let cookie_secret = loader.required(
+            "cookie_secret",
+            "COOKIE_SECRET",
+            cookie_secret,
+            parse_cookie_secret,
+        );
+
+        let token_keys = loader.value("TOKEN_KEYS", file.token_keys);
+        let token_keys =
+            loader.required("token_keys", "TOKEN_KEYS", token_keys, TokenCipher::parse);
+
+        let runtime = loader
+            .value("RUNTIME", file.runtime)
+            .unwrap_or_else(|| "multi_thread".to_owned());
+        let runtime = loader.parse("runtime", "RUNTIME", Some(runtime), parse_runtime);
+
+        let worker_threads = loader.value(
+            "WORKER_THREADS",
+            file.worker_threads.map(|threads| threads.to_string()),
+        );
+        let worker_threads = loader.parse(
+            "worker_threads",
+            "WORKER_THREADS",
+            worker_threads,
+            |threads| match threads.parse::<usize>() {
+                Ok(0) => Err("is not at least one".to_owned()),
+                Ok(threads) => Ok(threads),
+                Err(error) => Err(error.to_string()),
+            },
+        );
+        if worker_threads.is_some() && runtime == Some(Runtime::CurrentThread) {
+            loader.invalid(
+                "worker_threads",
+                "WORKER_THREADS",
+                "is set, while the runtime is current_thread",
+            );
+        }
+
+        let shutdown_timeout = loader.value(
+            "SHUTDOWN_TIMEOUT",
+            file.shutdown_timeout.map(|secs| secs.to_string()),
+        );
+        let shutdown_timeout = loader
+            .parse(
+                "shutdown_timeout",
+                "SHUTDOWN_TIMEOUT",
+                shutdown_timeout,
+                parse_secs,
+            )
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+
+        let spotify =
+            match loader.client(["SPOTIFY_CLIENT_ID", "SPOTIFY_CLIENT_SECRET"], file.spotify) {
+                (Some(client_id), Some(client_secret)) => Some(OAuthClient {
+                    client_id,
+                    client_secret: Secret(client_secret),
+                }),
+                (client_id, client_secret) => {
+                    if client_id.is_none() {
+                        loader.invalid("spotify.client_id", "SPOTIFY_CLIENT_ID", "is missing");
+                    }
+                    if client_secret.is_none() {
+                        loader.invalid(
+                            "spotify.client_secret",
+                            "SPOTIFY_CLIENT_SECRET",
+                            "is missing",
+                        );
+                    }
+
+                    None
+                }
+            };
+
+        // Either both or neither, GitHub login is disabled without them
+        let github = match loader.client(["GITHUB_CLIENT_ID", "GITHUB_CLIENT_SECRET"], file.github)
+        {
+            (Some(client_id), Some(client_secret)) => Some(Some(OAuthClient {
+                client_id,
+                client_secret: Secret(client_secret),
+            })),
+            (None, None) => Some(None),
+            (None, Some(_)) => {
+                loader.invalid(
+                    "github.client_id",
+                    "GITHUB_CLIENT_ID",
+                    "is missing, while github.client_secret is set",
+                );
+
+                None
+            }
+            (Some(_), None) => {
+                loader.invalid(
+                    "github.client_secret",
+                    "GITHUB_CLIENT_SECRET",
+                    "is missing, while github.client_id is set",
+                );
+
+                None
+            }
+        };
+
+        let auto_bangers = Self::auto_bangers(&mut loader, file.auto_bangers);
+
+        match (
+            origin,
+            bind,
+            log_filter,
+            cors_origins,
+            cookie_secret,
+            token_keys,
+            runtime,
+            spotify,
+            github,
+        ) {
+            (
+                Some(origin),
+                Some(bind),
+                Some(log_filter),
+                Some(cors_origins),
+                Some(cookie_secret),
+                Some(token_keys),
+                Some(runtime),
+                Some(spotify),
+                Some(github),
+            ) if loader.invalid.is_empty() => Ok(Self {
+                origin,
+                bind,
+                static_dir: PathBuf::from(static_dir),
+                database_path: PathBuf::from(database_path),
+                log_filter,
+                cors_origins,
+                cookie_secret: Secret(cookie_secret),
+                token_keys: Secret(token_keys),
+                runtime,
+                worker_threads,
+                shutdown_timeout,
+                spotify,
+                github,
+                auto_bangers,
+            }),
+            _ => Err(ConfigError::Invalid(loader.invalid)),
+        }
+    }
+
+    /// Detection is optional, and off unless enabled
+    fn auto_bangers<E: Fn(&str) -> Option<String>>(
+        loader: &mut Loader<E>,
+        file: AutoBangersFile,
+    ) -> AutoBangers {
+        let defaults = DetectorConfig::default();
+
+        AutoBangers {
+            enabled: loader
+                .setting(
+                    "auto_bangers.enabled",
+                    "AUTO_BANGERS",
+                    file.enabled,
+                    parse_bool,
+                )
+                .unwrap_or(false),
+            detector: DetectorConfig {
+                window: loader
+                    .setting(
+                        "auto_bangers.window",
+                        "AUTO_BANGER_WINDOW",
+                        file.window,
+                        parse_secs,
+                    )
+                    .unwrap_or(defaults.window),
+                min_confidence: loader
+                    .setting(
+                        "auto_bangers.min_confidence",
+                        "AUTO_BANGER_MIN_CONFIDENCE",
+                        file.min_confidence,
+                        parse_confidence,
+                    )
+                    .unwrap_or(defaults.min_confidence),
+                cooldown: loader
+                    .setting(
+                        "auto_bangers.cooldown",
+                        "AUTO_BANGER_COOLDOWN",
+                        file.cooldown,
+                        parse_secs,
+                    )
+                    .unwrap_or(defaults.cooldown),
+                replay: Replay {
+                    within: loader
+                        .setting(
+                            "auto_bangers.replay.within",
+                            "AUTO_BANGER_REPLAY_WITHIN",
+                            file.replay.within,
+                            parse_secs,
+                        )
+                        .unwrap_or(defaults.replay.within),
+                    start_ms: loader
+                        .setting(
+                            "auto_bangers.replay.start_ms",
+                            "AUTO_BANGER_REPLAY_START_MS",
+                            file.replay.start_ms,
+                            parse_millis,
+                        )
+                        .unwrap_or(defaults.replay.start_ms),
+                    confidence: loader
+                        .setting(
+                            "auto_bangers.replay.confidence",
+                            "AUTO_BANGER_REPLAY_CONFIDENCE",
+                            file.replay.confidence,
+                            parse_confidence,
+                        )
+                        .unwrap_or(defaults.replay.confidence),
+                },
+                seek_back: SeekBack {
+                    min_jump_ms: loader
+                        .setting(
+                            "auto_bangers.seek_back.min_jump_ms",
+                            "AUTO_BANGER_SEEK_BACK_MIN_JUMP_MS",
+                            file.seek_back.min_jump_ms,
+                            parse_millis,
+                        )
+                        .unwrap_or(defaults.seek_back.min_jump_ms),
+                    confidence: loader
+                        .setting(
+                            "auto_bangers.seek_back.confidence",
+                            "AUTO_BANGER_SEEK_BACK_CONFIDENCE",
+                            file.seek_back.confidence,
+                            parse_confidence,
+                        )
+                        .unwrap_or(defaults.seek_back.confidence),
+                },
+                skip_into: SkipInto {
+                    min_offset_ms: loader
+                        .setting(
+                            "auto_bangers.skip_into.min_offset_ms",
+                            "AUTO_BANGER_SKIP_INTO_MIN_OFFSET_MS",
+                            file.skip_into.min_offset_ms,
+                            parse_millis,
+                        )
+                        .unwrap_or(defaults.skip_into.min_offset_ms),
+                    confidence: loader
+                        .setting(
+                            "auto_bangers.skip_into.confidence",
+                            "AUTO_BANGER_SKIP_INTO_CONFIDENCE",
+                            file.skip_into.confidence,
+                            parse_confidence,
+                        )
+                        .unwrap_or(defaults.skip_into.confidence),
+                },
+                repeat: Repeat {
+                    confidence: loader
+                        .setting(
+                            "auto_bangers.repeat.confidence",
+                            "AUTO_BANGER_REPEAT_CONFIDENCE",
+                            file.repeat.confidence,
+                            parse_confidence,
+                        )
+                        .unwrap_or(defaults.repeat.confidence),
+                },
+            },
+        }
+    }
+
+    /// The configuration as it would be written in the file, without secrets
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("configuration should be representable as toml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::crypto::tests::{KEY_1, KEY_2};
+
+    const COOKIE_SECRET: &str =
+        "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0+Pw==";
+
+    fn load(file: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let env = env
+            .iter()
+            .map(|&(var, value)| (var.to_owned(), value.to_owned()))
+            .collect::<HashMap<_, _>>();
+
+        Config::from_sources(toml::from_str(file).unwrap(), |var| env.get(var).cloned())
+    }
+
+    fn invalid(result: Result<Config, ConfigError>) -> Vec<(&'static str, String)> {
+        match result {
+            Err(ConfigError::Invalid(invalid)) => invalid
+                .into_iter()
+                .map(|invalid| (invalid.key, invalid.problem))
+                .collect(),
+            other => panic!("expected invalid configuration, got {other:?}"),
+        }
+    }
+
+    fn required_env() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("ORIGIN", "https://banger.example.com"),
+            ("COOKIE_SECRET", COOKIE_SECRET),
+            ("TOKEN_KEYS", KEY_1),
+            ("SPOTIFY_CLIENT_ID", "spotify id"),
+            ("SPOTIFY_CLIENT_SECRET", "spotify secret"),
+        ]
+    }
+
+    #[test]
+    fn environment_alone_is_enough() {
+        let config = load("", &required_env()).unwrap();
+
+        assert_eq!(config.origin, "https://banger.example.com/");
+        assert_eq!(config.cors_origins, ["https://banger.example.com"]);
+        assert_eq!(config.bind, SocketAddr::from(([127, 0, 0, 1], 9000)));
+        assert_eq!(config.database_path, Path::new(DEFAULT_DATABASE_PATH));
+        assert_eq!(config.log_filter, DEFAULT_LOG_FILTER);
+        assert_eq!(config.runtime, Runtime::MultiThread);
+        assert_eq!(config.shutdown_timeout, DEFAULT_SHUTDOWN_TIMEOUT);
+        assert_eq!(config.spotify.client_id, "spotify id");
+        assert!(config.github.is_none());
+    }
+
+    #[test]
+    fn runtime_is_configurable() {
+        let mut env = required_env();
+        env.push(("WORKER_THREADS", "2"));
+
+        let config = load(
+            r#"
+                worker_threads = 8
+                shutdown_timeout = 10
+            "#,
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(config.runtime, Runtime::MultiThread);
+        assert_eq!(config.worker_threads, Some(2));
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(10));
+
+        assert_eq!(
+            invalid(load(
+                "runtime = \"current_thread\"\nworker_threads = 2",
+                &required_env()
+            )),
+            [(
+                "worker_threads",
+                "is set, while the runtime is current_thread".to_owned()
+            )]
+        );
+
+        let mut env = required_env();
+        env.push(("RUNTIME", "green_threads"));
+        env.push(("WORKER_THREADS", "0"));
+
+        let keys = invalid(load("", &env))
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["runtime", "worker_threads"]);
+    }
+
+    #[test]
+    fn auto_bangers_are_configurable() {
+        let config = load("", &required_env()).unwrap();
+        assert!(!config.auto_bangers.enabled);
+        assert_eq!(config.auto_bangers.detector(), None);
+
+        let mut env = required_env();
+        env.push(("AUTO_BANGER_MIN_CONFIDENCE", "80"));
+
+        let config = load(
+            r#"
+                [auto_bangers]
+                enabled = true
+                min_confidence = 60
+                window = 600
+
+                [auto_bangers.seek_back]
+                min_jump_ms = 8000
+                confidence = 40
+            "#,
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.auto_bangers.detector(),
+            Some(DetectorConfig {
+                window: Duration::from_secs(600),
+                min_confidence: 0.8,
+                seek_back: SeekBack {
+                    min_jump_ms: 8_000,
+                    confidence: 0.4,
+                },
+                ..DetectorConfig::default()
+            })
+        );
+
+        let mut env = required_env();
+        env.push(("AUTO_BANGERS", "yes"));
+        env.push(("AUTO_BANGER_MIN_CONFIDENCE", "150"));
+        env.push(("AUTO_BANGER_COOLDOWN", "soon"));
+        env.push(("AUTO_BANGER_REPEAT_CONFIDENCE", "-1"));
+
+        let keys = invalid(load("", &env))
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "auto_bangers.enabled",
+                "auto_bangers.min_confidence",
+                "auto_bangers.cooldown",
+                "auto_bangers.repeat.confidence",
+            ]
+        );
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let mut env = required_env();
+        env.push(("BIND", "0.0.0.0:8080"));
+        env.push((
+            "CORS_ORIGINS",
+            "https://a.example.com, http://localhost:8080/",
+        ));
+
+        let config = load(
+            r#"
+                origin = "https://file.example.com/banger/"
+                bind = "127.0.0.1:1234"
+                database_path = "/data/banger.sqlite"
+
+                [github]
+                client_id = "github id"
+                client_secret = "github secret"
+            "#,
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(config.origin, "https://banger.example.com/");
+        assert_eq!(config.bind, SocketAddr::from(([0, 0, 0, 0], 8080)));
+        assert_eq!(config.database_path, Path::new("/data/banger.sqlite"));
+        assert_eq!(
+            config.cors_origins,
+            ["https://a.example.com", "http://localhost:8080"]
+        );
+        assert_eq!(config.github.unwrap().client_id, "github id");
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let invalid = invalid(load(
+            r#"
+                origin = "ftp://banger.example.com"
+                bind = "everywhere"
+                cors_origins = ["nowhere"]
+                cookie_secret = "c2hvcnQ="
+                token_keys = "1:AAEC"
+
+                [github]
+                client_secret = "github secret"
+            "#,
+            &[],
+        ));
+
+        let keys = invalid.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "origin",
+                "bind",
+                "cors_origins",
+                "cookie_secret",
+                "token_keys",
+                "spotify.client_id",
+                "spotify.client_secret",
+                "github.client_id",
+            ]
+        );
+        assert_eq!(
+            invalid[3].1,
+            "is invalid: is shorter than 64 bytes".to_owned()
+        );
+    }
+
+    #[test]
+    fn missing_values_are_named_with_their_variables() {
+        let error = load("", &[("ORIGIN", "https://banger.example.com")]).unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.contains("cookie_secret (COOKIE_SECRET) is missing"));
+        assert!(message.contains("spotify.client_secret (SPOTIFY_CLIENT_SECRET) is missing"));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<File>("orign = \"https://banger.example.com\"").is_err());
+    }
+
+    #[test]
+    fn printed_config_has_no_secrets() {
+        let mut env = required_env();
+        env.push(("TOKEN_KEYS", KEY_2));
+        env.push(("GITHUB_CLIENT_ID", "github id"));
+        env.push(("GITHUB_CLIENT_SECRET", "github secret"));
+
+        let printed = load("", &env).unwrap().to_toml();
+
+        assert!(printed.contains("origin = \"https://banger.example.com/\""));
+        assert!(printed.contains("client_id = \"github id\""));
+        assert!(printed.contains("[auto_bangers]\nenabled = false"));
+        assert!(printed
+            .contains("[auto_bangers.replay]\nwithin = 600\nstart_ms = 10000\nconfidence = 70"));
+        assert!(!printed.contains("spotify secret"));
+        assert!(!printed.contains("github secret"));
+        assert!(!printed.contains(COOKIE_SECRET));
+        assert!(!printed.contains(&KEY_2[2..]));
+        assert_eq!(printed.matches(REDACTED).count(), 4);
+    }
+
+    #[test]
+    fn example_is_valid() {
+        load(
+            include_str!("../../../banger.example.toml"),
+            &[
+                ("COOKIE_SECRET", COOKIE_SECRET),
+                ("TOKEN_KEYS", KEY_1),
+                ("SPOTIFY_CLIENT_SECRET", "spotify secret"),
+            ],
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    sync::Arc,
+};
+
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    KeyInit, XChaCha20Poly1305, XNonce,
+};
+use rand::Rng;
+
+use crate::users::UserId;
+
+const NONCE_LEN: usize = 24;
+
+/// Identifies the key a token was sealed with, so that keys can be rotated
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct KeyId(pub u32);
+
+impl Display for KeyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// A token that can only be read with the key it was sealed with
+#[derive(Clone, PartialEq, Eq)]
+pub struct Sealed {
+    pub key_id: KeyId,
+    /// The nonce followed by the ciphertext
+    pub ciphertext: Vec<u8>,
+}
+
+impl Debug for Sealed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sealed")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenError {
+    /// The key has been rotated out, or was never known to begin with
+    UnknownKey(KeyId),
+    /// The ciphertext was tampered with, or belongs to another user
+    Corrupt,
+}
+
+impl Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::UnknownKey(key_id) => write!(f, "sealed with unknown key {key_id}"),
+            OpenError::Corrupt => write!(f, "failed to authenticate sealed token"),
+        }
+    }
+}
+
+/// Seals refresh tokens at rest with XChaCha20-Poly1305, binding each one to
+/// the user it belongs to.
+///
+/// New tokens are sealed with the active key, the others are kept around to
+/// open tokens sealed before a rotation.
+#[derive(Clone)]
+pub struct TokenCipher {
+    active: KeyId,
+    keys: Arc<HashMap<KeyId, XChaCha20Poly1305>>,
+}
+
+impl Debug for TokenCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCipher")
+            .field("active", &self.active)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl TokenCipher {
+    /// Parse keys formatted as `id:base64`, separated by commas, the first of
+    /// which becomes the active key
+    pub fn parse(keys: &str) -> Result<Self, String> {
+        let keys = keys
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                let (id, key) = key
+                    .split_once(':')
+                    .ok_or_else(|| "key is not formatted as id:base64".to_owned())?;
+
+                let id = id
+                    .parse()
+                    .map(KeyId)
+                    .map_err(|error| format!("key id {id:?} is invalid: {error}"))?;
+
+                let key = base64::decode(key)
+                    .map_err(|error| format!("key {id} is not valid base64: {error}"))?;
+
+                let cipher = XChaCha20Poly1305::new_from_slice(&key)
+                    .map_err(|_| format!("key {id} is not 32 bytes long"))?;
+
+                Ok((id, cipher))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let active = match keys.first() {
+            Some(&(id, _)) => id,
+            None => return Err("no keys given".to_owned()),
+        };
+
+        let count = keys.len();
+        let keys = keys.into_iter().collect::<HashMap<_, _>>();
+
+        if keys.len() != count {
+            return Err("key ids are not unique".to_owned());
+        }
+
+        Ok(Self {
+            active,
+            keys: Arc::new(keys),
+        })
+    }
+
+    /// A cipher with a single random key
+    #[cfg(test)]
+    pub fn random(id: KeyId) -> Self {
+        let key = XChaCha20Poly1305::generate_key(&mut chacha20poly1305::aead::OsRng);
+
+        Self {
+            active: id,
+            keys: Arc::new(HashMap::from([(id, XChaCha20Poly1305::new(&key))])),
+        }
+    }
+
+    pub fn active(&self) -> KeyId {
+        self.active
+    }
+
+    pub fn seal(&self, user: UserId, token: &str) -> Sealed {
+        let mut nonce = [0_u8; NONCE_LEN];
+        rand::thread_rng().fill(&mut nonce);
+
+        let ciphertext = self.keys[&self.active]
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: token.as_bytes(),
+                    aad: &user.0.to_le_bytes(),
+                },
+            )
+            .expect("tokens are far too small to fail encryption");
+
+        Sealed {
+            key_id: self.active,
+            ciphertext: [&nonce[..], &ciphertext].concat(),
+        }
+    }
+
+    pub fn open(&self, user: UserId, sealed: &Sealed) -> Result<String, OpenError> {
+        let cipher = self
+            .keys
+            .get(&sealed.key_id)
+            .ok_or(OpenError::UnknownKey(sealed.key_id))?;
+
+        if sealed.ciphertext.len() < NONCE_LEN {
+            return Err(OpenError::Corrupt);
+        }
+
+        let (nonce, ciphertext) = sealed.ciphertext.split_at(NONCE_LEN);
+
+        let token = cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &user.0.to_le_bytes(),
+                },
+            )
+            .map_err(|_| OpenError::Corrupt)?;
+
+        String::from_utf8(token).map_err(|_| OpenError::Corrupt)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub const KEY_1: &str = "1:AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    pub const KEY_2: &str = "2:ICEiIyQlJicoKSorLC0uLzAxMjM0NTY3ODk6Ozw9Pj8=";
+
+    #[test]
+    fn sealed_token_opens() {
+        let cipher = TokenCipher::random(KeyId(1));
+
+        let sealed = cipher.seal(UserId(1), "refresh");
+
+        assert_eq!(sealed.key_id, KeyId(1));
+        assert!(!sealed
+            .ciphertext
+            .windows(7)
+            .any(|window| window == b"refresh"));
+        assert_eq!(cipher.open(UserId(1), &sealed).unwrap(), "refresh");
+    }
+
+    #[test]
+    fn token_is_bound_to_user() {
+        let cipher = TokenCipher::random(KeyId(1));
+
+        let sealed = cipher.seal(UserId(1), "refresh");
+
+        assert_eq!(cipher.open(UserId(2), &sealed), Err(OpenError::Corrupt));
+    }
+
+    #[test]
+    fn tampered_token_is_corrupt() {
+        let cipher = TokenCipher::random(KeyId(1));
+
+        let mut sealed = cipher.seal(UserId(1), "refresh");
+        *sealed.ciphertext.last_mut().unwrap() ^= 1;
+
+        assert_eq!(cipher.open(UserId(1), &sealed), Err(OpenError::Corrupt));
+
+        sealed.ciphertext.truncate(3);
+        assert_eq!(cipher.open(UserId(1), &sealed), Err(OpenError::Corrupt));
+    }
+
+    #[test]
+    fn rotated_key_still_opens_old_tokens() {
+        let old = TokenCipher::parse(KEY_1).unwrap();
+        let rotated = TokenCipher::parse(&format!("{KEY_2},{KEY_1}")).unwrap();
+
+        let sealed = old.seal(UserId(1), "refresh");
+
+        assert_eq!(rotated.active(), KeyId(2));
+        assert_eq!(rotated.open(UserId(1), &sealed).unwrap(), "refresh");
+        assert_eq!(rotated.seal(UserId(1), "refresh").key_id, KeyId(2));
+
+        assert_eq!(
+            TokenCipher::parse(KEY_2).unwrap().open(UserId(1), &sealed),
+            Err(OpenError::UnknownKey(KeyId(1)))
+        );
+    }
+
+    #[test]
+    fn invalid_keys_are_refused() {
+        assert!(TokenCipher::parse("").is_err());
+        assert!(TokenCipher::parse("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").is_err());
+        assert!(TokenCipher::parse("1:AAEC").is_err());
+        assert!(TokenCipher::parse(&format!("{KEY_1},{KEY_1}")).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Serialize, Serializer};
+use spotify_banger_model::{BangerSource, Playback, PlayingItem, RepeatState};
+use tracing::debug;
+
+use crate::{bangers::Mark, config::serialize_secs, users::UserId};
+
+pub use self::rules::{Repeat, Replay, SeekBack, SkipInto};
+
+mod rules;
+
+/// What a user was listening to at some point, as far as the rules care
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    /// Unix millisecond timestamp of when playback was observed
+    pub at: u64,
+    pub track_id: String,
+    pub album_id: Option<String>,
+    pub artist_ids: Vec<String>,
+    pub progress_ms: u64,
+    pub is_playing: bool,
+    pub repeat: RepeatState,
+    pub device: Option<String>,
+    pub context: Option<String>,
+}
+
+impl Observation {
+    /// Only tracks can be bangers, so anything else is not worth observing
+    pub fn new(playback: Playback, at: u64) -> Option<Self> {
+        let track = match playback.playing.item? {
+            PlayingItem::Track(track) => track,
+            PlayingItem::Episode(_) => return None,
+        };
+
+        Some(Self {
+            at,
+            track_id: track.id?,
+            album_id: track.album.id,
+            artist_ids: track
+                .artists
+                .into_iter()
+                .filter_map(|artist| artist.id)
+                .collect(),
+            progress_ms: playback.playing.progress_ms.unwrap_or_default(),
+            is_playing: playback.playing.is_playing,
+            repeat: playback.repeat_state,
+            device: Some(playback.device.name),
+            context: playback.playing.context.map(|context| context.uri),
+        })
+    }
+
+    /// Where playback would be at `at` if nobody touched it since
+    fn progress_at(&self, at: u64) -> u64 {
+        if self.is_playing {
+            self.progress_ms + at.saturating_sub(self.at)
+        } else {
+            self.progress_ms
+        }
+    }
+}
+
+/// A heuristic that recognises a banger in the way a track is listened to
+pub trait Rule: Debug + Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// How sure the rule is, from 0 to 1, that the latest observation shows
+    /// the user treating its track as a banger
+    ///
+    /// The history is oldest first and ends with the latest observation
+    fn judge(&self, history: &[Observation]) -> Option<f64>;
+}
+
+/// Confidences are written as percentages wherever people configure them
+fn serialize_percent<S: Serializer>(confidence: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u8((confidence * 100.0).round() as u8)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DetectorConfig {
+    /// How far back the rules get to look
+    #[serde(serialize_with = "serialize_secs")]
+    pub window: Duration,
+    /// Detections the rules are less sure of are dropped
+    #[serde(serialize_with = "serialize_percent")]
+    pub min_confidence: f64,
+    /// How long to hold off on detecting the same track again
+    #[serde(serialize_with = "serialize_secs")]
+    pub cooldown: Duration,
+    pub replay: Replay,
+    pub seek_back: SeekBack,
+    pub skip_into: SkipInto,
+    pub repeat: Repeat,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(15 * 60),
+            min_confidence: 0.5,
+            cooldown: Duration::from_secs(10 * 60),
+            replay: Replay::default(),
+            seek_back: SeekBack::default(),
+            skip_into: SkipInto::default(),
+            repeat: Repeat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Listening {
+    history: VecDeque<Observation>,
+    /// The last track that was detected, and when
+    detected: Option<(String, u64)>,
+}
+
+/// Watches what users listen to and picks out the bangers they did not mark
+#[derive(Debug, Clone)]
+pub struct Detector {
+    rules: Arc<[Box<dyn Rule>]>,
+    config: DetectorConfig,
+    listening: Arc<Mutex<HashMap<UserId, Listening>>>,
+}
+
+impl Detector {
+    pub fn new(config: DetectorConfig, rules: Vec<Box<dyn Rule>>) -> Self {
+        Self {
+            rules: rules.into(),
+            config,
+            listening: Default::default(),
+        }
+    }
+
+    /// A detector with every rule, at the thresholds it is configured with
+    pub fn with_default_rules(config: DetectorConfig) -> Self {
+        Self::new(
+            config,
+            vec![
+                Box::new(config.replay),
+                Box::new(config.seek_back),
+                Box::new(config.skip_into),
+                Box::new(config.repeat),
+            ],
+        )
+    }
+
+    /// Take note of what the user is listening to, giving back a banger to
+    /// record if the rules are sure enough that it is one
+    pub fn observe(&self, user: UserId, observation: Observation) -> Option<Mark> {
+        let mut listening = self.listening.lock().unwrap();
+        let listening = listening.entry(user).or_default();
+
+        // Anything older than what was seen last came late, from another feed
+        if listening
+            .history
+            .back()
+            .is_some_and(|latest| latest.at > observation.at)
+        {
+            return None;
+        }
+
+        let window = self.config.window.as_millis() as u64;
+        listening.history.push_back(observation);
+
+        let latest = listening.history.back()?.clone();
+        while let Some(oldest) = listening.history.front() {
+            if oldest.at + window >= latest.at {
+                break;
+            }
+
+            listening.history.pop_front();
+        }
+
+        let history = listening.history.make_contiguous();
+
+        // Every rule that fires makes it more likely, without ever getting certain
+        let doubt = self
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let confidence = rule.judge(history)?.clamp(0.0, 1.0);
+                debug!(
+                    rule = rule.name(),
+                    confidence,
+                    track = latest.track_id,
+                    "rule fired"
+                );
+
+                Some(confidence)
+            })
+            .fold(1.0, |doubt, confidence| doubt * (1.0 - confidence));
+        let confidence = 1.0 - doubt;
+
+        if confidence < self.config.min_confidence {
+            return None;
+        }
+
+        let cooldown = self.config.cooldown.as_millis() as u64;
+        if let Some((track_id, at)) = &listening.detected {
+            if *track_id == latest.track_id && at + cooldown > latest.at {
+                return None;
+            }
+        }
+
+        listening.detected = Some((latest.track_id.clone(), latest.at));
+
+        Some(Mark {
+            track_id: latest.track_id,
+            album_id: latest.album_id,
+            artist_ids: latest.artist_ids,
+            marked_at: latest.at,
+            progress_ms: latest.progress_ms,
+            device: latest.device,
+            context: latest.context,
+            note: None,
+            source: BangerSource::Auto,
+            confidence: Some((confidence * 100.0).round() as u8),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1000;
+
+    /// Playback of a track, `at` and `progress` in seconds
+    pub(super) fn playing(at: u64, track_id: &str, progress: u64) -> Observation {
+        Observation {
+            at: at * SECOND,
+            track_id: track_id.into(),
+            album_id: None,
+            artist_ids: Vec::new(),
+            progress_ms: progress * SECOND,
+            is_playing: true,
+            repeat: RepeatState::Off,
+            device: None,
+            context: None,
+        }
+    }
+
+    /// Feed the timeline to the detector, giving back when and where in
+    /// which track bangers were detected
+    fn detect(detector: &Detector, timeline: Vec<Observation>) -> Vec<(u64, String, u64)> {
+        timeline
+            .into_iter()
+            .filter_map(|observation| detector.observe(UserId(1), observation))
+            .map(|mark| {
+                assert_eq!(mark.source, BangerSource::Auto);
+
+                (
+                    mark.marked_at / SECOND,
+                    mark.track_id,
+                    mark.progress_ms / SECOND,
+                )
+            })
+            .collect()
+    }
+
+    fn detector() -> Detector {
+        Detector::with_default_rules(DetectorConfig::default())
+    }
+
+    #[test]
+    fn plain_listening_is_no_banger() {
+        let timeline = (0..60)
+            .map(|tick| match tick * 5 {
+                at @ 0..=199 => playing(at, "first", at),
+                at => playing(at, "second", at - 200),
+            })
+            .collect();
+
+        assert_eq!(detect(&detector(), timeline), []);
+    }
+
+    #[test]
+    fn replay_is_detected() {
+        let timeline = vec![
+            playing(0, "track", 170),
+            playing(5, "track", 175),
+            playing(10, "other", 2),
+            // Went right back to it
+            playing(15, "track", 3),
+            playing(20, "track", 8),
+        ];
+
+        assert_eq!(detect(&detector(), timeline), [(15, "track".to_owned(), 3)]);
+    }
+
+    #[test]
+    fn seeking_back_is_detected() {
+        let timeline = vec![
+            playing(0, "track", 90),
+            playing(5, "track", 95),
+            playing(10, "track", 82),
+            playing(15, "track", 87),
+        ];
+
+        assert_eq!(
+            detect(&detector(), timeline.clone()),
+            [(10, "track".to_owned(), 82)]
+        );
+
+        let detector = Detector::with_default_rules(DetectorConfig {
+            seek_back: SeekBack {
+                min_jump_ms: 30_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        assert_eq!(detect(&detector, timeline), []);
+    }
+
+    #[test]
+    fn skipping_into_track_is_detected() {
+        let timeline = vec![
+            playing(0, "other", 30),
+            playing(5, "track", 95),
+            playing(10, "track", 100),
+        ];
+
+        assert_eq!(detect(&detector(), timeline), [(5, "track".to_owned(), 95)]);
+    }
+
+    #[test]
+    fn repeat_is_detected_once() {
+        let on_repeat = |at, progress| Observation {
+            repeat: RepeatState::Track,
+            ..playing(at, "track", progress)
+        };
+
+        let timeline = vec![
+            playing(0, "track", 10),
+            on_repeat(5, 15),
+            on_repeat(10, 20),
+            // Looping around, still within the cooldown
+            on_repeat(200, 5),
+        ];
+
+        assert_eq!(detect(&detector(), timeline), [(5, "track".to_owned(), 15)]);
+    }
+
+    #[test]
+    fn unsure_detections_are_dropped() {
+        let detector = Detector::with_default_rules(DetectorConfig {
+            min_confidence: 0.9,
+            ..Default::default()
+        });
+
+        let timeline = vec![playing(0, "other", 30), playing(5, "track", 95)];
+
+        assert_eq!(detect(&detector, timeline), []);
+    }
+
+    #[test]
+    fn late_observations_are_ignored() {
+        let timeline = vec![
+            playing(0, "track", 90),
+            playing(10, "track", 100),
+            // Seen before the latest, but only passed on now
+            playing(5, "track", 95),
+            playing(10, "track", 100),
+        ];
+
+        assert_eq!(detect(&detector(), timeline), []);
+    }
+
+    #[test]
+    fn users_are_watched_separately() {
+        let detector = detector();
+
+        assert!(detector
+            .observe(UserId(1), playing(0, "track", 90))
+            .is_none());
+        assert!(detector
+            .observe(UserId(2), playing(5, "track", 10))
+            .is_none());
+        assert!(detector
+            .observe(UserId(1), playing(5, "track", 80))
+            .is_some());
+    }
+
+    #[test]
+    fn history_is_forgotten() {
+        let detector = Detector::with_default_rules(DetectorConfig {
+            window: Duration::from_secs(60),
+            ..Default::default()
+        });
+
+        let timeline = vec![
+            playing(0, "track", 170),
+            playing(100, "other", 2),
+            playing(200, "track", 3),
+        ];
+
+        assert_eq!(detect(&detector, timeline), []);
+    }
+}
@@ -0,0 +1,239 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use spotify_banger_model::RepeatState;
+
+use super::{serialize_percent, Observation, Rule};
+use crate::config::serialize_secs;
+
+/// The latest observation, along with the one right before it
+fn last_two(history: &[Observation]) -> Option<(&Observation, &Observation)> {
+    match history {
+        [.., previous, latest] => Some((previous, latest)),
+        _ => None,
+    }
+}
+
+/// The track was played again shortly after it was played before
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Replay {
+    /// How recently the track must have been played before
+    #[serde(serialize_with = "serialize_secs")]
+    pub within: Duration,
+    /// How close to its start the track must be to count as played again
+    pub start_ms: u64,
+    #[serde(serialize_with = "serialize_percent")]
+    pub confidence: f64,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            within: Duration::from_secs(10 * 60),
+            start_ms: 10_000,
+            confidence: 0.7,
+        }
+    }
+}
+
+impl Rule for Replay {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn judge(&self, history: &[Observation]) -> Option<f64> {
+        let (previous, latest) = last_two(history)?;
+
+        let started_over = latest.progress_ms <= self.start_ms
+            && (previous.track_id != latest.track_id || previous.progress_ms > latest.progress_ms);
+        if !started_over {
+            return None;
+        }
+
+        let since = latest.at.saturating_sub(self.within.as_millis() as u64);
+        let played_before = history[..history.len() - 1]
+            .iter()
+            .any(|earlier| earlier.track_id == latest.track_id && earlier.at >= since);
+
+        played_before.then_some(self.confidence)
+    }
+}
+
+/// The track was sought back to hear part of it again
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SeekBack {
+    /// How far back playback must have jumped, so that polling jitter does
+    /// not count as seeking
+    pub min_jump_ms: u64,
+    #[serde(serialize_with = "serialize_percent")]
+    pub confidence: f64,
+}
+
+impl Default for SeekBack {
+    fn default() -> Self {
+        Self {
+            min_jump_ms: 5_000,
+            confidence: 0.6,
+        }
+    }
+}
+
+impl Rule for SeekBack {
+    fn name(&self) -> &'static str {
+        "seek back"
+    }
+
+    fn judge(&self, history: &[Observation]) -> Option<f64> {
+        let (previous, latest) = last_two(history)?;
+
+        (previous.track_id == latest.track_id
+            && latest.progress_ms + self.min_jump_ms < previous.progress_at(latest.at))
+        .then_some(self.confidence)
+    }
+}
+
+/// The track was skipped to, and right into the middle of it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SkipInto {
+    /// How much further along the track must be than if it had played from
+    /// the start
+    pub min_offset_ms: u64,
+    #[serde(serialize_with = "serialize_percent")]
+    pub confidence: f64,
+}
+
+impl Default for SkipInto {
+    fn default() -> Self {
+        Self {
+            min_offset_ms: 20_000,
+            confidence: 0.5,
+        }
+    }
+}
+
+impl Rule for SkipInto {
+    fn name(&self) -> &'static str {
+        "skip into"
+    }
+
+    fn judge(&self, history: &[Observation]) -> Option<f64> {
+        let (previous, latest) = last_two(history)?;
+
+        let elapsed = latest.at.saturating_sub(previous.at);
+
+        (previous.track_id != latest.track_id && latest.progress_ms > elapsed + self.min_offset_ms)
+            .then_some(self.confidence)
+    }
+}
+
+/// The track was put on repeat while it was playing
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Repeat {
+    #[serde(serialize_with = "serialize_percent")]
+    pub confidence: f64,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Self { confidence: 0.8 }
+    }
+}
+
+impl Rule for Repeat {
+    fn name(&self) -> &'static str {
+        "repeat"
+    }
+
+    fn judge(&self, history: &[Observation]) -> Option<f64> {
+        let (previous, latest) = last_two(history)?;
+
+        (previous.track_id == latest.track_id
+            && previous.repeat != RepeatState::Track
+            && latest.repeat == RepeatState::Track)
+            .then_some(self.confidence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detect::tests::playing;
+
+    #[test]
+    fn replay_needs_earlier_play() {
+        let rule = Replay::default();
+
+        assert_eq!(
+            rule.judge(&[playing(0, "other", 100), playing(5, "track", 2)]),
+            None
+        );
+        assert_eq!(
+            rule.judge(&[
+                playing(0, "track", 100),
+                playing(5, "other", 100),
+                playing(10, "track", 2)
+            ]),
+            Some(rule.confidence)
+        );
+        // Already playing again for a while
+        assert_eq!(
+            rule.judge(&[
+                playing(0, "track", 100),
+                playing(5, "track", 1),
+                playing(10, "track", 6)
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn seek_back_tolerates_jitter() {
+        let rule = SeekBack::default();
+
+        assert_eq!(
+            rule.judge(&[playing(0, "track", 60), playing(5, "track", 62)]),
+            None
+        );
+        assert_eq!(
+            rule.judge(&[playing(0, "track", 60), playing(5, "track", 50)]),
+            Some(rule.confidence)
+        );
+        assert_eq!(
+            rule.judge(&[playing(0, "other", 60), playing(5, "track", 50)]),
+            None
+        );
+    }
+
+    #[test]
+    fn paused_playback_does_not_move() {
+        let rule = SeekBack::default();
+        let paused = Observation {
+            is_playing: false,
+            ..playing(0, "track", 60)
+        };
+
+        assert_eq!(
+            rule.judge(&[paused.clone(), playing(300, "track", 58)]),
+            None
+        );
+        assert_eq!(
+            rule.judge(&[paused, playing(300, "track", 40)]),
+            Some(rule.confidence)
+        );
+    }
+
+    #[test]
+    fn skip_into_needs_offset() {
+        let rule = SkipInto::default();
+
+        assert_eq!(
+            rule.judge(&[playing(0, "other", 60), playing(5, "track", 10)]),
+            None
+        );
+        assert_eq!(
+            rule.judge(&[playing(0, "other", 60), playing(5, "track", 60)]),
+            Some(rule.confidence)
+        );
+        assert_eq!(rule.judge(&[playing(5, "track", 60)]), None);
+    }
+}
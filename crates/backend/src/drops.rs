@@ -0,0 +1,88 @@
+use std::cmp::Reverse;
+
+use spotify_banger_model::TrackDrop;
+
+/// Bangers this close to the one before are taken as reactions to the same
+/// moment, as nobody hits the button at exactly the same time twice
+const TOLERANCE_MS: u64 = 3_000;
+/// Past this, a cluster is a stretch of the track rather than a moment, so
+/// a new one is started even if the bangers keep coming close together
+const MAX_SPREAD_MS: u64 = 8_000;
+/// A single banger says nothing about where the drop is
+const MIN_HITS: usize = 2;
+
+/// Where in a track the drops are, going by how far into the track each of
+/// its bangers was marked
+pub fn drops(progress_ms: impl IntoIterator<Item = u64>) -> Vec<TrackDrop> {
+    let mut progress_ms = progress_ms.into_iter().collect::<Vec<_>>();
+    progress_ms.sort_unstable();
+
+    let mut clusters = Vec::<&[u64]>::new();
+    let mut start = 0;
+
+    for end in 1..=progress_ms.len() {
+        let splits = match progress_ms.get(end) {
+            Some(&next) => {
+                next - progress_ms[end - 1] > TOLERANCE_MS
+                    || next - progress_ms[start] > MAX_SPREAD_MS
+            }
+            None => true,
+        };
+
+        if splits {
+            clusters.push(&progress_ms[start..end]);
+            start = end;
+        }
+    }
+
+    let mut drops = clusters
+        .into_iter()
+        .filter(|cluster| cluster.len() >= MIN_HITS)
+        .map(|cluster| TrackDrop {
+            // Reactions trail the drop, so the lower median sits closest to it
+            progress_ms: cluster[(cluster.len() - 1) / 2],
+            hits: cluster.len() as u64,
+        })
+        .collect::<Vec<_>>();
+
+    drops.sort_by_key(|drop| (Reverse(drop.hits), drop.progress_ms));
+
+    drops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drop(progress_ms: u64, hits: u64) -> TrackDrop {
+        TrackDrop { progress_ms, hits }
+    }
+
+    #[test]
+    fn nothing_to_cluster() {
+        assert_eq!(drops([]), []);
+        assert_eq!(drops([96_000]), []);
+        assert_eq!(drops([30_000, 96_000, 200_000]), []);
+    }
+
+    #[test]
+    fn reactions_are_clustered() {
+        assert_eq!(drops([97_200, 96_000, 96_500, 98_100]), [drop(96_500, 4)]);
+    }
+
+    #[test]
+    fn drops_are_ordered_by_hits() {
+        assert_eq!(
+            drops([212_000, 96_000, 30_000, 213_000, 97_000, 211_500, 60_000]),
+            [drop(212_000, 3), drop(96_000, 2)]
+        );
+    }
+
+    #[test]
+    fn clusters_stay_short() {
+        // Close enough one after the other, but too spread out to be one moment
+        let progress_ms = (0..10).map(|second| 90_000 + second * 2_000);
+
+        assert_eq!(drops(progress_ms), [drop(94_000, 5), drop(104_000, 5)]);
+    }
+}
@@ -1,7 +1,7 @@
 use askama::Template;
 use axum::{
     body::Body,
-    http::{self, request, Request, StatusCode, Uri},
+    http::{self, Request, StatusCode},
     response::{IntoResponse, Response},
 };
 use tracing::{error, trace};
@@ -1,79 +1,162 @@
-use std::{env, io, net::SocketAddr, sync::atomic::AtomicU64};
+use std::{env, io, process};
 
 use axum::{
     routing::{any_service, get_service},
     Router,
 };
 use reqwest::StatusCode;
+use tokio::{
+    sync::oneshot,
+    time::{timeout_at, Instant},
+};
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, services::ServeDir, ServiceBuilderExt};
-use tracing::{debug, error, Level};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
-use crate::error::not_found;
+use crate::{
+    config::{Config, Runtime},
+    error::not_found,
+    shutdown::Workers,
+    storage::Storage,
+};
 
 mod api;
+mod bangers;
+mod clock;
+mod config;
+mod crypto;
+mod detect;
+mod drops;
 mod error;
+mod plays;
 mod serde;
+mod session;
+mod shutdown;
+mod stats;
+mod storage;
+mod users;
 
 fn main() {
     #[cfg(debug_assertions)]
     dotenv::dotenv().ok();
 
+    // Everything that is wrong with the configuration at once, before there
+    // is anywhere to log to
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{error}");
+
+            process::exit(1);
+        }
+    };
+
+    if env::args().any(|arg| arg == "--print-config") {
+        return print!("{}", config.to_toml());
+    }
+
     tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_error| {
-            EnvFilter::default()
-                .add_directive(Level::INFO.into())
-                .add_directive("tower_http=debug".parse().unwrap())
-                .add_directive("spotify_banger_backend=trace".parse().unwrap())
-                .add_directive("spotify_banger_model=trace".parse().unwrap())
-        }))
+        .with_env_filter(EnvFilter::new(&config.log_filter))
         .init();
 
-    tokio::runtime::Builder::new_current_thread()
+    if env::args().nth(1).as_deref() == Some("reseal-tokens") {
+        return reseal_tokens(&config);
+    }
+
+    let mut runtime = match config.runtime {
+        Runtime::CurrentThread => tokio::runtime::Builder::new_current_thread(),
+        Runtime::MultiThread => tokio::runtime::Builder::new_multi_thread(),
+    };
+
+    if let Some(worker_threads) = config.worker_threads {
+        runtime.worker_threads(worker_threads);
+    }
+
+    runtime
         .enable_all()
         .build()
         .unwrap()
-        .block_on(async_main());
+        .block_on(async_main(config));
+}
+
+/// Seal the stored refresh tokens with the active key from `TOKEN_KEYS`, after
+/// which the other keys can be removed from it
+fn reseal_tokens(config: &Config) {
+    match Storage::open(&config.database_path).reseal_refresh_tokens(&config.token_keys.0) {
+        Ok(resealed) => info!(
+            resealed.resealed,
+            resealed.current, resealed.forgotten, "resealed refresh tokens"
+        ),
+        Err(error) => {
+            error!(%error, "failed to reseal refresh tokens");
+
+            process::exit(1);
+        }
+    }
 }
 
-async fn async_main() {
+async fn async_main(config: Config) {
+    let storage = Storage::open(&config.database_path);
+    let mut workers = Workers::default();
+
     let app = Router::new()
         .fallback(
             any_service(
-                ServeDir::new(env::var("STATIC_FILES").unwrap_or_else(|_| {
-                    format!(
-                        "{}/../client/dist",
-                        env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set")
-                    )
-                }))
-                .precompressed_br()
-                .append_index_html_on_directories(true)
-                .fallback(get_service(tower::service_fn(not_found::<io::Error>))), // FIXME:
+                ServeDir::new(&config.static_dir)
+                    .precompressed_br()
+                    .append_index_html_on_directories(true)
+                    .fallback(get_service(tower::service_fn(not_found::<io::Error>))), // FIXME:
             )
             .handle_error(|_err: io::Error| async {
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
             }),
         )
         .layer(CorsLayer::permissive())
-        .nest("/api", api::create_router())
+        .nest(
+            "/api",
+            api::create_router(&config, storage.clone(), &mut workers),
+        )
         .layer(ServiceBuilder::new().trace_for_http().compression());
 
-    let addr = env::var("BIND")
-        .ok()
-        .and_then(|addr| {
-            addr.parse()
-                .map_err(|error| {
-                    error!(%error, "failed to parse BIND environment variable");
-                    error
-                })
-                .ok()
-        })
-        .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 9000)));
-
-    debug!("listening on http://{}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    let (drain, draining) = oneshot::channel();
+    let mut server = tokio::spawn(
+        axum::Server::bind(&config.bind)
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async {
+                draining.await.ok();
+            }),
+    );
+
+    debug!("listening on http://{}", config.bind);
+
+    let stopped_early = tokio::select! {
+        result = &mut server => {
+            error!(?result, "server stopped before being asked to");
+
+            true
+        }
+        () = shutdown::signal() => false,
+    };
+
+    let deadline = Instant::now() + config.shutdown_timeout;
+    info!(timeout = ?config.shutdown_timeout, "shutting down");
+
+    let drained = async {
+        if stopped_early {
+            return;
+        }
+
+        drain.send(()).ok();
+
+        match timeout_at(deadline, server).await {
+            Ok(Ok(Ok(()))) => debug!("drained open connections"),
+            Ok(result) => error!(?result, "server failed while draining connections"),
+            Err(_) => warn!("connections were still open at the deadline, dropping them"),
+        }
+    };
+
+    tokio::join!(drained, workers.stop(deadline));
+
+    info!("shut down");
 }
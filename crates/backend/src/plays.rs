@@ -0,0 +1,44 @@
+use spotify_banger_model::PlayHistory;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// A play as it is kept in storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlayId(pub i64);
+
+/// A stretch of time that a track kept playing for, from when the listener
+/// first saw it until it last did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Play {
+    pub track_id: String,
+    pub context: Option<String>,
+    pub device: Option<String>,
+    /// Unix millisecond timestamp of when the track started playing, going
+    /// by its progress when it was first seen
+    pub started_at: u64,
+    /// Unix millisecond timestamp of when the track was last seen playing
+    pub last_seen: u64,
+    /// How far into the track playback was when it was last seen
+    pub progress_ms: u64,
+    /// Unix millisecond timestamp of when spotify counted the play, once it
+    /// shows up in the recently played tracks of the user
+    pub played_at: Option<u64>,
+}
+
+impl Play {
+    /// A play that spotify remembers, which is assumed to have played from
+    /// start to finish right up until it was counted
+    pub fn from_history(history: PlayHistory) -> Option<Self> {
+        let played_at = OffsetDateTime::parse(&history.played_at, &Rfc3339).ok()?;
+        let played_at = u64::try_from(played_at.unix_timestamp_nanos() / 1_000_000).ok()?;
+
+        Some(Self {
+            track_id: history.track.id?,
+            context: history.context.map(|context| context.uri),
+            device: None,
+            started_at: played_at.saturating_sub(history.track.duration_ms),
+            last_seen: played_at,
+            progress_ms: history.track.duration_ms,
+            played_at: Some(played_at),
+        })
+    }
+}
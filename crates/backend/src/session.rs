@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::display::Base64Display;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use spotify_banger_model::SessionKey;
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    crypto::TokenCipher,
+    storage::{RefreshToken, Storage, StorageError},
+    users::UserId,
+};
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// How long before expiry an access token is considered stale and gets refreshed
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+/// How stale the last seen time of a session is allowed to get
+const LAST_SEEN_RESOLUTION: Duration = Duration::from_secs(60);
+/// How long a session can go unused before it ends on its own
+pub const SESSION_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct SessionId {
+    id: [u8; 32],
+}
+
+impl SessionId {
+    pub fn random() -> Self {
+        let mut id = [0_u8; 32];
+        rand::thread_rng().fill(&mut id);
+
+        Self { id }
+    }
+
+    /// What the session is looked up by, so that reading the database is not
+    /// enough to take it over
+    fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.id).into()
+    }
+}
+
+impl Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Base64Display::with_config(&self.id, base64::URL_SAFE_NO_PAD).fmt(f)
+    }
+}
+
+impl FromStr for SessionId {
+    type Err = base64::DecodeError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let mut id = [0_u8; 32];
+
+        let len = base64::decode_config_slice(str, base64::URL_SAFE_NO_PAD, &mut id)?;
+
+        if len != id.len() {
+            return Err(base64::DecodeError::InvalidLength);
+        }
+
+        Ok(Self { id })
+    }
+}
+
+/// How the backend identifies itself to spotify when using the tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClient {
+    /// Authenticated by the client secret
+    Confidential,
+    /// Identified only by the client id, as the tokens came from a PKCE flow
+    Public,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpotifyTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub scope: String,
+    pub expires_at: SystemTime,
+    pub client: TokenClient,
+}
+
+impl SpotifyTokens {
+    pub fn needs_refresh(&self) -> bool {
+        self.expires_at <= SystemTime::now() + REFRESH_MARGIN
+    }
+
+    pub fn expires_in(&self) -> Duration {
+        self.expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+
+    /// The part of the tokens worth keeping once the session is gone, sealed
+    /// so that it is of no use to anyone reading the database
+    pub fn persistent(&self, cipher: &TokenCipher, user: UserId) -> RefreshToken {
+        RefreshToken {
+            refresh_token: cipher.seal(user, &self.refresh_token),
+            scope: self.scope.clone(),
+            client: self.client,
+        }
+    }
+}
+
+/// The tokens of a single session, locked while they are being refreshed so
+/// that concurrent requests do not race to use a refresh token that spotify
+/// is about to rotate out
+pub type SessionTokens = Arc<AsyncMutex<SpotifyTokens>>;
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub key: SessionKey,
+    pub user: UserId,
+    /// Missing when the user logged in through a linked account instead of
+    /// spotify, or the backend restarted since the session was last used
+    pub spotify: Option<SessionTokens>,
+}
+
+/// Sessions kept in storage, along with the spotify tokens of those used
+/// since the backend started
+#[derive(Clone)]
+pub struct SessionStorage {
+    storage: Storage,
+    tokens: Arc<Mutex<HashMap<SessionKey, SessionTokens>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SessionStorage {
+    pub fn new(storage: Storage) -> Self {
+        Self::with_clock(storage, SystemClock)
+    }
+
+    pub fn with_clock(storage: Storage, clock: impl Clock) -> Self {
+        Self {
+            storage,
+            tokens: Default::default(),
+            clock: Arc::new(clock),
+        }
+    }
+
+    pub fn create_session(
+        &self,
+        user: UserId,
+        tokens: Option<SpotifyTokens>,
+        user_agent: Option<&str>,
+    ) -> Result<SessionId, StorageError> {
+        let id = SessionId::random();
+        let key = self.storage.create_session(user, &id.hash(), user_agent)?;
+
+        if let Some(tokens) = tokens {
+            self.tokens
+                .lock()
+                .unwrap()
+                .insert(key, Arc::new(AsyncMutex::new(tokens)));
+        }
+
+        Ok(id)
+    }
+
+    /// End the session if it belongs to the user, returning whether it did
+    pub fn end_session(&self, user: UserId, key: SessionKey) -> Result<bool, StorageError> {
+        self.tokens.lock().unwrap().remove(&key);
+
+        self.storage.end_session(user, key)
+    }
+
+    /// The session with the id, noting that it was just used, unless it went
+    /// unused for so long that it expired
+    pub fn session(&self, id: SessionId) -> Result<Option<Session>, StorageError> {
+        let stored = match self.storage.session(&id.hash())? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        if now.saturating_sub(stored.last_seen) >= SESSION_TTL.as_millis() as u64 {
+            self.end_session(stored.user, stored.key)?;
+
+            return Ok(None);
+        }
+
+        // Writing on every single request is not worth the precision
+        if now.saturating_sub(stored.last_seen) >= LAST_SEEN_RESOLUTION.as_millis() as u64 {
+            self.storage.touch_session(stored.key, now)?;
+        }
+
+        Ok(Some(Session {
+            key: stored.key,
+            user: stored.user,
+            spotify: self.tokens.lock().unwrap().get(&stored.key).cloned(),
+        }))
+    }
+
+    /// Give spotify tokens to a session that is missing them, unless it got
+    /// some in the meantime
+    pub fn attach_spotify(&self, key: SessionKey, tokens: SpotifyTokens) -> SessionTokens {
+        self.tokens
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(AsyncMutex::new(tokens)))
+            .clone()
+    }
+
+    #[cfg(test)]
+    pub fn spotify_tokens(&self, id: SessionId) -> Option<SessionTokens> {
+        self.session(id).unwrap()?.spotify
+    }
+}
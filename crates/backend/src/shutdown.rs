@@ -0,0 +1,188 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{timeout_at, Instant},
+};
+use tracing::{debug, error, info, warn};
+
+/// How late a worker may be with its heartbeat before it counts as stuck
+const HEARTBEAT_GRACE: Duration = Duration::from_secs(30);
+
+/// Resolves once the process is asked to stop, be it by SIGINT or SIGTERM
+pub async fn signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => info!("received SIGINT"),
+        () = terminate => info!("received SIGTERM"),
+    }
+}
+
+/// Lets a background worker know that it is time to wrap up
+#[derive(Debug, Clone)]
+pub struct Stop(watch::Receiver<bool>);
+
+impl Stop {
+    /// Resolves once the worker should stop, or right away if it already should
+    pub async fn requested(&mut self) {
+        // The sender is only ever dropped when shutting down anyway
+        let _ = self.0.wait_for(|stop| *stop).await;
+    }
+}
+
+/// Lets a background worker show that it is still getting somewhere, holding
+/// when the next beat is due
+#[derive(Debug, Clone)]
+pub struct Heartbeat(Arc<Mutex<Instant>>);
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now() + HEARTBEAT_GRACE)))
+    }
+
+    /// A heartbeat that was due some time ago
+    #[cfg(test)]
+    pub fn late(by: Duration) -> Self {
+        Self(Arc::new(Mutex::new(Instant::now() - by)))
+    }
+
+    /// Note that the worker is still going, and that it beats again within
+    /// the given time
+    pub fn beat(&self, next_within: Duration) {
+        *self.0.lock().unwrap() = Instant::now() + next_within + HEARTBEAT_GRACE;
+    }
+
+    /// How long ago the next beat was due, if it is overdue
+    pub fn overdue(&self) -> Option<Duration> {
+        Instant::now().checked_duration_since(*self.0.lock().unwrap())
+    }
+}
+
+/// The tasks that keep running in the background for as long as the server does
+#[derive(Debug)]
+pub struct Workers {
+    stop: watch::Sender<bool>,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+    heartbeats: Vec<(&'static str, Heartbeat)>,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        Self {
+            stop: watch::channel(false).0,
+            tasks: Vec::new(),
+            heartbeats: Vec::new(),
+        }
+    }
+}
+
+impl Workers {
+    /// Spawn a worker, which has to beat its heartbeat for as long as it runs,
+    /// and return once it is told to stop
+    pub fn spawn<F>(&mut self, name: &'static str, worker: impl FnOnce(Stop, Heartbeat) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let heartbeat = Heartbeat::new();
+        let task = tokio::spawn(worker(Stop(self.stop.subscribe()), heartbeat.clone()));
+
+        self.tasks.push((name, task));
+        self.heartbeats.push((name, heartbeat));
+    }
+
+    /// The heartbeat of every worker spawned so far, by name
+    pub fn heartbeats(&self) -> Vec<(&'static str, Heartbeat)> {
+        self.heartbeats.clone()
+    }
+
+    /// Tell every worker to stop, giving them until the deadline to finish
+    /// what they are in the middle of before they are cut off
+    pub async fn stop(self, deadline: Instant) {
+        self.stop.send_replace(true);
+
+        for (name, task) in self.tasks {
+            let abort = task.abort_handle();
+
+            match timeout_at(deadline, task).await {
+                Ok(Ok(())) => debug!(name, "worker stopped"),
+                Ok(Err(error)) => error!(name, %error, "worker failed"),
+                Err(_) => {
+                    warn!(name, "worker did not stop in time, cutting it off");
+
+                    abort.abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn workers_finish_when_told_to_stop() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut workers = Workers::default();
+
+        workers.spawn("test", |mut stop, _| {
+            let finished = finished.clone();
+
+            async move {
+                stop.requested().await;
+                finished.store(true, Ordering::SeqCst);
+            }
+        });
+
+        workers.stop(Instant::now() + Duration::from_secs(10)).await;
+
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn workers_are_cut_off_at_deadline() {
+        let mut workers = Workers::default();
+
+        workers.spawn("stubborn", |_, _| {
+            tokio::time::sleep(Duration::from_secs(60))
+        });
+
+        let started = Instant::now();
+        workers.stop(started + Duration::from_millis(50)).await;
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn heartbeat_is_overdue_once_late() {
+        assert_eq!(Heartbeat::new().overdue(), None);
+
+        let heartbeat = Heartbeat::late(Duration::from_secs(5));
+        assert!(heartbeat.overdue().unwrap() >= Duration::from_secs(5));
+
+        heartbeat.beat(Duration::from_secs(60));
+        assert_eq!(heartbeat.overdue(), None);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use spotify_banger_model::{ArtistStats, Banger, BangerSource, HitStats, Stats, TrackStats};
+
+const HOUR_MS: i64 = 60 * 60 * 1000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+/// The unix epoch fell on a thursday
+const EPOCH_WEEKDAY: i64 = 3;
+
+/// Time zones stray at most this far from UTC, in minutes
+pub const MAX_UTC_OFFSET: i32 = 14 * 60;
+
+/// A point in time, as seen in some time zone
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    ms: i64,
+}
+
+impl Local {
+    fn new(unix_ms: u64, utc_offset: i32) -> Self {
+        Self {
+            ms: unix_ms as i64 + utc_offset as i64 * 60 * 1000,
+        }
+    }
+
+    /// Days since the unix epoch
+    fn day(self) -> i64 {
+        self.ms.div_euclid(DAY_MS)
+    }
+
+    fn hour(self) -> usize {
+        (self.ms.rem_euclid(DAY_MS) / HOUR_MS) as usize
+    }
+
+    /// Days since monday
+    fn weekday(self) -> usize {
+        (self.day() + EPOCH_WEEKDAY).rem_euclid(7) as usize
+    }
+}
+
+/// How often and when every track and artist in the bangers hit, counting
+/// days and hours in the time zone `utc_offset` minutes east of UTC
+pub fn stats(bangers: &[Banger], utc_offset: i32, now: SystemTime) -> Stats {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let today = Local::new(now, utc_offset).day();
+
+    let mut tracks = BTreeMap::<&str, Vec<&Banger>>::new();
+    let mut artists = BTreeMap::<&str, Vec<&Banger>>::new();
+
+    for banger in bangers {
+        tracks.entry(&banger.track_id).or_default().push(banger);
+
+        for artist_id in &banger.artist_ids {
+            artists.entry(artist_id).or_default().push(banger);
+        }
+    }
+
+    let mut tracks = tracks
+        .into_iter()
+        .map(|(track_id, bangers)| TrackStats {
+            track_id: track_id.to_owned(),
+            hits: hits(&bangers, utc_offset, today),
+        })
+        .collect::<Vec<_>>();
+    let mut artists = artists
+        .into_iter()
+        .map(|(artist_id, bangers)| ArtistStats {
+            artist_id: artist_id.to_owned(),
+            hits: hits(&bangers, utc_offset, today),
+        })
+        .collect::<Vec<_>>();
+
+    tracks.sort_by_key(|track| most_hit(&track.hits));
+    artists.sort_by_key(|artist| most_hit(&artist.hits));
+
+    Stats { tracks, artists }
+}
+
+/// Orders the most marked first, then the most recently marked
+fn most_hit(hits: &HitStats) -> (Reverse<u64>, Reverse<u64>) {
+    (Reverse(hits.count), Reverse(hits.last_hit))
+}
+
+fn hits(bangers: &[&Banger], utc_offset: i32, today: i64) -> HitStats {
+    let mut hour_of_day = [0; 24];
+    let mut day_of_week = [0; 7];
+    let mut days = Vec::with_capacity(bangers.len());
+    let mut progress = Vec::with_capacity(bangers.len());
+
+    for banger in bangers {
+        let local = Local::new(banger.marked_at, utc_offset);
+
+        hour_of_day[local.hour()] += 1;
+        day_of_week[local.weekday()] += 1;
+        days.push(local.day());
+        // Detected bangers are marked wherever playback was at the time
+        if banger.source == BangerSource::Manual {
+            progress.push(banger.progress_ms);
+        }
+    }
+
+    days.sort_unstable();
+    days.dedup();
+    progress.sort_unstable();
+
+    let (longest_streak, current_streak) = streaks(&days, today);
+
+    HitStats {
+        count: bangers.len() as u64,
+        first_hit: bangers
+            .iter()
+            .map(|banger| banger.marked_at)
+            .min()
+            .unwrap_or_default(),
+        last_hit: bangers
+            .iter()
+            .map(|banger| banger.marked_at)
+            .max()
+            .unwrap_or_default(),
+        longest_streak,
+        current_streak,
+        hour_of_day,
+        day_of_week,
+        typical_progress_ms: progress
+            .get(progress.len().saturating_sub(1) / 2)
+            .copied()
+            .unwrap_or_default(),
+    }
+}
+
+/// The longest run of consecutive days, along with the run that is still
+/// going as of today. A run that ended yesterday still counts, as today is
+/// not over yet
+fn streaks(days: &[i64], today: i64) -> (u32, u32) {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous = None;
+
+    for &day in days {
+        current = match previous {
+            Some(previous) if day == previous + 1 => current + 1,
+            _ => 1,
+        };
+        longest = longest.max(current);
+        previous = Some(day);
+    }
+
+    match previous {
+        Some(last) if last >= today - 1 => (longest, current),
+        _ => (longest, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use spotify_banger_model::BangerId;
+
+    use super::*;
+
+    /// Friday the 15th of July 2022, at midnight UTC
+    const FRIDAY: u64 = 1657843200000;
+    const DAY: u64 = DAY_MS as u64;
+    const HOUR: u64 = HOUR_MS as u64;
+
+    fn banger(track_id: &str, artist_ids: &[&str], marked_at: u64, progress_ms: u64) -> Banger {
+        Banger {
+            id: BangerId(marked_at),
+            track_id: track_id.into(),
+            album_id: None,
+            artist_ids: artist_ids.iter().map(|&id| id.into()).collect(),
+            marked_at,
+            progress_ms,
+            device: None,
+            context: None,
+            note: None,
+            source: BangerSource::Manual,
+            confidence: None,
+        }
+    }
+
+    fn at(unix_ms: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(unix_ms)
+    }
+
+    #[test]
+    fn tracks_and_artists_are_counted() {
+        let bangers = [
+            banger(
+                "one more time",
+                &["daft punk", "romanthony"],
+                FRIDAY,
+                96_000,
+            ),
+            banger(
+                "one more time",
+                &["daft punk", "romanthony"],
+                FRIDAY + DAY,
+                90_000,
+            ),
+            banger("aerodynamic", &["daft punk"], FRIDAY + 2 * DAY, 30_000),
+        ];
+
+        let stats = stats(&bangers, 0, at(FRIDAY + 10 * DAY));
+
+        let tracks = stats
+            .tracks
+            .iter()
+            .map(|track| (track.track_id.as_str(), track.hits.count))
+            .collect::<Vec<_>>();
+        let artists = stats
+            .artists
+            .iter()
+            .map(|artist| (artist.artist_id.as_str(), artist.hits.count))
+            .collect::<Vec<_>>();
+
+        assert_eq!(tracks, [("one more time", 2), ("aerodynamic", 1)]);
+        assert_eq!(artists, [("daft punk", 3), ("romanthony", 2)]);
+
+        let hits = &stats.tracks[0].hits;
+        assert_eq!(hits.first_hit, FRIDAY);
+        assert_eq!(hits.last_hit, FRIDAY + DAY);
+        assert_eq!(hits.typical_progress_ms, 90_000);
+        assert_eq!(hits.longest_streak, 2);
+        assert_eq!(hits.current_streak, 0);
+        assert_eq!(stats.artists[0].hits.longest_streak, 3);
+    }
+
+    #[test]
+    fn typical_progress_leaves_out_detected_bangers() {
+        let detected = |marked_at| Banger {
+            source: BangerSource::Auto,
+            confidence: Some(70),
+            ..banger("track", &[], marked_at, 3_000)
+        };
+        let bangers = [
+            banger("track", &[], FRIDAY, 96_000),
+            detected(FRIDAY + HOUR),
+            detected(FRIDAY + 2 * HOUR),
+        ];
+
+        let hits = &stats(&bangers, 0, at(FRIDAY)).tracks[0].hits;
+
+        assert_eq!(hits.count, 3);
+        assert_eq!(hits.typical_progress_ms, 96_000);
+    }
+
+    #[test]
+    fn histograms_follow_time_zone() {
+        let bangers = [banger("track", &[], FRIDAY + 23 * HOUR, 0)];
+
+        let utc = stats(&bangers, 0, at(FRIDAY));
+        let hits = &utc.tracks[0].hits;
+        assert_eq!(hits.hour_of_day[23], 1);
+        assert_eq!(hits.day_of_week[4], 1);
+
+        // Already saturday morning two hours east of UTC
+        let east = stats(&bangers, 120, at(FRIDAY));
+        let hits = &east.tracks[0].hits;
+        assert_eq!(hits.hour_of_day[1], 1);
+        assert_eq!(hits.day_of_week[5], 1);
+
+        let west = stats(&bangers, -MAX_UTC_OFFSET, at(FRIDAY));
+        assert_eq!(west.tracks[0].hits.day_of_week[4], 1);
+    }
+
+    #[test]
+    fn streaks_run_until_yesterday() {
+        assert_eq!(streaks(&[], 10), (0, 0));
+        assert_eq!(streaks(&[1, 2, 3, 7, 8], 8), (3, 2));
+        assert_eq!(streaks(&[1, 2, 3, 7, 8], 9), (3, 2));
+        assert_eq!(streaks(&[1, 2, 3, 7, 8], 10), (3, 0));
+        assert_eq!(streaks(&[5], 5), (1, 1));
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    fmt::{self, Debug, Display},
+    ops::Deref,
+    path::Path,
+    sync::Arc,
+};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use spotify_banger_model::{Banger, BangerId, SessionKey};
+use tracing::{error, warn};
+
+pub use self::sqlite::Sqlite;
+use crate::{
+    bangers::{Listed, Listing, Mark},
+    crypto::{Sealed, TokenCipher},
+    plays::{Play, PlayId},
+    session::TokenClient,
+    users::{GithubIdentity, LinkError, User, UserId},
+};
+
+mod sqlite;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    Migration {
+        version: usize,
+        error: rusqlite::Error,
+    },
+    /// The database was migrated by a newer version of the backend
+    UnknownVersion(usize),
+    /// The database is missing migrations, which were undone after startup
+    Outdated(usize),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(error) => write!(f, "{error}"),
+            StorageError::Migration { version, error } => {
+                write!(f, "failed to apply migration {version}: {error}")
+            }
+            StorageError::UnknownVersion(version) => {
+                write!(f, "database is at unknown version {version}")
+            }
+            StorageError::Outdated(version) => {
+                write!(f, "database is at outdated version {version}")
+            }
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> Self {
+        StorageError::Sqlite(error)
+    }
+}
+
+impl IntoResponse for StorageError {
+    fn into_response(self) -> Response {
+        error!(error = %self, "storage failed");
+
+        (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
+    }
+}
+
+/// The long lived part of a user's spotify tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshToken {
+    pub refresh_token: Sealed,
+    pub scope: String,
+    pub client: TokenClient,
+}
+
+/// A session as it is kept in storage, identified by the hash of its id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredSession {
+    pub key: SessionKey,
+    pub user: UserId,
+    pub created_at: u64,
+    pub last_seen: u64,
+    pub user_agent: Option<String>,
+}
+
+/// Everything that has to outlive the process: users, their linked accounts,
+/// their refresh tokens, their sessions, their bangers and their plays
+pub trait Repository: Debug + Send + Sync + 'static {
+    /// Find the user owning the spotify account, creating them on their first login
+    fn spotify_user(&self, spotify_id: &str) -> Result<UserId, StorageError>;
+
+    fn github_user(&self, github_id: u64) -> Result<Option<UserId>, StorageError>;
+
+    fn user(&self, id: UserId) -> Result<Option<User>, StorageError>;
+
+    /// Link a github account to the user, replacing any account linked before
+    fn link_github(&self, id: UserId, identity: &GithubIdentity) -> Result<(), LinkError>;
+
+    /// Remember the refresh token of the user, replacing the one from a previous login
+    fn store_refresh_token(&self, id: UserId, token: &RefreshToken) -> Result<(), StorageError>;
+
+    fn refresh_token(&self, id: UserId) -> Result<Option<RefreshToken>, StorageError>;
+
+    fn forget_refresh_token(&self, id: UserId) -> Result<(), StorageError>;
+
+    /// The refresh tokens of every user
+    fn refresh_tokens(&self) -> Result<Vec<(UserId, RefreshToken)>, StorageError>;
+
+    fn create_session(
+        &self,
+        id: UserId,
+        token_hash: &[u8],
+        user_agent: Option<&str>,
+    ) -> Result<SessionKey, StorageError>;
+
+    fn session(&self, token_hash: &[u8]) -> Result<Option<StoredSession>, StorageError>;
+
+    /// Note that the session was used at the unix millisecond timestamp
+    fn touch_session(&self, key: SessionKey, last_seen: u64) -> Result<(), StorageError>;
+
+    /// End the session if it belongs to the user, returning whether it did
+    fn end_session(&self, id: UserId, key: SessionKey) -> Result<bool, StorageError>;
+
+    /// The sessions of the user, most recently seen first
+    fn sessions(&self, id: UserId) -> Result<Vec<StoredSession>, StorageError>;
+
+    fn record_banger(&self, id: UserId, mark: Mark) -> Result<Banger, StorageError>;
+
+    /// A page of the bangers of the user
+    fn bangers(&self, id: UserId, listing: &Listing) -> Result<Vec<Listed>, StorageError>;
+
+    /// Replace the note on one of the bangers of the user, returning the
+    /// banger if there is one
+    fn edit_banger(
+        &self,
+        id: UserId,
+        banger: BangerId,
+        note: Option<&str>,
+    ) -> Result<Option<Banger>, StorageError>;
+
+    /// Forget one of the bangers of the user, returning whether there was one
+    fn forget_banger(&self, id: UserId, banger: BangerId) -> Result<bool, StorageError>;
+
+    /// Have the backend listen along with the user, or stop doing so
+    fn set_listening(&self, id: UserId, listening: bool) -> Result<(), StorageError>;
+
+    fn is_listening(&self, id: UserId) -> Result<bool, StorageError>;
+
+    /// The users to listen along with, which are those who asked for it and
+    /// left a refresh token to do it with
+    fn listeners(&self) -> Result<Vec<UserId>, StorageError>;
+
+    fn start_play(&self, id: UserId, play: &Play) -> Result<PlayId, StorageError>;
+
+    /// Note that the play is still going
+    fn extend_play(
+        &self,
+        play: PlayId,
+        last_seen: u64,
+        progress_ms: u64,
+    ) -> Result<(), StorageError>;
+
+    /// Where to pick up backfilling plays from what spotify remembers the
+    /// user played, as a unix millisecond timestamp
+    fn recently_played_after(&self, id: UserId) -> Result<Option<u64>, StorageError>;
+
+    /// Backfill plays that spotify remembers the user played, skipping those
+    /// that were backfilled before and tying those the listener saw to when
+    /// spotify counted them, and move the cursor to pick up from after
+    ///
+    /// Gives back how many plays were missing
+    fn record_recently_played(
+        &self,
+        id: UserId,
+        plays: &[Play],
+        after: u64,
+    ) -> Result<usize, StorageError>;
+
+    /// Make sure the database can be reached and is migrated all the way
+    fn check(&self) -> Result<(), StorageError>;
+
+    /// The plays of the user, most recently started first
+    #[cfg(test)]
+    fn plays(&self, id: UserId) -> Result<Vec<Play>, StorageError>;
+}
+
+/// A shared handle to the [`Repository`] in use
+#[derive(Debug, Clone)]
+pub struct Storage {
+    repository: Arc<dyn Repository>,
+}
+
+/// The outcome of [`Storage::reseal_refresh_tokens`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Resealed {
+    pub resealed: usize,
+    /// Already sealed with the active key
+    pub current: usize,
+    /// Could not be opened, so their users have to log in again
+    pub forgotten: usize,
+}
+
+impl Storage {
+    pub fn new(repository: impl Repository) -> Self {
+        Self {
+            repository: Arc::new(repository),
+        }
+    }
+
+    pub fn open(path: &Path) -> Self {
+        Self::new(
+            Sqlite::open(path).unwrap_or_else(|error| {
+                panic!("failed to open database {}: {error}", path.display())
+            }),
+        )
+    }
+
+    /// Seal every refresh token with the active key, so that the keys before
+    /// it can be retired
+    pub fn reseal_refresh_tokens(&self, cipher: &TokenCipher) -> Result<Resealed, StorageError> {
+        let mut resealed = Resealed::default();
+
+        for (user, mut token) in self.refresh_tokens()? {
+            if token.refresh_token.key_id == cipher.active() {
+                resealed.current += 1;
+
+                continue;
+            }
+
+            match cipher.open(user, &token.refresh_token) {
+                Ok(refresh_token) => {
+                    token.refresh_token = cipher.seal(user, &refresh_token);
+                    self.store_refresh_token(user, &token)?;
+
+                    resealed.resealed += 1;
+                }
+                Err(error) => {
+                    warn!(%user, %error, "forgetting refresh token that can not be opened");
+                    self.forget_refresh_token(user)?;
+
+                    resealed.forgotten += 1;
+                }
+            }
+        }
+
+        Ok(resealed)
+    }
+
+    /// A fresh database that is gone once the last handle to it is dropped
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self::new(Sqlite::in_memory().unwrap())
+    }
+}
+
+impl Deref for Storage {
+    type Target = dyn Repository;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.repository
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        tests::{KEY_1, KEY_2},
+        KeyId,
+    };
+
+    #[test]
+    fn refresh_tokens_are_resealed_with_active_key() {
+        let storage = Storage::in_memory();
+        let old = TokenCipher::parse(KEY_1).unwrap();
+        let new = TokenCipher::parse(KEY_2).unwrap();
+        let rotated = TokenCipher::parse(&format!("{KEY_2},{KEY_1}")).unwrap();
+
+        let users = ["a", "b", "c"].map(|user| storage.spotify_user(user).unwrap());
+        let tokens = [&old, &new, &TokenCipher::random(KeyId(3))];
+
+        for (&user, cipher) in users.iter().zip(tokens) {
+            let token = RefreshToken {
+                refresh_token: cipher.seal(user, "refresh"),
+                scope: "scope".into(),
+                client: TokenClient::Confidential,
+            };
+
+            storage.store_refresh_token(user, &token).unwrap();
+        }
+
+        assert_eq!(
+            storage.reseal_refresh_tokens(&rotated).unwrap(),
+            Resealed {
+                resealed: 1,
+                current: 1,
+                forgotten: 1,
+            }
+        );
+
+        for user in &users[..2] {
+            let token = storage.refresh_token(*user).unwrap().unwrap();
+
+            assert_eq!(token.refresh_token.key_id, KeyId(2));
+            assert_eq!(new.open(*user, &token.refresh_token).unwrap(), "refresh");
+        }
+
+        assert_eq!(storage.refresh_token(users[2]).unwrap(), None);
+    }
+}
@@ -0,0 +1,916 @@
+use std::{
+    cmp::Ordering,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{named_params, params, Connection, OptionalExtension, Row};
+use spotify_banger_model::{Banger, BangerId, BangerSort, BangerSource, SessionKey};
+use tracing::info;
+
+use super::{RefreshToken, Repository, StorageError, StoredSession};
+use crate::{
+    bangers::{Listed, Listing, Mark},
+    crypto::{KeyId, Sealed},
+    plays::{Play, PlayId},
+    session::TokenClient,
+    users::{GithubIdentity, LinkError, User, UserId},
+};
+
+/// Applied in order, the database remembers how many of them it has seen
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0001_initial.sql"),
+    include_str!("../../migrations/0002_sessions.sql"),
+    include_str!("../../migrations/0003_banger_artists.sql"),
+    include_str!("../../migrations/0004_auto_bangers.sql"),
+    include_str!("../../migrations/0005_listening.sql"),
+    include_str!("../../migrations/0006_recently_played.sql"),
+];
+
+const GITHUB: &str = "github";
+
+const MANUAL: &str = "manual";
+const AUTO: &str = "auto";
+
+/// Every column of the bangers, along with their artists and how often their
+/// track was marked among the selected bangers
+const BANGERS: &str = "SELECT bangers.*,
+        (SELECT group_concat(artist_id, ',') FROM (
+            SELECT artist_id FROM banger_artists
+            WHERE banger_id = bangers.id
+            ORDER BY position
+        )) AS artist_ids,
+        COUNT(*) OVER (PARTITION BY track_id) AS times
+    FROM bangers";
+
+/// A [`Repository`] backed by an embedded SQLite database
+#[derive(Debug)]
+pub struct Sqlite {
+    connection: Mutex<Connection>,
+}
+
+impl Sqlite {
+    /// Open the database at the path, creating and migrating it as needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::new(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self, StorageError> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut connection: Connection) -> Result<Self, StorageError> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        migrate(&mut connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), StorageError> {
+    let current: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if current > MIGRATIONS.len() {
+        return Err(StorageError::UnknownVersion(current));
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = version + 1;
+
+        let transaction = connection.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .map_err(|error| StorageError::Migration { version, error })?;
+        transaction.pragma_update(None, "user_version", version)?;
+        transaction.commit()?;
+
+        info!(version, "applied database migration");
+    }
+
+    Ok(())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn token_client_name(client: TokenClient) -> &'static str {
+    match client {
+        TokenClient::Confidential => "confidential",
+        TokenClient::Public => "public",
+    }
+}
+
+fn refresh_token(row: &Row) -> rusqlite::Result<RefreshToken> {
+    Ok(RefreshToken {
+        refresh_token: Sealed {
+            key_id: KeyId(row.get("key_id")?),
+            ciphertext: row.get("refresh_token")?,
+        },
+        scope: row.get("scope")?,
+        client: match row.get_ref("client")?.as_str()? {
+            "public" => TokenClient::Public,
+            _ => TokenClient::Confidential,
+        },
+    })
+}
+
+fn session(row: &Row) -> rusqlite::Result<StoredSession> {
+    Ok(StoredSession {
+        key: SessionKey(row.get("id")?),
+        user: UserId(row.get("user_id")?),
+        created_at: row.get("created_at")?,
+        last_seen: row.get("last_seen")?,
+        user_agent: row.get("user_agent")?,
+    })
+}
+
+fn banger(row: &Row) -> rusqlite::Result<Banger> {
+    let artist_ids: Option<String> = row.get("artist_ids")?;
+
+    Ok(Banger {
+        id: BangerId(row.get("id")?),
+        track_id: row.get("track_id")?,
+        album_id: row.get("album_id")?,
+        artist_ids: artist_ids
+            .map(|ids| ids.split(',').map(str::to_owned).collect())
+            .unwrap_or_default(),
+        marked_at: row.get("marked_at")?,
+        progress_ms: row.get("progress_ms")?,
+        device: row.get("device")?,
+        context: row.get("context")?,
+        note: row.get("note")?,
+        source: match row.get_ref("source")?.as_str()? {
+            AUTO => BangerSource::Auto,
+            _ => BangerSource::Manual,
+        },
+        confidence: row.get("confidence")?,
+    })
+}
+
+fn source(source: BangerSource) -> &'static str {
+    match source {
+        BangerSource::Manual => MANUAL,
+        BangerSource::Auto => AUTO,
+    }
+}
+
+impl Repository for Sqlite {
+    fn spotify_user(&self, spotify_id: &str) -> Result<UserId, StorageError> {
+        let connection = self.connection.lock().unwrap();
+
+        connection.execute(
+            "INSERT INTO users (spotify_id, created_at) VALUES (?, ?)
+             ON CONFLICT (spotify_id) DO NOTHING",
+            params![spotify_id, unix_millis()],
+        )?;
+
+        Ok(connection.query_row(
+            "SELECT id FROM users WHERE spotify_id = ?",
+            [spotify_id],
+            |row| row.get(0).map(UserId),
+        )?)
+    }
+
+    fn github_user(&self, github_id: u64) -> Result<Option<UserId>, StorageError> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT user_id FROM identities WHERE provider = ? AND provider_id = ?",
+                params![GITHUB, github_id.to_string()],
+                |row| row.get(0).map(UserId),
+            )
+            .optional()?)
+    }
+
+    fn user(&self, id: UserId) -> Result<Option<User>, StorageError> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT users.spotify_id, identities.provider_id, identities.login
+                 FROM users
+                 LEFT JOIN identities ON identities.user_id = users.id AND identities.provider = ?
+                 WHERE users.id = ?",
+                params![GITHUB, id.0],
+                |row| {
+                    let github_id: Option<String> = row.get(1)?;
+
+                    Ok(User {
+                        id,
+                        spotify_id: row.get(0)?,
+                        github: match (github_id.and_then(|id| id.parse().ok()), row.get(2)?) {
+                            (Some(id), Some(login)) => Some(GithubIdentity { id, login }),
+                            _ => None,
+                        },
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    fn link_github(&self, id: UserId, identity: &GithubIdentity) -> Result<(), LinkError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(StorageError::from)?;
+
+        let owner = transaction
+            .query_row(
+                "SELECT user_id FROM identities WHERE provider = ? AND provider_id = ?",
+                params![GITHUB, identity.id.to_string()],
+                |row| row.get(0).map(UserId),
+            )
+            .optional()
+            .map_err(StorageError::from)?;
+
+        match owner {
+            Some(owner) if owner != id => return Err(LinkError::AlreadyLinked),
+            _ => {}
+        }
+
+        let exists = transaction
+            .query_row("SELECT 1 FROM users WHERE id = ?", [id.0], |_| Ok(()))
+            .optional()
+            .map_err(StorageError::from)?;
+
+        if exists.is_none() {
+            return Err(LinkError::UnknownUser);
+        }
+
+        transaction
+            .execute(
+                "INSERT INTO identities (provider, provider_id, user_id, login) VALUES (?, ?, ?, ?)
+                 ON CONFLICT (user_id, provider) DO UPDATE
+                 SET provider_id = excluded.provider_id, login = excluded.login",
+                params![GITHUB, identity.id.to_string(), id.0, identity.login],
+            )
+            .map_err(StorageError::from)?;
+
+        transaction.commit().map_err(StorageError::from)?;
+
+        Ok(())
+    }
+
+    fn store_refresh_token(&self, id: UserId, token: &RefreshToken) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO refresh_tokens (user_id, key_id, refresh_token, scope, client, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT (user_id) DO UPDATE
+             SET key_id = excluded.key_id,
+                 refresh_token = excluded.refresh_token,
+                 scope = excluded.scope,
+                 client = excluded.client,
+                 updated_at = excluded.updated_at",
+            params![
+                id.0,
+                token.refresh_token.key_id.0,
+                token.refresh_token.ciphertext,
+                token.scope,
+                token_client_name(token.client),
+                unix_millis()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn refresh_token(&self, id: UserId) -> Result<Option<RefreshToken>, StorageError> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT * FROM refresh_tokens WHERE user_id = ?",
+                [id.0],
+                refresh_token,
+            )
+            .optional()?)
+    }
+
+    fn forget_refresh_token(&self, id: UserId) -> Result<(), StorageError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM refresh_tokens WHERE user_id = ?", [id.0])?;
+
+        Ok(())
+    }
+
+    fn refresh_tokens(&self) -> Result<Vec<(UserId, RefreshToken)>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare("SELECT * FROM refresh_tokens")?;
+
+        let tokens = statement
+            .query_map([], |row| {
+                Ok((UserId(row.get("user_id")?), refresh_token(row)?))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(tokens)
+    }
+
+    fn create_session(
+        &self,
+        id: UserId,
+        token_hash: &[u8],
+        user_agent: Option<&str>,
+    ) -> Result<SessionKey, StorageError> {
+        let now = unix_millis();
+
+        Ok(self.connection.lock().unwrap().query_row(
+            "INSERT INTO sessions (token_hash, user_id, created_at, last_seen, user_agent)
+             VALUES (?, ?, ?, ?, ?)
+             RETURNING id",
+            params![token_hash, id.0, now, now, user_agent],
+            |row| row.get(0).map(SessionKey),
+        )?)
+    }
+
+    fn session(&self, token_hash: &[u8]) -> Result<Option<StoredSession>, StorageError> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT * FROM sessions WHERE token_hash = ?",
+                [token_hash],
+                session,
+            )
+            .optional()?)
+    }
+
+    fn touch_session(&self, key: SessionKey, last_seen: u64) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE sessions SET last_seen = ? WHERE id = ?",
+            params![last_seen, key.0],
+        )?;
+
+        Ok(())
+    }
+
+    fn end_session(&self, id: UserId, key: SessionKey) -> Result<bool, StorageError> {
+        let ended = self.connection.lock().unwrap().execute(
+            "DELETE FROM sessions WHERE id = ? AND user_id = ?",
+            params![key.0, id.0],
+        )?;
+
+        Ok(ended > 0)
+    }
+
+    fn sessions(&self, id: UserId) -> Result<Vec<StoredSession>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT * FROM sessions WHERE user_id = ? ORDER BY last_seen DESC, id DESC")?;
+
+        let sessions = statement
+            .query_map([id.0], session)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(sessions)
+    }
+
+    fn record_banger(&self, id: UserId, mark: Mark) -> Result<Banger, StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let banger_id: u64 = transaction.query_row(
+            "INSERT INTO bangers
+                (user_id, track_id, album_id, marked_at, progress_ms, device, context, note,
+                 source, confidence)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             RETURNING id",
+            params![
+                id.0,
+                mark.track_id,
+                mark.album_id,
+                mark.marked_at,
+                mark.progress_ms,
+                mark.device,
+                mark.context,
+                mark.note,
+                source(mark.source),
+                mark.confidence
+            ],
+            |row| row.get(0),
+        )?;
+
+        for (position, artist_id) in mark.artist_ids.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO banger_artists (banger_id, position, artist_id) VALUES (?, ?, ?)",
+                params![banger_id, position, artist_id],
+            )?;
+        }
+
+        let banger =
+            transaction.query_row(&format!("{BANGERS} WHERE id = ?"), [banger_id], banger)?;
+
+        transaction.commit()?;
+
+        Ok(banger)
+    }
+
+    fn bangers(&self, id: UserId, listing: &Listing) -> Result<Vec<Listed>, StorageError> {
+        let (after, order) = match listing.sort {
+            BangerSort::Recent => (
+                "(marked_at, id) < (:marked_at, :id)",
+                "marked_at DESC, id DESC",
+            ),
+            BangerSort::Count => (
+                "(times, marked_at, id) < (:times, :marked_at, :id)",
+                "times DESC, marked_at DESC, id DESC",
+            ),
+        };
+
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "SELECT * FROM ({BANGERS}
+                WHERE user_id = :user
+                AND (:from IS NULL OR marked_at >= :from)
+                AND (:to IS NULL OR marked_at < :to)
+                AND (:track IS NULL OR track_id = :track)
+                AND (:album IS NULL OR album_id = :album)
+                AND (:context IS NULL OR context = :context)
+                AND (:source IS NULL OR source = :source)
+                AND (:artist IS NULL OR EXISTS (
+                    SELECT 1 FROM banger_artists
+                    WHERE banger_id = bangers.id AND artist_id = :artist
+                ))
+            )
+            WHERE :id IS NULL OR {after}
+            ORDER BY {order}
+            LIMIT :limit"
+        ))?;
+
+        let cursor = listing.after;
+        let (times, marked_at, banger_id) = (
+            cursor.map(|cursor| cursor.times),
+            cursor.map(|cursor| cursor.marked_at),
+            cursor.map(|cursor| cursor.id.0),
+        );
+        let banger_source = listing.source.map(source);
+
+        let mut params = named_params! {
+            ":user": id.0,
+            ":from": listing.from,
+            ":to": listing.to,
+            ":track": listing.track,
+            ":album": listing.album,
+            ":context": listing.context,
+            ":source": banger_source,
+            ":artist": listing.artist,
+            ":marked_at": marked_at,
+            ":id": banger_id,
+            ":limit": listing.limit,
+        }
+        .to_vec();
+
+        // Only counted listings are paged by the count
+        if listing.sort == BangerSort::Count {
+            params.push((":times", &times));
+        }
+
+        let bangers = statement
+            .query_map(&*params, |row| {
+                Ok(Listed {
+                    banger: banger(row)?,
+                    times: row.get("times")?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(bangers)
+    }
+
+    fn edit_banger(
+        &self,
+        id: UserId,
+        banger: BangerId,
+        note: Option<&str>,
+    ) -> Result<Option<Banger>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+
+        let edited = connection.execute(
+            "UPDATE bangers SET note = ? WHERE id = ? AND user_id = ?",
+            params![note, banger.0, id.0],
+        )?;
+
+        if edited == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(connection.query_row(
+            &format!("{BANGERS} WHERE id = ?"),
+            [banger.0],
+            self::banger,
+        )?))
+    }
+
+    fn forget_banger(&self, id: UserId, banger: BangerId) -> Result<bool, StorageError> {
+        let forgotten = self.connection.lock().unwrap().execute(
+            "DELETE FROM bangers WHERE id = ? AND user_id = ?",
+            params![banger.0, id.0],
+        )?;
+
+        Ok(forgotten > 0)
+    }
+
+    fn set_listening(&self, id: UserId, listening: bool) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE users SET listening = ? WHERE id = ?",
+            params![listening, id.0],
+        )?;
+
+        Ok(())
+    }
+
+    fn is_listening(&self, id: UserId) -> Result<bool, StorageError> {
+        let listening = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("SELECT listening FROM users WHERE id = ?", [id.0], |row| {
+                row.get(0)
+            })
+            .optional()?;
+
+        Ok(listening.unwrap_or(false))
+    }
+
+    fn listeners(&self) -> Result<Vec<UserId>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT users.id FROM users
+             JOIN refresh_tokens ON refresh_tokens.user_id = users.id
+             WHERE listening
+             ORDER BY users.id",
+        )?;
+
+        let users = statement
+            .query_map([], |row| Ok(UserId(row.get(0)?)))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(users)
+    }
+
+    fn start_play(&self, id: UserId, play: &Play) -> Result<PlayId, StorageError> {
+        Ok(PlayId(self.connection.lock().unwrap().query_row(
+            "INSERT INTO plays
+                (user_id, track_id, context, device, started_at, last_seen, progress_ms, played_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+             RETURNING id",
+            params![
+                id.0,
+                play.track_id,
+                play.context,
+                play.device,
+                play.started_at,
+                play.last_seen,
+                play.progress_ms,
+                play.played_at
+            ],
+            |row| row.get(0),
+        )?))
+    }
+
+    fn extend_play(
+        &self,
+        play: PlayId,
+        last_seen: u64,
+        progress_ms: u64,
+    ) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE plays SET last_seen = ?, progress_ms = ? WHERE id = ?",
+            params![last_seen, progress_ms, play.0],
+        )?;
+
+        Ok(())
+    }
+
+    fn recently_played_after(&self, id: UserId) -> Result<Option<u64>, StorageError> {
+        let after = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT recently_played_after FROM users WHERE id = ?",
+                [id.0],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(after.flatten())
+    }
+
+    fn record_recently_played(
+        &self,
+        id: UserId,
+        plays: &[Play],
+        after: u64,
+    ) -> Result<usize, StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let mut backfilled = 0;
+
+        for play in plays {
+            let known = transaction
+                .query_row(
+                    "SELECT 1 FROM plays WHERE user_id = ? AND played_at = ?",
+                    params![id.0, play.played_at],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if known {
+                continue;
+            }
+
+            // The listener may have seen the play already, which then only needs to be told
+            // when spotify counted it
+            let reconciled = transaction.execute(
+                "UPDATE plays SET played_at = :played_at
+                 WHERE id = (
+                     SELECT id FROM plays
+                     WHERE user_id = :user_id AND track_id = :track_id AND played_at IS NULL
+                         AND started_at <= :last_seen AND last_seen >= :started_at
+                     ORDER BY abs(started_at - :started_at)
+                     LIMIT 1
+                 )",
+                named_params! {
+                    ":played_at": play.played_at,
+                    ":user_id": id.0,
+                    ":track_id": play.track_id,
+                    ":started_at": play.started_at,
+                    ":last_seen": play.last_seen,
+                },
+            )?;
+            if reconciled > 0 {
+                continue;
+            }
+
+            transaction.execute(
+                "INSERT INTO plays
+                    (user_id, track_id, context, device, started_at, last_seen, progress_ms, played_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    id.0,
+                    play.track_id,
+                    play.context,
+                    play.device,
+                    play.started_at,
+                    play.last_seen,
+                    play.progress_ms,
+                    play.played_at
+                ],
+            )?;
+            backfilled += 1;
+        }
+
+        transaction.execute(
+            "UPDATE users SET recently_played_after = max(coalesce(recently_played_after, 0), ?)
+             WHERE id = ?",
+            params![after, id.0],
+        )?;
+        transaction.commit()?;
+
+        Ok(backfilled)
+    }
+
+    fn check(&self) -> Result<(), StorageError> {
+        let version: usize =
+            self.connection
+                .lock()
+                .unwrap()
+                .pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        match version.cmp(&MIGRATIONS.len()) {
+            Ordering::Less => Err(StorageError::Outdated(version)),
+            Ordering::Equal => Ok(()),
+            Ordering::Greater => Err(StorageError::UnknownVersion(version)),
+        }
+    }
+
+    #[cfg(test)]
+    fn plays(&self, id: UserId) -> Result<Vec<Play>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT * FROM plays WHERE user_id = ? ORDER BY started_at DESC, id DESC")?;
+
+        let plays = statement
+            .query_map([id.0], |row| {
+                Ok(Play {
+                    track_id: row.get("track_id")?,
+                    context: row.get("context")?,
+                    device: row.get("device")?,
+                    started_at: row.get("started_at")?,
+                    last_seen: row.get("last_seen")?,
+                    progress_ms: row.get("progress_ms")?,
+                    played_at: row.get("played_at")?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(plays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_applied_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn check_notices_changed_version() {
+        let storage = Sqlite::in_memory().unwrap();
+
+        storage.check().unwrap();
+
+        let set_version = |version: usize| {
+            storage
+                .connection
+                .lock()
+                .unwrap()
+                .pragma_update(None, "user_version", version)
+                .unwrap()
+        };
+
+        set_version(MIGRATIONS.len() - 1);
+        assert!(matches!(storage.check(), Err(StorageError::Outdated(_))));
+
+        set_version(MIGRATIONS.len() + 1);
+        assert!(matches!(
+            storage.check(),
+            Err(StorageError::UnknownVersion(_))
+        ));
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+
+        assert!(matches!(
+            Sqlite::new(connection),
+            Err(StorageError::UnknownVersion(_))
+        ));
+    }
+
+    fn refresh_token(key_id: u32, ciphertext: &[u8]) -> RefreshToken {
+        RefreshToken {
+            refresh_token: Sealed {
+                key_id: KeyId(key_id),
+                ciphertext: ciphertext.to_vec(),
+            },
+            scope: "scope".into(),
+            client: TokenClient::Public,
+        }
+    }
+
+    #[test]
+    fn refresh_token_is_replaced() {
+        let storage = Sqlite::in_memory().unwrap();
+        let user = storage.spotify_user("spotify").unwrap();
+
+        assert_eq!(storage.refresh_token(user).unwrap(), None);
+
+        storage
+            .store_refresh_token(user, &refresh_token(1, b"first"))
+            .unwrap();
+        storage
+            .store_refresh_token(user, &refresh_token(2, b"second"))
+            .unwrap();
+
+        assert_eq!(
+            storage.refresh_token(user).unwrap(),
+            Some(refresh_token(2, b"second"))
+        );
+        assert_eq!(
+            storage.refresh_tokens().unwrap(),
+            vec![(user, refresh_token(2, b"second"))]
+        );
+
+        storage.forget_refresh_token(user).unwrap();
+
+        assert_eq!(storage.refresh_token(user).unwrap(), None);
+    }
+
+    #[test]
+    fn sessions_only_end_for_their_user() {
+        let storage = Sqlite::in_memory().unwrap();
+        let user = storage.spotify_user("spotify").unwrap();
+        let other = storage.spotify_user("other").unwrap();
+
+        let key = storage
+            .create_session(user, b"hash", Some("Firefox"))
+            .unwrap();
+        storage.create_session(other, b"other hash", None).unwrap();
+
+        let session = storage.session(b"hash").unwrap().unwrap();
+        assert_eq!(session.key, key);
+        assert_eq!(session.user, user);
+        assert_eq!(session.user_agent.as_deref(), Some("Firefox"));
+        assert_eq!(storage.sessions(user).unwrap(), vec![session]);
+
+        assert!(!storage.end_session(other, key).unwrap());
+        assert!(storage.end_session(user, key).unwrap());
+        assert!(!storage.end_session(user, key).unwrap());
+
+        assert_eq!(storage.session(b"hash").unwrap(), None);
+        assert_eq!(storage.sessions(other).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn database_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("banger-{}.sqlite", rand::random::<u64>()));
+
+        let user = Sqlite::open(&path)
+            .unwrap()
+            .spotify_user("spotify")
+            .unwrap();
+        let reopened = Sqlite::open(&path).unwrap();
+
+        assert_eq!(reopened.spotify_user("spotify").unwrap(), user);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn recently_played_is_recorded_once() {
+        let storage = Sqlite::in_memory().unwrap();
+        let user = storage.spotify_user("spotify").unwrap();
+
+        let play = |track_id: &str, started_at, last_seen, played_at| Play {
+            track_id: track_id.into(),
+            context: None,
+            device: None,
+            started_at,
+            last_seen,
+            progress_ms: last_seen - started_at,
+            played_at,
+        };
+
+        // Seen by the listener, though it missed the end of it
+        storage
+            .start_play(user, &play("seen", 10_000, 150_000, None))
+            .unwrap();
+        assert_eq!(storage.recently_played_after(user).unwrap(), None);
+
+        let recently_played = [
+            play("missed", 200_000, 380_000, Some(380_000)),
+            play("seen", 10_000, 190_000, Some(190_000)),
+        ];
+
+        assert_eq!(
+            storage
+                .record_recently_played(user, &recently_played, 380_000)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            storage
+                .record_recently_played(user, &recently_played, 380_000)
+                .unwrap(),
+            0
+        );
+        assert_eq!(storage.recently_played_after(user).unwrap(), Some(380_000));
+
+        let plays = storage
+            .plays(user)
+            .unwrap()
+            .into_iter()
+            .map(|play| (play.track_id, play.last_seen, play.played_at))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            plays,
+            [
+                ("missed".into(), 380_000, Some(380_000)),
+                ("seen".into(), 150_000, Some(190_000)),
+            ]
+        );
+    }
+}
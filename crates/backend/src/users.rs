@@ -0,0 +1,102 @@
+use std::fmt::{self, Display};
+
+use crate::storage::StorageError;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct UserId(pub(crate) i64);
+
+impl Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubIdentity {
+    pub id: u64,
+    pub login: String,
+}
+
+/// A banger user, identified by their spotify account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub id: UserId,
+    pub spotify_id: String,
+    /// Allows logging back in when access to the spotify account is lost
+    pub github: Option<GithubIdentity>,
+}
+
+#[derive(Debug)]
+pub enum LinkError {
+    /// The github account already belongs to another user
+    AlreadyLinked,
+    UnknownUser,
+    Storage(StorageError),
+}
+
+impl From<StorageError> for LinkError {
+    fn from(error: StorageError) -> Self {
+        LinkError::Storage(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn github(id: u64) -> GithubIdentity {
+        GithubIdentity {
+            id,
+            login: format!("user{id}"),
+        }
+    }
+
+    #[test]
+    fn spotify_account_maps_to_one_user() {
+        let users = Storage::in_memory();
+
+        let user = users.spotify_user("spotify").unwrap();
+
+        assert_eq!(users.spotify_user("spotify").unwrap(), user);
+        assert_ne!(users.spotify_user("other").unwrap(), user);
+    }
+
+    #[test]
+    fn linked_github_finds_user() {
+        let users = Storage::in_memory();
+        let user = users.spotify_user("spotify").unwrap();
+
+        assert_eq!(users.github_user(1).unwrap(), None);
+        users.link_github(user, &github(1)).unwrap();
+        assert_eq!(users.github_user(1).unwrap(), Some(user));
+        assert_eq!(users.user(user).unwrap().unwrap().github, Some(github(1)));
+    }
+
+    #[test]
+    fn relinking_replaces_github_account() {
+        let users = Storage::in_memory();
+        let user = users.spotify_user("spotify").unwrap();
+
+        users.link_github(user, &github(1)).unwrap();
+        users.link_github(user, &github(2)).unwrap();
+
+        assert_eq!(users.github_user(1).unwrap(), None);
+        assert_eq!(users.github_user(2).unwrap(), Some(user));
+    }
+
+    #[test]
+    fn github_account_cannot_be_shared() {
+        let users = Storage::in_memory();
+        let user = users.spotify_user("spotify").unwrap();
+        let other = users.spotify_user("other").unwrap();
+
+        users.link_github(user, &github(1)).unwrap();
+
+        assert!(matches!(
+            users.link_github(other, &github(1)),
+            Err(LinkError::AlreadyLinked)
+        ));
+        assert_eq!(users.github_user(1).unwrap(), Some(user));
+    }
+}
@@ -1 +1,5 @@
+pub mod banger;
+pub mod listener;
+pub mod now_playing;
 pub mod spotify;
+pub mod stats;
@@ -0,0 +1,51 @@
+use dioxus::{core::Scope, prelude::*};
+use gloo_net::http::Request;
+use spotify_banger_model::{Banger, NewBanger};
+
+use crate::config::api_url;
+
+const BANGERS_PATH: &str = "/bangers";
+
+async fn mark_banger(new: &NewBanger) -> Result<Banger, String> {
+    let response = Request::post(&api_url(BANGERS_PATH))
+        .json(new)
+        .map_err(|error| error.to_string())?
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.ok() {
+        return Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text()));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+#[allow(non_snake_case)]
+pub fn BangerButton(cx: Scope) -> Element {
+    let marked = use_state(&cx, || None::<Result<Banger, String>>);
+
+    let status = match marked.get() {
+        Some(Ok(_)) => rsx! { div { class: "banger_status", "Banger recorded!" } },
+        Some(Err(error)) => rsx! { div { class: "banger_status error", "{error}" } },
+        None => rsx! { Fragment {} },
+    };
+
+    cx.render(rsx! {
+        button {
+            class: "banger",
+            onclick: move |_| {
+                let marked = marked.clone();
+
+                cx.spawn(async move {
+                    marked.set(Some(mark_banger(&NewBanger::default()).await));
+                });
+            },
+            "Banger!"
+        }
+        status
+    })
+}
@@ -0,0 +1,77 @@
+use dioxus::{core::Scope, prelude::*};
+use gloo_net::http::{Request, Response};
+use spotify_banger_model::ListenerSettings;
+
+use crate::config::api_url;
+
+const LISTENER_PATH: &str = "/listener";
+
+async fn settings(response: Response) -> Result<ListenerSettings, String> {
+    if !response.ok() {
+        return Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text()));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+async fn fetch_settings() -> Result<ListenerSettings, String> {
+    let response = Request::new(&api_url(LISTENER_PATH))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    settings(response).await
+}
+
+async fn store_settings(new: &ListenerSettings) -> Result<ListenerSettings, String> {
+    let response = Request::put(&api_url(LISTENER_PATH))
+        .header("Accept", "application/json")
+        .json(new)
+        .map_err(|error| error.to_string())?
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    settings(response).await
+}
+
+/// Lets the user have the backend listen along while no tab is open
+#[allow(non_snake_case)]
+pub fn ListenAlong(cx: Scope) -> Element {
+    let settings = use_state(&cx, || None::<Result<ListenerSettings, String>>);
+
+    use_future(&cx, (), |_| {
+        let settings = settings.clone();
+
+        async move { settings.set(Some(fetch_settings().await)) }
+    });
+
+    let enabled = match settings.get() {
+        Some(Ok(settings)) => settings.enabled,
+        Some(Err(error)) => return cx.render(rsx! { div { class: "listener error", "{error}" } }),
+        None => return None,
+    };
+
+    cx.render(rsx! {
+        label {
+            class: "listener",
+            title: "Keeps track of what you play and picks up bangers, even with this page closed",
+            input {
+                r#type: "checkbox",
+                checked: "{enabled}",
+                onchange: move |_| {
+                    let settings = settings.clone();
+
+                    cx.spawn(async move {
+                        settings.set(Some(store_settings(&ListenerSettings { enabled: !enabled }).await));
+                    });
+                },
+            }
+            "Listen along in the background"
+        }
+    })
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use dioxus::{core::Scope, prelude::*};
+use gloo_timers::future::sleep;
+
+use crate::{
+    components::banger::BangerButton,
+    hooks::{
+        use_drops::{marker_percent, use_drops},
+        use_now_playing::{self, seek, use_now_playing},
+    },
+};
+
+/// How often the progress bar moves between polls
+const TICK: Duration = Duration::from_secs(1);
+
+/// Format milliseconds as `m:ss`
+pub fn timestamp(ms: u64) -> String {
+    let seconds = ms / 1000;
+
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[inline_props]
+#[allow(non_snake_case)]
+pub fn NowPlaying(cx: Scope, access_token: String) -> Element {
+    let now_playing = use_now_playing(&cx, access_token);
+    let track_id = now_playing
+        .as_ref()
+        .and_then(|playing| playing.item.as_ref()?.track_id.as_deref());
+    let drops = use_drops(&cx, track_id);
+    let seek_error = use_state(&cx, || None::<String>);
+
+    // Re-render every tick so the progress bar keeps moving between polls
+    use_future(&cx, (), |_| {
+        let update = cx.schedule_update();
+
+        async move {
+            loop {
+                sleep(TICK).await;
+                update();
+            }
+        }
+    });
+
+    let (playing, item) = match now_playing {
+        Some(
+            playing @ use_now_playing::NowPlaying {
+                item: Some(item), ..
+            },
+        ) => (playing, item),
+        Some(_) => {
+            return cx.render(rsx! {
+                div { class: "now_playing", "Something is playing" }
+            })
+        }
+        None => {
+            return cx.render(rsx! {
+                div { class: "now_playing", "Nothing is playing" }
+            })
+        }
+    };
+
+    let progress_ms = playing.progress_at(instant::now());
+    let percent = progress_ms as f64 / item.duration_ms.max(1) as f64 * 100.0;
+    let progress = timestamp(progress_ms);
+    let duration = timestamp(item.duration_ms);
+    let artists = item.artists.join(", ");
+    let state = if playing.is_playing {
+        "playing"
+    } else {
+        "paused"
+    };
+
+    let album_art = item.album_art.as_ref().map(|url| {
+        rsx! { img { class: "album_art", src: "{url}" } }
+    });
+    let banger = playing.is_track().then(|| rsx! { BangerButton {} });
+
+    let markers = drops.iter().map(|drop| {
+        let left = marker_percent(drop, item.duration_ms);
+        let at = timestamp(drop.progress_ms);
+
+        rsx! {
+            div {
+                key: "{drop.progress_ms}",
+                class: "drop_marker",
+                title: "Drop at {at}, {drop.hits} hits",
+                style: "left: {left:.2}%"
+            }
+        }
+    });
+    let jump = drops.first().map(|drop| {
+        let position_ms = drop.progress_ms;
+
+        rsx! {
+            button {
+                class: "jump_to_drop",
+                onclick: move |_| {
+                    let access_token = access_token.clone();
+                    let seek_error = seek_error.clone();
+
+                    cx.spawn(async move {
+                        seek_error.set(seek(&access_token, position_ms).await.err());
+                    });
+                },
+                "Jump to the drop"
+            }
+        }
+    });
+    let seek_error = seek_error
+        .as_ref()
+        .map(|error| rsx! { div { class: "seek_error", "{error}" } });
+
+    cx.render(rsx! {
+        div {
+            class: "now_playing {state}",
+            album_art
+            div { class: "title", "{item.name}" }
+            div { class: "artists", "{artists}" }
+            div {
+                class: "progress",
+                div {
+                    class: "progress_bar",
+                    style: "width: {percent:.2}%"
+                }
+                markers
+            }
+            div { class: "times", "{progress} / {duration}" }
+            jump
+            seek_error
+        }
+        banger
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_minutes_and_seconds() {
+        assert_eq!(timestamp(0), "0:00");
+        assert_eq!(timestamp(96_000), "1:36");
+        assert_eq!(timestamp(320_357), "5:20");
+    }
+}
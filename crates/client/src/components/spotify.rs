@@ -1,10 +1,16 @@
 use dioxus::{core::Scope, prelude::*};
 
-use crate::hooks::use_spotify::state::{SpotifySession, SpotifyState};
+use crate::{
+    components::{listener::ListenAlong, now_playing::NowPlaying, stats::Stats},
+    config::{api_url, config},
+    hooks::use_spotify::state::{SpotifySession, SpotifyState},
+};
 
 #[inline_props]
 #[allow(non_snake_case)]
 pub fn Spotify<'s>(cx: Scope<'s>, state: SpotifyState<'s>) -> Element {
+    let show_stats = use_state(&cx, || false);
+
     let spotify = match state {
         SpotifyState::Unauthorized(state) => rsx! {
             div { "Unauthorized" }
@@ -23,8 +29,38 @@ pub fn Spotify<'s>(cx: Scope<'s>, state: SpotifyState<'s>) -> Element {
 
                 let username = me.display_name.as_ref().unwrap_or(&me.id);
                 let url = &me.external_urls.spotify;
+                let access_token = session.authorization().access_token().to_owned();
+                let github = config()
+                    .providers
+                    .iter()
+                    .any(|provider| provider == "github");
+                let link_github = github.then(|| {
+                    let href = api_url("/auth/github");
+
+                    rsx! {
+                        a {
+                            class: "link-github",
+                            href: "{href}",
+                            title: "Allows recovering your bangers if you lose access to spotify",
+                            "Link GitHub"
+                        }
+                    }
+                });
+                let stats = show_stats.then(|| {
+                    let access_token = access_token.clone();
+
+                    rsx! { Stats { access_token: access_token } }
+                });
 
                 rsx! {
+                    NowPlaying { access_token: access_token }
+                    button {
+                        class: "show_stats",
+                        onclick: move |_| show_stats.modify(|shown| !shown),
+                        [if **show_stats { "Hide Stats" } else { "Stats" }]
+                    }
+                    stats
+                    ListenAlong {}
                     div {
                         "Authorized as "
                         a {
@@ -38,6 +74,7 @@ pub fn Spotify<'s>(cx: Scope<'s>, state: SpotifyState<'s>) -> Element {
                         onclick: move |_| session.unauthorize(),
                         "Unauthorize"
                     }
+                    link_github
                 }
             }
             SpotifySession::Invalid(session) => rsx! {
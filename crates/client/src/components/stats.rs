@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use dioxus::{core::Scope, prelude::*};
+use gloo_net::http::Request;
+use spotify_banger_model::{HitStats, Stats, StatsQuery};
+use spotify_banger_web_api::Error;
+use tracing::warn;
+
+use crate::{components::now_playing::timestamp, config::api_url, hooks::use_spotify::web_api};
+
+const STATS_PATH: &str = "/stats";
+
+/// How many tracks and artists to show, well within the
+/// [`MAX_IDS`](spotify_banger_web_api::MAX_IDS) that
+/// spotify resolves in a single request
+const TOP: usize = 10;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Minutes east of UTC in the time zone of the browser
+fn utc_offset() -> i32 {
+    -js_sys::Date::new_0().get_timezone_offset() as i32
+}
+
+async fn fetch_stats() -> Result<Stats, String> {
+    let query = serde_urlencoded::to_string(StatsQuery {
+        utc_offset: Some(utc_offset()),
+    })
+    .unwrap();
+
+    let response = Request::new(&format!("{}?{query}", api_url(STATS_PATH)))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.ok() {
+        return Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text()));
+    }
+
+    response.json().await.map_err(|error| error.to_string())
+}
+
+/// Look up the names of the top tracks and artists, keyed by their ids
+async fn fetch_names(access_token: &str, stats: &Stats) -> Result<HashMap<String, String>, Error> {
+    let spotify = web_api(access_token);
+
+    let mut names = HashMap::new();
+
+    let track_ids = top(&stats.tracks)
+        .map(|track| track.track_id.as_str())
+        .collect::<Vec<_>>();
+    if !track_ids.is_empty() {
+        names.extend(
+            spotify
+                .tracks(&track_ids)
+                .await?
+                .into_iter()
+                .flatten()
+                .filter_map(|track| Some((track.id?, track.name))),
+        );
+    }
+
+    let artist_ids = top(&stats.artists)
+        .map(|artist| artist.artist_id.as_str())
+        .collect::<Vec<_>>();
+    if !artist_ids.is_empty() {
+        names.extend(
+            spotify
+                .artists(&artist_ids)
+                .await?
+                .into_iter()
+                .flatten()
+                .filter_map(|artist| Some((artist.id?, artist.name))),
+        );
+    }
+
+    Ok(names)
+}
+
+fn top<T>(all: &[T]) -> std::slice::Iter<'_, T> {
+    all[..all.len().min(TOP)].iter()
+}
+
+/// Add up the histograms of every track, so that every banger counts once
+fn overall(stats: &Stats) -> ([u64; 24], [u64; 7]) {
+    stats.tracks.iter().fold(
+        ([0; 24], [0; 7]),
+        |(mut hour_of_day, mut day_of_week), track| {
+            for (total, count) in hour_of_day.iter_mut().zip(track.hits.hour_of_day) {
+                *total += count;
+            }
+            for (total, count) in day_of_week.iter_mut().zip(track.hits.day_of_week) {
+                *total += count;
+            }
+
+            (hour_of_day, day_of_week)
+        },
+    )
+}
+
+/// The height of every bar of a histogram, as a percentage of the tallest
+fn bar_heights(histogram: &[u64]) -> Vec<f64> {
+    let tallest = histogram.iter().copied().max().unwrap_or_default().max(1);
+
+    histogram
+        .iter()
+        .map(|&count| count as f64 / tallest as f64 * 100.0)
+        .collect()
+}
+
+fn summary(hits: &HitStats) -> String {
+    let mut summary = format!(
+        "{} {}, usually at {}",
+        hits.count,
+        if hits.count == 1 { "hit" } else { "hits" },
+        timestamp(hits.typical_progress_ms)
+    );
+
+    if hits.current_streak > 1 {
+        summary += &format!(", {} days in a row", hits.current_streak);
+    } else if hits.longest_streak > 1 {
+        summary += &format!(", up to {} days in a row", hits.longest_streak);
+    }
+
+    summary
+}
+
+#[inline_props]
+#[allow(non_snake_case)]
+pub fn Stats(cx: Scope, access_token: String) -> Element {
+    let stats = use_future(&cx, (), |_| fetch_stats());
+    let names = use_state(&cx, HashMap::<String, String>::new);
+
+    use_future(
+        &cx,
+        (access_token, &stats.value().is_some()),
+        |(access_token, _)| {
+            let names = names.clone();
+            let stats = stats.value().cloned();
+
+            async move {
+                if let Some(Ok(stats)) = stats {
+                    match fetch_names(&access_token, &stats).await {
+                        Ok(resolved) => names.set(resolved),
+                        Err(error) => warn!(%error, "failed to look up track and artist names"),
+                    }
+                }
+            }
+        },
+    );
+
+    let stats = match stats.value() {
+        Some(Ok(stats)) => stats,
+        Some(Err(error)) => return cx.render(rsx! { div { class: "stats error", "{error}" } }),
+        None => return cx.render(rsx! { div { class: "stats", "Loading stats" } }),
+    };
+
+    if stats.tracks.is_empty() {
+        return cx.render(rsx! { div { class: "stats", "No bangers yet" } });
+    }
+
+    let name = |id: &str| {
+        names
+            .get()
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.to_owned())
+    };
+
+    let tracks = top(&stats.tracks).map(|track| {
+        let name = name(&track.track_id);
+        let summary = summary(&track.hits);
+
+        rsx! {
+            li {
+                key: "{track.track_id}",
+                span { class: "name", "{name}" }
+                span { class: "summary", "{summary}" }
+            }
+        }
+    });
+    let artists = top(&stats.artists).map(|artist| {
+        let name = name(&artist.artist_id);
+        let summary = summary(&artist.hits);
+
+        rsx! {
+            li {
+                key: "{artist.artist_id}",
+                span { class: "name", "{name}" }
+                span { class: "summary", "{summary}" }
+            }
+        }
+    });
+
+    let (hour_of_day, day_of_week) = overall(stats);
+    let hours = bar_heights(&hour_of_day)
+        .into_iter()
+        .enumerate()
+        .map(|(hour, height)| {
+            rsx! {
+                div {
+                    class: "bar",
+                    title: "{hour}:00",
+                    style: "height: {height:.2}%"
+                }
+            }
+        });
+    let weekdays = bar_heights(&day_of_week)
+        .into_iter()
+        .zip(WEEKDAYS)
+        .map(|(height, weekday)| {
+            rsx! {
+                div {
+                    class: "bar",
+                    title: "{weekday}",
+                    style: "height: {height:.2}%"
+                }
+            }
+        });
+
+    cx.render(rsx! {
+        div {
+            class: "stats",
+            h3 { "Top tracks" }
+            ol { tracks }
+            h3 { "Top artists" }
+            ol { artists }
+            h3 { "Time of day" }
+            div { class: "histogram", hours }
+            h3 { "Day of week" }
+            div { class: "histogram", weekdays }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars_are_relative_to_tallest() {
+        assert_eq!(bar_heights(&[0, 2, 4]), [0.0, 50.0, 100.0]);
+        assert_eq!(bar_heights(&[0, 0]), [0.0, 0.0]);
+    }
+}
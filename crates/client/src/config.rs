@@ -0,0 +1,55 @@
+use std::{cell::RefCell, rc::Rc};
+
+use gloo_net::http::Request;
+use spotify_banger_model::ClientConfig;
+
+/// Always served next to the client, wherever the rest of the api lives
+const CONFIG_URL: &str = "/api/config";
+
+thread_local! {
+    static CONFIG: RefCell<Option<Rc<ClientConfig>>> = RefCell::default();
+}
+
+/// Ask the backend how the deployment serving the client is set up
+pub async fn fetch_config() -> Result<Rc<ClientConfig>, String> {
+    let response = Request::new(CONFIG_URL)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.ok() {
+        return Err(response
+            .text()
+            .await
+            .unwrap_or_else(|_| response.status_text()));
+    }
+
+    response
+        .json()
+        .await
+        .map(Rc::new)
+        .map_err(|error| error.to_string())
+}
+
+/// Hand the configuration to everything rendered from here on out
+pub fn set_config(config: Rc<ClientConfig>) {
+    CONFIG.with(|cell| *cell.borrow_mut() = Some(config));
+}
+
+/// The configuration fetched at startup
+///
+/// Nothing that needs it is rendered before it is fetched, so this panics if
+/// called any earlier
+pub fn config() -> Rc<ClientConfig> {
+    CONFIG.with(|cell| {
+        cell.borrow()
+            .clone()
+            .expect("configuration is not loaded yet")
+    })
+}
+
+/// The url of an endpoint of the backend api
+pub fn api_url(path: &str) -> String {
+    format!("{}{path}", config().api_url)
+}
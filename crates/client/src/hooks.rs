@@ -1,2 +1,4 @@
+pub mod use_drops;
+pub mod use_now_playing;
 pub mod use_persist;
 pub mod use_spotify;
@@ -0,0 +1,70 @@
+use dioxus::prelude::*;
+use gloo_net::http::Request;
+use spotify_banger_model::{TrackDrop, TrackDrops};
+use tracing::warn;
+
+use crate::config::api_url;
+
+async fn fetch_drops(track_id: &str) -> Result<Vec<TrackDrop>, String> {
+    let response = Request::new(&api_url(&format!("/tracks/{track_id}/drops")))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    if !response.ok() {
+        return Err(response.status_text());
+    }
+
+    Ok(response
+        .json::<TrackDrops>()
+        .await
+        .map_err(|error| error.to_string())?
+        .drops)
+}
+
+/// Where in the track bangers keep getting marked, most marked first
+///
+/// Empty while loading and for anything that is not a track
+pub fn use_drops<'a>(cx: &'a ScopeState, track_id: Option<&str>) -> &'a [TrackDrop] {
+    let drops = use_state(cx, Vec::new);
+
+    use_future(cx, &track_id.map(str::to_owned), |track_id| {
+        let drops = drops.clone();
+
+        async move {
+            drops.set(Vec::new());
+
+            if let Some(track_id) = track_id {
+                match fetch_drops(&track_id).await {
+                    Ok(found) => drops.set(found),
+                    Err(error) => warn!(error, "failed to fetch the drops of the track"),
+                }
+            }
+        }
+    });
+
+    drops.get()
+}
+
+/// Where to put a marker for the drop on a progress bar, as a percentage
+pub fn marker_percent(drop: &TrackDrop, duration_ms: u64) -> f64 {
+    (drop.progress_ms as f64 / duration_ms.max(1) as f64 * 100.0).min(100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_stay_on_the_bar() {
+        let drop = TrackDrop {
+            progress_ms: 96_000,
+            hits: 2,
+        };
+
+        assert_eq!(marker_percent(&drop, 192_000), 50.0);
+        assert_eq!(marker_percent(&drop, 48_000), 100.0);
+        assert_eq!(marker_percent(&drop, 0), 100.0);
+    }
+}
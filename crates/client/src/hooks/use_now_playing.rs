@@ -0,0 +1,346 @@
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use gloo_net::http::Request;
+use gloo_timers::future::sleep;
+use spotify_banger_model::{CurrentlyPlaying, Image, Playback, PlayingItem, PlayingType};
+use spotify_banger_web_api::Error;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{api_url, config},
+    hooks::use_spotify::web_api,
+};
+
+const LISTENING_PATH: &str = "/listening";
+
+/// How often to poll while everything is going well
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The longest to ever wait between two polls
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The track or episode that is playing, boiled down to what gets displayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// The Spotify ID of a track, episodes have none
+    pub track_id: Option<String>,
+    pub name: String,
+    /// The artists of a track, or the show an episode belongs to
+    pub artists: Vec<String>,
+    pub album_art: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl Item {
+    fn new(item: PlayingItem) -> Self {
+        match item {
+            PlayingItem::Track(track) => Self {
+                track_id: track.id,
+                name: track.name,
+                artists: track
+                    .artists
+                    .into_iter()
+                    .map(|artist| artist.name)
+                    .collect(),
+                album_art: largest_image(track.album.images),
+                duration_ms: track.duration_ms,
+            },
+            PlayingItem::Episode(episode) => Self {
+                track_id: None,
+                name: episode.name,
+                artists: vec![episode.show.name],
+                album_art: largest_image(episode.images)
+                    .or_else(|| largest_image(episode.show.images)),
+                duration_ms: episode.duration_ms,
+            },
+        }
+    }
+}
+
+fn largest_image(images: Vec<Image>) -> Option<String> {
+    images
+        .into_iter()
+        .max_by_key(|image| image.width.unwrap_or_default())
+        .map(|image| image.url)
+}
+
+/// What the user is listening to, as of the last poll
+#[derive(Debug, Clone, PartialEq)]
+pub struct NowPlaying {
+    /// Missing during ads and when spotify does not say what is playing
+    pub item: Option<Item>,
+    pub kind: PlayingType,
+    pub progress_ms: u64,
+    pub is_playing: bool,
+    /// When the progress was measured, in milliseconds from [`instant::now`]
+    pub polled_at: f64,
+}
+
+impl NowPlaying {
+    fn new(playing: CurrentlyPlaying, polled_at: f64) -> Self {
+        Self {
+            item: playing.item.map(Item::new),
+            kind: playing.currently_playing_type,
+            progress_ms: playing.progress_ms.unwrap_or_default(),
+            is_playing: playing.is_playing,
+            polled_at,
+        }
+    }
+
+    /// The progress at `now`, assuming playback carried on since the last poll
+    pub fn progress_at(&self, now: f64) -> u64 {
+        if !self.is_playing {
+            return self.progress_ms;
+        }
+
+        let progress = self.progress_ms + (now - self.polled_at).max(0.0) as u64;
+
+        match &self.item {
+            Some(item) => progress.min(item.duration_ms),
+            None => progress,
+        }
+    }
+
+    pub fn is_track(&self) -> bool {
+        self.kind == PlayingType::Track && self.item.is_some()
+    }
+}
+
+/// The outcome of a single poll
+#[derive(Debug)]
+enum Poll {
+    Playing(Box<Playback>),
+    /// Spotify answered with 204 No Content
+    Nothing,
+    /// Spotify answered with 429 Too Many Requests
+    RateLimited(Option<Duration>),
+    Failed,
+}
+
+impl Poll {
+    async fn fetch(access_token: &str) -> Self {
+        match web_api(access_token).playback().await {
+            Ok(Some(playback)) => Self::Playing(Box::new(playback)),
+            Ok(None) => Self::Nothing,
+            Err(Error::RateLimited(retry_after)) => Self::RateLimited(retry_after),
+            Err(error) => {
+                error!(%error, "failed to fetch the playback state");
+
+                Self::Failed
+            }
+        }
+    }
+}
+
+/// Move playback of whatever is playing to the position
+pub async fn seek(access_token: &str, position_ms: u64) -> Result<(), String> {
+    match web_api(access_token).seek(position_ms).await {
+        Ok(()) => Ok(()),
+        // Tokens from before playback could be controlled
+        Err(error) if error.status() == Some(403) => Err("Reauthorize to control playback".into()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Pass the playback on to the backend, so that it can pick out the bangers
+/// that were not marked
+///
+/// Gives back whether the backend is detecting bangers at all
+async fn forward(playback: &Playback) -> bool {
+    let response = match Request::post(&api_url(LISTENING_PATH)).json(playback) {
+        Ok(request) => request.send().await,
+        Err(error) => {
+            error!(%error, "failed to serialize playback state");
+
+            return false;
+        }
+    };
+
+    match response {
+        Ok(response) if response.status() == 404 => {
+            info!("backend is not detecting bangers, no longer forwarding playback");
+
+            false
+        }
+        Ok(response) if response.status() == 201 => {
+            info!("backend detected a banger");
+
+            true
+        }
+        Ok(_) => true,
+        Err(error) => {
+            warn!(%error, "failed to forward playback state to backend");
+
+            true
+        }
+    }
+}
+
+/// How long to wait before polling again, given the outcome of the last poll
+/// and how many polls in a row have failed, that one included
+fn next_delay(poll: &Poll, failures: u32) -> Duration {
+    match poll {
+        Poll::Playing(_) | Poll::Nothing => POLL_INTERVAL,
+        Poll::RateLimited(Some(retry_after)) => (*retry_after).max(POLL_INTERVAL),
+        Poll::RateLimited(None) | Poll::Failed => POLL_INTERVAL
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(MAX_POLL_INTERVAL),
+    }
+}
+
+/// Keep polling what the user is listening to for as long as the access token
+/// stays the same
+///
+/// [`None`] until the first poll, and whenever nothing is playing
+pub fn use_now_playing<'a>(cx: &'a ScopeState, access_token: &str) -> &'a Option<NowPlaying> {
+    let now_playing = use_state(cx, || None);
+
+    use_future(cx, &access_token.to_owned(), |access_token| {
+        let now_playing = now_playing.clone();
+
+        async move {
+            let mut failures = 0;
+            let mut detecting = config().features.auto_bangers;
+
+            loop {
+                let poll = Poll::fetch(&access_token).await;
+
+                match &poll {
+                    Poll::Playing(_) | Poll::Nothing => failures = 0,
+                    Poll::RateLimited(_) | Poll::Failed => failures += 1,
+                }
+                let delay = next_delay(&poll, failures);
+
+                match poll {
+                    Poll::Playing(playback) => {
+                        if detecting {
+                            detecting = forward(&playback).await;
+                        }
+
+                        now_playing.set(Some(NowPlaying::new(playback.playing, instant::now())));
+                    }
+                    Poll::Nothing => now_playing.set(None),
+                    Poll::RateLimited(retry_after) => {
+                        warn!(?retry_after, "rate limited by spotify");
+                    }
+                    Poll::Failed => {}
+                }
+
+                sleep(delay).await;
+            }
+        }
+    });
+
+    now_playing.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> NowPlaying {
+        NowPlaying::new(serde_json::from_str(json).unwrap(), 0.0)
+    }
+
+    #[test]
+    fn track_fixture() {
+        let playing = parse(include_str!("../../fixtures/currently_playing/track.json"));
+
+        assert_eq!(
+            playing,
+            NowPlaying {
+                item: Some(Item {
+                    track_id: Some("0DiWol3AO6WpXZgp0goxAV".into()),
+                    name: "One More Time".into(),
+                    artists: vec!["Daft Punk".into(), "Romanthony".into()],
+                    album_art: Some(
+                        "https://i.scdn.co/image/ab67616d0000b273ba5db46f4b838ef6027e6f96".into()
+                    ),
+                    duration_ms: 320357,
+                }),
+                kind: PlayingType::Track,
+                progress_ms: 96000,
+                is_playing: true,
+                polled_at: 0.0,
+            }
+        );
+        assert!(playing.is_track());
+    }
+
+    #[test]
+    fn episode_fixture() {
+        let playing = parse(include_str!(
+            "../../fixtures/currently_playing/episode.json"
+        ));
+
+        let item = playing.item.as_ref().unwrap();
+        assert_eq!(item.name, "The Episode");
+        assert_eq!(item.artists, ["The Show"]);
+        assert_eq!(
+            item.album_art.as_deref(),
+            Some("https://i.scdn.co/image/ab6765630000ba8a")
+        );
+        assert_eq!(playing.kind, PlayingType::Episode);
+        assert!(!playing.is_playing);
+        assert!(!playing.is_track());
+    }
+
+    #[test]
+    fn ad_fixture() {
+        let playing = parse(include_str!("../../fixtures/currently_playing/ad.json"));
+
+        assert_eq!(playing.item, None);
+        assert_eq!(playing.kind, PlayingType::Ad);
+        assert_eq!(playing.progress_ms, 12000);
+        assert!(!playing.is_track());
+    }
+
+    #[test]
+    fn null_item_fixture() {
+        let playing = parse(include_str!(
+            "../../fixtures/currently_playing/null_item.json"
+        ));
+
+        assert_eq!(playing.item, None);
+        assert_eq!(playing.kind, PlayingType::Unknown);
+        assert_eq!(playing.progress_ms, 0);
+    }
+
+    #[test]
+    fn progress_is_interpolated_while_playing() {
+        let playing = parse(include_str!("../../fixtures/currently_playing/track.json"));
+
+        assert_eq!(playing.progress_at(1500.0), 97500);
+        assert_eq!(playing.progress_at(-1.0), 96000);
+        assert_eq!(playing.progress_at(1_000_000.0), 320357);
+
+        let paused = NowPlaying {
+            is_playing: false,
+            ..playing
+        };
+        assert_eq!(paused.progress_at(1500.0), 96000);
+    }
+
+    #[test]
+    fn failures_back_off() {
+        assert_eq!(next_delay(&Poll::Nothing, 0), POLL_INTERVAL);
+        assert_eq!(next_delay(&Poll::Failed, 1), POLL_INTERVAL * 2);
+        assert_eq!(next_delay(&Poll::Failed, 2), POLL_INTERVAL * 4);
+        assert_eq!(next_delay(&Poll::Failed, 40), MAX_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn rate_limits_are_respected() {
+        let retry_after = Duration::from_secs(30);
+
+        assert_eq!(
+            next_delay(&Poll::RateLimited(Some(retry_after)), 1),
+            retry_after
+        );
+        assert_eq!(
+            next_delay(&Poll::RateLimited(Some(Duration::ZERO)), 1),
+            POLL_INTERVAL
+        );
+        assert_eq!(next_delay(&Poll::RateLimited(None), 1), POLL_INTERVAL * 2);
+    }
+}
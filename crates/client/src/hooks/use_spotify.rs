@@ -1,24 +1,25 @@
 use dioxus::{fermi::use_atom_state, prelude::*};
 use futures_util::StreamExt;
-use gloo_net::http::Request;
-use gloo_storage::{errors::StorageError, LocalStorage, Storage};
-use gloo_utils::window;
-use tracing::{error, trace};
+use spotify_banger_model::Me;
+use spotify_banger_web_api::{Error, Fetch, Gloo, Retry, Spotify};
+use tracing::{error, info};
 
-use self::{model::Me, state::SpotifyState};
+use self::{
+    auth::{authorize, exchange_code, fetch_authorization, logout, take_code_grant},
+    state::SpotifyState,
+};
 use crate::{
     atoms::persist::PersistAtom,
-    consts::{SPOTIFY_STATE_STORAGE, SPOTIFY_STORAGE},
+    consts::SPOTIFY_STORAGE,
     hooks::use_spotify::state::{
         InvalidSession, Session, SpotifySession, Unauthorized, ValidSession,
     },
-    oauth::{Authorization, ImplicitGrantResponse},
+    oauth::{Authorization, PkceExchange},
 };
 
 use super::use_persist::use_persist;
 
 mod auth;
-pub mod model;
 pub mod state;
 
 static SPOTIFY_CREDENTIALS: PersistAtom<Option<Authorization>> =
@@ -26,22 +27,29 @@ static SPOTIFY_CREDENTIALS: PersistAtom<Option<Authorization>> =
 
 static ME: Atom<Option<Result<Me, ()>>> = |_| None;
 
-async fn get_me(auth: &Authorization) -> Result<Result<Me, ()>, gloo_net::Error> {
-    let response = Request::new("https://api.spotify.com/v1/me")
-        .header("Authorization", &format!("Bearer {}", auth.access_token()))
-        .header("Accept", "application/json")
-        .send()
-        .await?;
-
-    Ok(if !response.ok() {
-        let error = response.json::<serde_json::Value>().await;
+thread_local! {
+    /// Shared by everything that talks to spotify, so that they all count
+    /// towards the same limits
+    static TRANSPORT: Retry<Fetch, Gloo> = Retry::new(Fetch, Gloo);
+}
 
-        error!(?error, "Spotify api returned error");
+/// Talk to spotify on behalf of the owner of the access token, retrying
+/// requests that spotify turned away for the time being
+pub fn web_api(access_token: &str) -> Spotify<Retry<Fetch, Gloo>> {
+    Spotify::new(TRANSPORT.with(Clone::clone), access_token)
+}
 
-        Err(())
-    } else {
-        Ok(response.json::<Me>().await?)
-    })
+/// Ways of obtaining a fresh [`Authorization`], or getting rid of it
+#[derive(Debug, Clone)]
+pub enum Refresh {
+    /// Pick up an existing backend session, if there is one
+    Restore,
+    /// Refresh an expired authorization, falling back to the consent screen
+    Reauthorize,
+    /// Redeem the code the consent screen redirected back with
+    Exchange(PkceExchange),
+    /// End the backend session, so that it is not restored on the next visit
+    Logout,
 }
 
 pub fn use_spotify(cx: &ScopeState) -> SpotifyState {
@@ -57,49 +65,75 @@ pub fn use_spotify(cx: &ScopeState) -> SpotifyState {
                     continue;
                 }
 
-                match get_me(&auth).await {
-                    Ok(new_me) => me.set(Some(new_me)),
-                    Err(error) => {
-                        error!(?error, "failed to fetch /me")
+                match web_api(auth.access_token()).me().await {
+                    Ok(new_me) => me.set(Some(Ok(new_me))),
+                    Err(error @ (Error::Api(_) | Error::RateLimited(_))) => {
+                        error!(%error, "Spotify api returned error");
+                        me.set(Some(Err(())));
                     }
+                    Err(error) => error!(%error, "failed to fetch /me"),
                 };
             }
         }
     });
 
-    let hash = window().location().hash().unwrap();
-    if let Some(query) = hash.strip_prefix('#') {
-        let query = serde_urlencoded::from_str::<ImplicitGrantResponse>(query).unwrap();
-        trace!(?query);
-
-        // TODO: less jank?
-        match LocalStorage::get(SPOTIFY_STATE_STORAGE) as Result<String, _> {
-            Ok(known_state) => match query.into_authorization(&known_state) {
-                Some(authorization) => {
-                    LocalStorage::set(SPOTIFY_STORAGE, &authorization).unwrap();
-                    spotify_credentials.set(Some(authorization));
+    let refresh = use_coroutine::<Refresh, _, _>(cx, |mut rx| {
+        let spotify_credentials = spotify_credentials.clone();
+        let me = me.clone();
+
+        async move {
+            while let Some(mut refresh) = rx.next().await {
+                // Requests pile up while rendering, only one refresh is needed
+                while let Ok(Some(next)) = rx.try_next() {
+                    if let Refresh::Restore = refresh {
+                        refresh = next;
+                    }
+                }
+
+                let authorization = match &refresh {
+                    Refresh::Exchange(exchange) => exchange_code(exchange).await,
+                    Refresh::Restore | Refresh::Reauthorize => fetch_authorization().await,
+                    Refresh::Logout => {
+                        if let Err(error) = logout().await {
+                            error!(?error, "failed to end backend session");
+                        }
+
+                        me.set(None);
+                        spotify_credentials.set(None);
+
+                        continue;
+                    }
+                };
+
+                let authorization = authorization.unwrap_or_else(|error| {
+                    error!(?error, "failed to obtain spotify authorization");
+
+                    None
+                });
+
+                match authorization {
+                    Some(authorization) => {
+                        info!("refreshed spotify authorization");
+
+                        me.set(None);
+                        spotify_credentials.set(Some(authorization));
+                    }
+                    None if matches!(refresh, Refresh::Reauthorize) => authorize(),
+                    None => {}
                 }
-                None => error!("States do not match, rejecting token"),
-            },
-            Err(StorageError::KeyNotFound(_)) => {
-                error!("No state saved, rejecting token");
-            }
-            Err(StorageError::SerdeError(serde_error)) => {
-                error!(%serde_error, "Encountered an error parsing spotify state local storage");
-            }
-            Err(StorageError::JsError(js_error)) => {
-                error!(%js_error, "Encountered a javascript error loading spotify state local storage",);
             }
         }
+    });
 
-        me.set(None);
-        LocalStorage::delete(SPOTIFY_STATE_STORAGE);
-        window().location().set_hash("").unwrap();
-    }
+    cx.use_hook(|_| match take_code_grant() {
+        Some(exchange) => refresh.send(Refresh::Exchange(exchange)),
+        None if spotify_credentials.is_none() => refresh.send(Refresh::Restore),
+        None => {}
+    });
 
     if let Some(authorization) = spotify_credentials.get() {
         let session = Session {
-            atom_ref: spotify_credentials,
+            refresh,
             authorization,
         };
 
@@ -1,35 +1,68 @@
+use gloo_net::http::Request;
 use gloo_storage::{LocalStorage, Storage};
-use gloo_utils::window;
+use gloo_utils::{history, window};
 use rand::Rng;
-use tracing::info;
+use tracing::{error, info, trace, warn};
+use wasm_bindgen::JsValue;
 
 use crate::{
-    consts::{SPOTIFY_CLIENT_ID, SPOTIFY_STATE_STORAGE},
-    oauth::ImplicitGrantRequest,
+    config::{api_url, config},
+    consts::{SPOTIFY_STATE_STORAGE, SPOTIFY_VERIFIER_STORAGE},
+    oauth::{
+        pkce::CodeVerifier, AccessTokenResponse, Authorization, CodeGrantRequest,
+        CodeGrantResponse, CodeGrantResponseInner, PkceExchange, PkceTokenRequest,
+    },
 };
 
 const SPOTIFY_AUTH_URL: &str = "https://accounts.spotify.com/authorize";
+const SPOTIFY_TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const SPOTIFY_SCOPE: &str =
+    "user-read-currently-playing user-read-playback-state user-modify-playback-state user-read-recently-played";
+
+const BACKEND_AUTH_PATH: &str = "/auth/spotify";
+const BACKEND_TOKEN_PATH: &str = "/auth/spotify/token";
+const BACKEND_PKCE_PATH: &str = "/auth/spotify/pkce";
+const BACKEND_LOGOUT_PATH: &str = "/logout";
+
+fn redirect_uri() -> String {
+    format!("{}/", window().location().origin().unwrap())
+}
 
 #[tracing::instrument]
 pub fn authorize() {
-    // Save the random state to local storage for verification
     let state = {
         let mut state = [0_u8; 128];
         rand::thread_rng().fill(&mut state);
 
-        let state = base64::encode(state);
-        LocalStorage::set(SPOTIFY_STATE_STORAGE, &state)
-            .expect("failed to save state to LocalStorage");
-
-        state
+        base64::encode(state)
     };
+    let verifier = CodeVerifier::random();
+
+    // Save the random state and the verifier to local storage for the redirect
+    let saved = LocalStorage::set(SPOTIFY_STATE_STORAGE, &state)
+        .and_then(|()| LocalStorage::set(SPOTIFY_VERIFIER_STORAGE, &verifier));
 
-    let query = serde_urlencoded::to_string(ImplicitGrantRequest {
+    // Without them, let the backend handle the whole flow instead
+    if let Err(error) = saved {
+        warn!(%error, "failed to save PKCE parameters to LocalStorage, authorizing through the backend");
+
+        window()
+            .location()
+            .set_href(&api_url(BACKEND_AUTH_PATH))
+            .unwrap();
+
+        return;
+    }
+
+    let config = config();
+    let query = serde_urlencoded::to_string(CodeGrantRequest {
         response_type: Default::default(),
-        client_id: SPOTIFY_CLIENT_ID,
-        scope: "user-read-currently-playing",
-        redirect_uri: &window().location().origin().unwrap(),
+        client_id: &config.spotify_client_id,
+        scope: SPOTIFY_SCOPE,
+        redirect_uri: &redirect_uri(),
         state: &state,
+        code_challenge_method: Default::default(),
+        code_challenge: &verifier.challenge(),
     })
     .unwrap();
 
@@ -39,3 +72,163 @@ pub fn authorize() {
 
     window().location().set_href(&href).unwrap();
 }
+
+/// Take the authorization code out of the url, if this page load is the
+/// redirect back from the spotify authorization page
+pub fn take_code_grant() -> Option<PkceExchange> {
+    let search = window().location().search().unwrap();
+    let grant = serde_urlencoded::from_str::<CodeGrantResponse>(search.strip_prefix('?')?)
+        .map_err(|error| trace!(%error, "query is not an authorization response"))
+        .ok()?;
+
+    let known_state = LocalStorage::get::<String>(SPOTIFY_STATE_STORAGE);
+    let verifier = LocalStorage::get::<CodeVerifier>(SPOTIFY_VERIFIER_STORAGE);
+
+    // The state and code are single use, whatever the outcome
+    LocalStorage::delete(SPOTIFY_STATE_STORAGE);
+    LocalStorage::delete(SPOTIFY_VERIFIER_STORAGE);
+    history()
+        .replace_state_with_url(
+            &JsValue::NULL,
+            "",
+            Some(&window().location().pathname().unwrap()),
+        )
+        .unwrap();
+
+    match (known_state, verifier) {
+        (Ok(known_state), Ok(code_verifier)) if known_state == grant.state => match grant.inner {
+            CodeGrantResponseInner::Success { code } => Some(PkceExchange {
+                code,
+                code_verifier,
+            }),
+            CodeGrantResponseInner::Failure { error } => {
+                error!(error, "Spotify rejected authorization request");
+
+                None
+            }
+        },
+        (Ok(_), Ok(_)) => {
+            error!("States do not match, rejecting code");
+
+            None
+        }
+        (Err(error), _) | (_, Err(error)) => {
+            error!(%error, "No PKCE parameters saved, rejecting code");
+
+            None
+        }
+    }
+}
+
+/// Fetch a fresh access token from the backend session, if there is one
+pub async fn fetch_authorization() -> Result<Option<Authorization>, gloo_net::Error> {
+    let response = Request::new(&api_url(BACKEND_TOKEN_PATH))
+        .header("Accept", "application/json")
+        .send()
+        .await?;
+
+    if !response.ok() {
+        if response.status() != 401 {
+            error!(
+                status = response.status(),
+                "backend failed to hand out an access token"
+            );
+        }
+
+        return Ok(None);
+    }
+
+    Ok(Some(
+        response
+            .json::<AccessTokenResponse>()
+            .await?
+            .into_authorization(),
+    ))
+}
+
+/// End the backend session, if there is one
+pub async fn logout() -> Result<(), gloo_net::Error> {
+    let response = Request::post(&api_url(BACKEND_LOGOUT_PATH)).send().await?;
+
+    if !response.ok() {
+        error!(status = response.status(), "backend failed to end session");
+    }
+
+    Ok(())
+}
+
+/// Redeem an authorization code, preferably through the backend so that it
+/// can keep the session alive
+pub async fn exchange_code(
+    exchange: &PkceExchange,
+) -> Result<Option<Authorization>, gloo_net::Error> {
+    let response = Request::post(&api_url(BACKEND_PKCE_PATH))
+        .header("Accept", "application/json")
+        .json(exchange)?
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.ok() => {
+            return Ok(Some(
+                response
+                    .json::<AccessTokenResponse>()
+                    .await?
+                    .into_authorization(),
+            ))
+        }
+        // Spotify already turned the code down, it will not accept it from us either
+        Ok(response) if response.status() == 401 => {
+            error!("Spotify rejected code exchange through the backend");
+
+            return Ok(None);
+        }
+        Ok(response) => {
+            warn!(
+                status = response.status(),
+                "backend failed to exchange code, exchanging it directly"
+            )
+        }
+        Err(error) => {
+            warn!(%error, "failed to reach backend, exchanging code directly")
+        }
+    }
+
+    exchange_code_directly(exchange).await
+}
+
+async fn exchange_code_directly(
+    exchange: &PkceExchange,
+) -> Result<Option<Authorization>, gloo_net::Error> {
+    let config = config();
+    let body = serde_urlencoded::to_string(PkceTokenRequest {
+        grant_type: Default::default(),
+        code: &exchange.code,
+        redirect_uri: &redirect_uri(),
+        client_id: &config.spotify_client_id,
+        code_verifier: &exchange.code_verifier,
+    })
+    .unwrap();
+
+    let response = Request::post(SPOTIFY_TOKEN_URL)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body(body)
+        .send()
+        .await?;
+
+    if !response.ok() {
+        let error = response.json::<serde_json::Value>().await;
+
+        error!(?error, "Spotify rejected code exchange");
+
+        return Ok(None);
+    }
+
+    Ok(Some(
+        response
+            .json::<AccessTokenResponse>()
+            .await?
+            .into_authorization(),
+    ))
+}
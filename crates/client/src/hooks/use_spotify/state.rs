@@ -3,8 +3,11 @@ use std::{
     rc::Rc,
 };
 
-use super::{auth::authorize, model::Me};
-use crate::{hooks::use_persist::UsePersistAtom, oauth::Authorization};
+use dioxus::hooks::CoroutineHandle;
+use spotify_banger_model::Me;
+
+use super::{auth::authorize, Refresh};
+use crate::oauth::Authorization;
 
 #[derive(Debug)]
 pub enum SpotifyState<'state> {
@@ -30,7 +33,7 @@ impl Unauthorized {
 
 #[derive(Clone)]
 pub(super) struct Session<'state> {
-    pub(super) atom_ref: &'state UsePersistAtom<Option<Authorization>>,
+    pub(super) refresh: &'state CoroutineHandle<Refresh>,
     pub(super) authorization: &'state Authorization,
 }
 
@@ -50,7 +53,7 @@ pub struct ValidSession<'state> {
 
 impl<'state> ValidSession<'state> {
     pub fn unauthorize(&self) {
-        self.session.atom_ref.set(None);
+        self.session.refresh.send(Refresh::Logout)
     }
 
     pub fn authorization(&self) -> &Authorization {
@@ -69,11 +72,11 @@ pub struct InvalidSession<'state> {
 
 impl<'state> InvalidSession<'state> {
     pub fn reauthorize(&self) {
-        authorize()
+        self.session.refresh.send(Refresh::Reauthorize)
     }
 
     pub fn unauthorize(&self) {
-        self.session.atom_ref.set(None)
+        self.session.refresh.send(Refresh::Logout)
     }
 
     pub fn authorization(&self) -> &Authorization {
@@ -1,4 +1,5 @@
 use atoms::persist::PersistAtom;
+use config::{fetch_config, set_config};
 use consts::SETTING_AUTO_REFRESH;
 use dioxus::prelude::*;
 use hooks::{
@@ -15,6 +16,7 @@ use components::spotify::Spotify;
 
 mod atoms;
 mod components;
+mod config;
 mod consts;
 mod hooks;
 mod oauth;
@@ -28,7 +30,41 @@ fn main() {
 
 static AUTO_REAUTHORIZE: PersistAtom<bool> = PersistAtom::new(SETTING_AUTO_REFRESH, || false);
 
+/// Hold off on everything until the backend says how it is set up
 fn app(cx: Scope) -> Element {
+    let config = use_future(&cx, (), |_| fetch_config());
+
+    match config.value() {
+        Some(Ok(loaded)) => {
+            set_config(loaded.clone());
+
+            cx.render(rsx! { Banger {} })
+        }
+        Some(Err(error)) => cx.render(rsx! {
+            main {
+                class: "auth_section",
+                div {
+                    class: "spotify",
+                    div { class: "error", "Failed to load configuration: {error}" }
+                    button {
+                        class: "authorize",
+                        onclick: move |_| config.restart(),
+                        "Retry"
+                    }
+                }
+            }
+        }),
+        None => cx.render(rsx! {
+            main {
+                class: "auth_section",
+                div { class: "spotify", "Loading Configuration" }
+            }
+        }),
+    }
+}
+
+#[allow(non_snake_case)]
+fn Banger(cx: Scope) -> Element {
     let auto_reauthorize = use_persist(&cx, AUTO_REAUTHORIZE);
     let spotify = use_spotify(&cx);
 
@@ -1,37 +1,65 @@
-use std::borrow::Cow;
-
 use monostate::MustBe;
 use serde::{Deserialize, Serialize};
 
+use self::pkce::CodeVerifier;
+
+pub mod pkce;
+
 #[derive(Debug, Serialize)]
-pub struct ImplicitGrantRequest<'a> {
-    pub response_type: MustBe!("token"),
+pub struct CodeGrantRequest<'a> {
+    pub response_type: MustBe!("code"),
     pub client_id: &'a str,
     pub scope: &'a str,
     pub redirect_uri: &'a str,
     pub state: &'a str,
+    pub code_challenge_method: MustBe!("S256"),
+    pub code_challenge: &'a str,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct ImplicitGrantResponse<'t> {
-    access_token: Cow<'t, str>,
-    #[allow(dead_code)]
-    token_type: MustBe!("Bearer"),
-    state: Cow<'t, str>,
-    expires_in: u64,
+#[serde(untagged)]
+pub enum CodeGrantResponseInner {
+    Success { code: String },
+    Failure { error: String },
 }
 
-impl ImplicitGrantResponse<'_> {
-    /// This should be called as soon as an [`ImplicitGrant`] is procured
-    pub fn into_authorization(self, known_state: &str) -> Option<Authorization> {
-        if self.state != known_state {
-            return None;
-        }
+#[derive(Debug, Deserialize)]
+pub struct CodeGrantResponse {
+    #[serde(flatten)]
+    pub inner: CodeGrantResponseInner,
+    pub state: String,
+}
+
+/// An authorization code along with the verifier needed to redeem it
+#[derive(Debug, Clone, Serialize)]
+pub struct PkceExchange {
+    pub code: String,
+    pub code_verifier: CodeVerifier,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PkceTokenRequest<'a> {
+    pub grant_type: MustBe!("authorization_code"),
+    pub code: &'a str,
+    pub redirect_uri: &'a str,
+    pub client_id: &'a str,
+    pub code_verifier: &'a CodeVerifier,
+}
+
+/// A short lived access token, handed out by either spotify or the backend session
+#[derive(Debug, Deserialize)]
+pub struct AccessTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
 
-        Some(Authorization {
-            access_token: self.access_token.into_owned(),
+impl AccessTokenResponse {
+    /// This should be called as soon as an [`AccessTokenResponse`] is procured
+    pub fn into_authorization(self) -> Authorization {
+        Authorization {
+            access_token: self.access_token,
             expires_at: instant::now() as u64 + self.expires_in * 1000,
-        })
+        }
     }
 }
 
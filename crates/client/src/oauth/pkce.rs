@@ -0,0 +1,60 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A PKCE code verifier, as described by [RFC 7636][rfc]
+///
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc7636#section-4.1
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CodeVerifier(String);
+
+impl CodeVerifier {
+    pub fn random() -> Self {
+        // 64 bytes encode to 86 characters, well within the allowed 43 to 128
+        let mut verifier = [0_u8; 64];
+        rand::thread_rng().fill(&mut verifier);
+
+        Self(base64::encode_config(verifier, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// The `S256` code challenge derived from this verifier
+    pub fn challenge(&self) -> String {
+        base64::encode_config(Sha256::digest(&self.0), base64::URL_SAFE_NO_PAD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_matches_rfc_example() {
+        // https://www.rfc-editor.org/rfc/rfc7636#appendix-B
+        let verifier = CodeVerifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".into());
+
+        assert_eq!(
+            verifier.challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn random_verifier_is_valid() {
+        let verifier = CodeVerifier::random();
+
+        assert!((43..=128).contains(&verifier.0.len()));
+        assert!(verifier
+            .0
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || "-._~".contains(char)));
+    }
+
+    #[test]
+    fn challenge_is_unpadded_base64url_sha256() {
+        let challenge = CodeVerifier::random().challenge();
+
+        assert_eq!(challenge.len(), 43);
+        assert!(!challenge.contains(['=', '+', '/']));
+    }
+}
@@ -0,0 +1,169 @@
+use alloc::{string::String, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct BangerId(pub u64);
+
+/// A moment in a track that a user marked as a banger
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Banger {
+    pub id: BangerId,
+    /// The Spotify ID of the track.
+    pub track_id: String,
+    /// The Spotify ID of the album of the track, if known.
+    pub album_id: Option<String>,
+    /// The Spotify IDs of the artists of the track.
+    pub artist_ids: Vec<String>,
+    /// Unix millisecond timestamp of when the banger was marked.
+    pub marked_at: u64,
+    /// Progress into the track in milliseconds when it was marked.
+    pub progress_ms: u64,
+    /// The name of the device the track was playing on, if known.
+    pub device: Option<String>,
+    /// The Spotify URI of the context the track was playing from, if any.
+    pub context: Option<String>,
+    /// A note the user left on the banger.
+    pub note: Option<String>,
+    /// Whether the user marked the banger or it was detected from how they listened.
+    pub source: BangerSource,
+    /// How sure the detector was of an automatic banger, in percent.
+    pub confidence: Option<u8>,
+}
+
+/// Who decided that something is a banger
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BangerSource {
+    /// The user pressed the button.
+    #[default]
+    Manual,
+    /// The detector picked it up from replays, seeks, skips or repeats.
+    Auto,
+}
+
+/// A request to mark whatever is currently playing as a banger
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+pub struct NewBanger {
+    /// A note to leave on the banger.
+    pub note: Option<String>,
+}
+
+/// Changes to a banger, as the body of `PATCH /api/bangers/:id`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BangerEdit {
+    /// The note to leave on the banger, replacing the one before.
+    pub note: Option<String>,
+}
+
+/// The order to list bangers in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BangerSort {
+    /// Most recently marked first.
+    #[default]
+    Recent,
+    /// Bangers of the tracks marked most often first, then most recently marked first.
+    Count,
+}
+
+/// Which bangers to list, as the query of `GET /api/bangers`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct BangerQuery {
+    /// Continue where the page before left off, see [`BangerPage::next_cursor`].
+    pub cursor: Option<String>,
+    /// The most bangers to list at once.
+    pub limit: Option<u32>,
+    /// Only bangers marked at or after this unix millisecond timestamp.
+    pub from: Option<u64>,
+    /// Only bangers marked before this unix millisecond timestamp.
+    pub to: Option<u64>,
+    /// Only bangers of the track with this Spotify ID.
+    pub track: Option<String>,
+    /// Only bangers of tracks by the artist with this Spotify ID.
+    pub artist: Option<String>,
+    /// Only bangers of tracks on the album with this Spotify ID.
+    pub album: Option<String>,
+    /// Only bangers marked while playing from the context with this Spotify URI.
+    pub context: Option<String>,
+    /// Only bangers that were marked by the user, or only ones that were detected.
+    pub source: Option<BangerSource>,
+    pub sort: BangerSort,
+}
+
+/// A page of bangers, as listed by `GET /api/bangers`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct BangerPage {
+    pub bangers: Vec<Banger>,
+    /// Pass as [`BangerQuery::cursor`] to get the next page, missing on the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn banger() {
+        let banger = round_trip::<Banger>(
+            r#"{
+                "id": 7,
+                "track_id": "0DiWol3AO6WpXZgp0goxAV",
+                "album_id": "2noRn2Aes5aoNVsU6iWThc",
+                "artist_ids": ["4tZwfgrHOc3mvqYlEYSvVi", "5K8xUu8dFEk7XhJc8nhrsU"],
+                "marked_at": 1657843200000,
+                "progress_ms": 96000,
+                "device": "Web Player (Firefox)",
+                "context": "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M",
+                "note": "the drop",
+                "source": "manual",
+                "confidence": null
+            }"#,
+        );
+
+        assert_eq!(banger.id, BangerId(7));
+    }
+
+    #[test]
+    fn banger_without_extras() {
+        let banger = round_trip::<Banger>(
+            r#"{
+                "id": 8,
+                "track_id": "0DiWol3AO6WpXZgp0goxAV",
+                "album_id": null,
+                "artist_ids": [],
+                "marked_at": 1657843200000,
+                "progress_ms": 0,
+                "device": null,
+                "context": null,
+                "note": null,
+                "source": "auto",
+                "confidence": 80
+            }"#,
+        );
+
+        assert_eq!(banger.note, None);
+        assert_eq!(banger.source, BangerSource::Auto);
+    }
+
+    #[test]
+    fn banger_page() {
+        let page = round_trip::<BangerPage>(
+            r#"{
+                "bangers": [],
+                "next_cursor": "ci4xLjE2NTc4NDMyMDAwMDAuNw"
+            }"#,
+        );
+
+        assert!(page.next_cursor.is_some());
+    }
+
+    #[test]
+    fn banger_sort() {
+        assert_eq!(round_trip::<BangerSort>(r#""recent""#), BangerSort::Recent);
+        assert_eq!(round_trip::<BangerSort>(r#""count""#), BangerSort::Count);
+    }
+}
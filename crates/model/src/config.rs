@@ -0,0 +1,45 @@
+use alloc::{string::String, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+/// What the client needs to know about the deployment serving it, so that
+/// the same build can be pointed at any backend
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ClientConfig {
+    /// The public id of the spotify app to authorize with.
+    pub spotify_client_id: String,
+    /// The names of the providers users can log in with.
+    pub providers: Vec<String>,
+    pub features: Features,
+    /// Where the backend api is served, without a trailing slash.
+    pub api_url: String,
+}
+
+/// The optional parts of the backend that are turned on
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Features {
+    /// Whether the backend picks out bangers from the playback it is sent.
+    pub auto_bangers: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn client_config() {
+        let config = round_trip::<ClientConfig>(
+            r#"{
+                "spotify_client_id": "be6201c1e3154c51b50ffb302e770db5",
+                "providers": ["github", "spotify"],
+                "features": {
+                    "auto_bangers": true
+                },
+                "api_url": "https://banger.example.com/api"
+            }"#,
+        );
+
+        assert!(config.features.auto_bangers);
+    }
+}
@@ -0,0 +1,42 @@
+use alloc::string::String;
+use core::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+/// The body of an unsuccessful response from the Web API
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorResponse {
+    pub error: Error,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Error {
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// A short description of the cause of the error.
+    pub message: String,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn error_envelope() {
+        let response = round_trip::<ErrorResponse>(
+            r#"{ "error": { "status": 401, "message": "The access token expired" } }"#,
+        );
+
+        assert_eq!(response.error.status, 401);
+        assert_eq!(response.error.to_string(), "The access token expired (401)");
+    }
+}
@@ -1,8 +1,46 @@
+//! The types shared between the backend and the client, mostly mirroring the
+//! objects of the [Spotify Web API][web-api].
+//!
+//! [web-api]: https://developer.spotify.com/documentation/web-api/reference/
+
+#![no_std]
+
+extern crate alloc;
+
+pub use self::{
+    banger::*, config::*, error::*, playing::*, playlist::*, session::*, stats::*, track::*,
+    user::*,
+};
+
+mod banger;
+mod config;
+mod error;
+mod playing;
+mod playlist;
+mod session;
+mod stats;
+mod track;
+mod user;
+
 #[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+mod test {
+    use core::fmt::Debug;
+
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::Value;
+
+    /// Deserialize the json, making sure that serializing it again gives back
+    /// the same json
+    pub fn round_trip<T>(json: &str) -> T
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let value = serde_json::from_str::<Value>(json).expect("fixture should be valid json");
+        let model = serde_json::from_value::<T>(value.clone()).expect("fixture should deserialize");
+
+        assert_eq!(serde_json::to_value(&model).unwrap(), value);
+        assert_eq!(serde_json::from_value::<T>(value).unwrap(), model);
+
+        model
     }
 }
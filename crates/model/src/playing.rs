@@ -0,0 +1,322 @@
+use alloc::{string::String, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ExternalUrls, Image, Track};
+
+/// The response of the [currently playing][endpoint] endpoint
+///
+/// [endpoint]: https://developer.spotify.com/documentation/web-api/reference/#/operations/get-the-users-currently-playing-track
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct CurrentlyPlaying {
+    /// Unix millisecond timestamp when the data was fetched.
+    pub timestamp: u64,
+    /// The context the item is being played from. null if unknown.
+    pub context: Option<Context>,
+    /// Progress into the currently playing item in milliseconds.
+    pub progress_ms: Option<u64>,
+    /// If something is currently playing.
+    pub is_playing: bool,
+    /// The currently playing item. null during ads and while nothing is playing.
+    pub item: Option<PlayingItem>,
+    /// The kind of item that is currently playing.
+    pub currently_playing_type: PlayingType,
+}
+
+/// The response of the [playback state][endpoint] endpoint, which also knows
+/// about the device that is playing
+///
+/// [endpoint]: https://developer.spotify.com/documentation/web-api/reference/#/operations/get-information-about-the-users-current-playback
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Playback {
+    /// The device that is currently active.
+    pub device: Device,
+    /// Whether the track, the context or nothing is on repeat.
+    #[serde(default)]
+    pub repeat_state: RepeatState,
+    #[serde(flatten)]
+    pub playing: CurrentlyPlaying,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Device {
+    /// The device ID. null for some devices.
+    pub id: Option<String>,
+    /// If this device is the currently active device.
+    pub is_active: bool,
+    /// A human-readable name for the device.
+    pub name: String,
+    /// Device type, such as "computer", "smartphone" or "speaker".
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The current volume in percent.
+    pub volume_percent: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatState {
+    #[default]
+    Off,
+    Track,
+    Context,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlayingItem {
+    Track(Track),
+    Episode(Episode),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayingType {
+    Track,
+    Episode,
+    Ad,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Context {
+    /// The object type of the context.
+    #[serde(rename = "type")]
+    pub ty: ContextType,
+    /// A link to the Web API endpoint providing full details of the context.
+    pub href: Option<String>,
+    /// Known external URLs for this context.
+    pub external_urls: ExternalUrls,
+    /// The Spotify URI for the context.
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextType {
+    Album,
+    Artist,
+    Playlist,
+    Show,
+    Collection,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Episode {
+    /// The episode length in milliseconds.
+    pub duration_ms: u64,
+    /// Whether or not the episode has explicit content.
+    pub explicit: bool,
+    /// Known external URLs for this episode.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint providing full details of the episode.
+    pub href: String,
+    /// The Spotify ID for the episode.
+    pub id: String,
+    /// The cover art for the episode in various sizes, widest first.
+    pub images: Vec<Image>,
+    /// The name of the episode.
+    pub name: String,
+    /// The date the episode was first released.
+    pub release_date: String,
+    /// The show on which the episode belongs.
+    pub show: Show,
+    /// The Spotify URI for the episode.
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Show {
+    /// Known external URLs for this show.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint providing full details of the show.
+    pub href: String,
+    /// The Spotify ID for the show.
+    pub id: String,
+    /// The cover art for the show in various sizes, widest first.
+    pub images: Vec<Image>,
+    /// The name of the show.
+    pub name: String,
+    /// The publisher of the show.
+    pub publisher: String,
+    /// The Spotify URI for the show.
+    pub uri: String,
+}
+
+/// The response of the [recently played tracks][endpoint] endpoint
+///
+/// [endpoint]: https://developer.spotify.com/documentation/web-api/reference/#/operations/get-recently-played
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct RecentlyPlayed {
+    /// A link to the Web API endpoint returning the full result of the request.
+    pub href: String,
+    /// The tracks that were played, most recently played first.
+    pub items: Vec<PlayHistory>,
+    /// The maximum number of items in the response.
+    pub limit: u32,
+    /// URL to the next page of items. null if none.
+    pub next: Option<String>,
+    /// The cursors used to find the next set of items. null if there are no items.
+    pub cursors: Option<Cursors>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct PlayHistory {
+    /// The track the user listened to.
+    pub track: Track,
+    /// The date and time the track was played, in ISO 8601 format.
+    pub played_at: String,
+    /// The context the track was played from.
+    pub context: Option<Context>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Cursors {
+    /// The cursor to use as key to find the next page of items, a unix
+    /// millisecond timestamp.
+    pub after: Option<String>,
+    /// The cursor to use as key to find the previous page of items, a unix
+    /// millisecond timestamp.
+    pub before: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+    use crate::{test::round_trip, track::tests::TRACK};
+
+    #[test]
+    fn playing_track() {
+        let playing = round_trip::<CurrentlyPlaying>(&format!(
+            r#"{{
+                "timestamp": 1657843200000,
+                "context": {{
+                    "type": "playlist",
+                    "href": "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M",
+                    "external_urls": {{ "spotify": "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M" }},
+                    "uri": "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"
+                }},
+                "progress_ms": 42000,
+                "is_playing": true,
+                "item": {TRACK_ITEM},
+                "currently_playing_type": "track"
+            }}"#,
+            TRACK_ITEM = TRACK.replacen('{', r#"{ "type": "track","#, 1)
+        ));
+
+        assert!(matches!(playing.item, Some(PlayingItem::Track(_))));
+        assert_eq!(playing.context.unwrap().ty, ContextType::Playlist);
+    }
+
+    #[test]
+    fn playing_episode() {
+        let playing = round_trip::<CurrentlyPlaying>(
+            r#"{
+                "timestamp": 1657843200000,
+                "context": null,
+                "progress_ms": 1000,
+                "is_playing": false,
+                "item": {
+                    "type": "episode",
+                    "duration_ms": 3600000,
+                    "explicit": false,
+                    "external_urls": { "spotify": "https://open.spotify.com/episode/512ojhOuo1ktJprKbVcKyQ" },
+                    "href": "https://api.spotify.com/v1/episodes/512ojhOuo1ktJprKbVcKyQ",
+                    "id": "512ojhOuo1ktJprKbVcKyQ",
+                    "images": [],
+                    "name": "Episode",
+                    "release_date": "2022-07-14",
+                    "show": {
+                        "external_urls": { "spotify": "https://open.spotify.com/show/38bS44xjbVVZ3No3ByF1dJ" },
+                        "href": "https://api.spotify.com/v1/shows/38bS44xjbVVZ3No3ByF1dJ",
+                        "id": "38bS44xjbVVZ3No3ByF1dJ",
+                        "images": [],
+                        "name": "Show",
+                        "publisher": "Publisher",
+                        "uri": "spotify:show:38bS44xjbVVZ3No3ByF1dJ"
+                    },
+                    "uri": "spotify:episode:512ojhOuo1ktJprKbVcKyQ"
+                },
+                "currently_playing_type": "episode"
+            }"#,
+        );
+
+        match playing.item {
+            Some(PlayingItem::Episode(episode)) => assert_eq!(episode.show.name, "Show"),
+            item => panic!("expected an episode, got {item:?}"),
+        }
+    }
+
+    #[test]
+    fn playback() {
+        let playback = round_trip::<Playback>(
+            r#"{
+                "device": {
+                    "id": "5fbb3ba6aa454b5534c4ba43a8c7e8e45a63ad0e",
+                    "is_active": true,
+                    "name": "Web Player (Firefox)",
+                    "type": "Computer",
+                    "volume_percent": 100
+                },
+                "repeat_state": "track",
+                "timestamp": 1657843200000,
+                "context": null,
+                "progress_ms": 5000,
+                "is_playing": true,
+                "item": null,
+                "currently_playing_type": "ad"
+            }"#,
+        );
+
+        assert_eq!(playback.device.name, "Web Player (Firefox)");
+        assert_eq!(playback.repeat_state, RepeatState::Track);
+        assert_eq!(playback.playing.currently_playing_type, PlayingType::Ad);
+    }
+
+    #[test]
+    fn playing_ad() {
+        let playing = round_trip::<CurrentlyPlaying>(
+            r#"{
+                "timestamp": 1657843200000,
+                "context": null,
+                "progress_ms": 5000,
+                "is_playing": true,
+                "item": null,
+                "currently_playing_type": "ad"
+            }"#,
+        );
+
+        assert_eq!(playing.item, None);
+        assert_eq!(playing.currently_playing_type, PlayingType::Ad);
+    }
+
+    #[test]
+    fn recently_played() {
+        let recently_played = round_trip::<RecentlyPlayed>(&format!(
+            r#"{{
+                "href": "https://api.spotify.com/v1/me/player/recently-played?limit=50&after=1657843200000",
+                "items": [
+                    {{
+                        "track": {TRACK},
+                        "played_at": "2022-07-15T00:03:21.521Z",
+                        "context": null
+                    }}
+                ],
+                "limit": 50,
+                "next": null,
+                "cursors": {{ "after": "1657843401521", "before": "1657843401521" }}
+            }}"#
+        ));
+
+        assert_eq!(
+            recently_played.items[0].played_at,
+            "2022-07-15T00:03:21.521Z"
+        );
+        assert_eq!(
+            recently_played.cursors.unwrap().after.as_deref(),
+            Some("1657843401521")
+        );
+    }
+}
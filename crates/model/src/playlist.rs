@@ -0,0 +1,123 @@
+use alloc::{string::String, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ExternalUrls, Image};
+
+/// A page of a list that is too long to get all at once
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Page<T> {
+    /// A link to the Web API endpoint returning the full result of the request.
+    pub href: String,
+    /// The requested content.
+    pub items: Vec<T>,
+    /// The maximum number of items in the response.
+    pub limit: u32,
+    /// URL to the next page of items. null if none.
+    pub next: Option<String>,
+    /// The offset of the items returned.
+    pub offset: u32,
+    /// URL to the previous page of items. null if none.
+    pub previous: Option<String>,
+    /// The total number of items available to return.
+    pub total: u32,
+}
+
+/// A playlist as it is listed, without its tracks
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Playlist {
+    /// true if the owner allows other users to modify the playlist.
+    pub collaborative: bool,
+    /// The playlist description. Only returned for modified, verified playlists, otherwise null.
+    pub description: Option<String>,
+    /// Known external URLs for this playlist.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint providing full details of the playlist.
+    pub href: String,
+    /// The Spotify ID for the playlist.
+    pub id: String,
+    /// Images for the playlist, widest first. May be empty.
+    pub images: Vec<Image>,
+    /// The name of the playlist.
+    pub name: String,
+    /// The user who owns the playlist.
+    pub owner: PlaylistOwner,
+    /// Whether the playlist is public, null if not relevant.
+    pub public: Option<bool>,
+    /// The version identifier for the current playlist.
+    pub snapshot_id: String,
+    /// Where to find the tracks of the playlist, and how many there are.
+    pub tracks: PlaylistTracks,
+    /// The Spotify URI for the playlist.
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct PlaylistOwner {
+    /// The name displayed on the user's profile. null if not available.
+    pub display_name: Option<String>,
+    /// Known public external URLs for this user.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint for this user.
+    pub href: String,
+    /// The Spotify user ID for this user.
+    pub id: String,
+    /// The Spotify URI for this user.
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct PlaylistTracks {
+    /// A link to the Web API endpoint where full details of the playlist's tracks can be retrieved.
+    pub href: String,
+    /// Number of tracks in the playlist.
+    pub total: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn playlists() {
+        let page = round_trip::<Page<Playlist>>(
+            r#"{
+                "href": "https://api.spotify.com/v1/me/playlists?offset=0&limit=20",
+                "items": [
+                    {
+                        "collaborative": false,
+                        "description": "",
+                        "external_urls": { "spotify": "https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M" },
+                        "href": "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M",
+                        "id": "37i9dQZF1DXcBWIGoYBM5M",
+                        "images": [],
+                        "name": "Bangers",
+                        "owner": {
+                            "display_name": "DusterTheFirst",
+                            "external_urls": { "spotify": "https://open.spotify.com/user/dusterthefirst" },
+                            "href": "https://api.spotify.com/v1/users/dusterthefirst",
+                            "id": "dusterthefirst",
+                            "uri": "spotify:user:dusterthefirst"
+                        },
+                        "public": true,
+                        "snapshot_id": "MTY1Nzg0MzIwMCwwMDAwMDAwMDAwMDAwMDAw",
+                        "tracks": {
+                            "href": "https://api.spotify.com/v1/playlists/37i9dQZF1DXcBWIGoYBM5M/tracks",
+                            "total": 42
+                        },
+                        "uri": "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"
+                    }
+                ],
+                "limit": 20,
+                "next": null,
+                "offset": 0,
+                "previous": null,
+                "total": 1
+            }"#,
+        );
+
+        assert_eq!(page.items[0].name, "Bangers");
+        assert_eq!(page.items[0].tracks.total, 42);
+    }
+}
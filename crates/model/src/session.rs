@@ -0,0 +1,42 @@
+use alloc::string::String;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct SessionKey(pub u64);
+
+/// A device the user is logged in on
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct SessionInfo {
+    pub id: SessionKey,
+    /// Unix millisecond timestamp of when the user logged in.
+    pub created_at: u64,
+    /// Unix millisecond timestamp of when the session was last used, give or take a minute.
+    pub last_seen: u64,
+    /// The user agent of the browser that logged in, if it sent one.
+    pub user_agent: Option<String>,
+    /// Whether this is the session the request was made with.
+    pub current: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn session_info() {
+        let session = round_trip::<SessionInfo>(
+            r#"{
+                "id": 3,
+                "created_at": 1657843200000,
+                "last_seen": 1657846800000,
+                "user_agent": "Mozilla/5.0 (X11; Linux x86_64; rv:102.0) Gecko/20100101 Firefox/102.0",
+                "current": true
+            }"#,
+        );
+
+        assert_eq!(session.id, SessionKey(3));
+    }
+}
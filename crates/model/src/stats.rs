@@ -0,0 +1,116 @@
+use alloc::{string::String, vec::Vec};
+
+use serde::{Deserialize, Serialize};
+
+/// How often and when something was marked as a banger
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct HitStats {
+    pub count: u64,
+    /// Unix millisecond timestamp of the first time it was marked.
+    pub first_hit: u64,
+    /// Unix millisecond timestamp of the last time it was marked.
+    pub last_hit: u64,
+    /// The most days in a row it was marked on.
+    pub longest_streak: u32,
+    /// The days in a row it was marked on, up to today or yesterday.
+    pub current_streak: u32,
+    /// How often it was marked during each hour of the day, starting at midnight.
+    pub hour_of_day: [u64; 24],
+    /// How often it was marked on each day of the week, starting on Monday.
+    pub day_of_week: [u64; 7],
+    /// The median progress into the track when it was marked, in milliseconds.
+    pub typical_progress_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct TrackStats {
+    /// The Spotify ID of the track.
+    pub track_id: String,
+    pub hits: HitStats,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ArtistStats {
+    /// The Spotify ID of the artist.
+    pub artist_id: String,
+    pub hits: HitStats,
+}
+
+/// The statistics of a user's bangers, as returned by `GET /api/stats`, most
+/// marked first
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Stats {
+    pub tracks: Vec<TrackStats>,
+    pub artists: Vec<ArtistStats>,
+}
+
+/// A moment in a track that keeps getting marked as a banger
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrackDrop {
+    /// Where in the track the drop hits, in milliseconds.
+    pub progress_ms: u64,
+    /// How many bangers were marked around it.
+    pub hits: u64,
+}
+
+/// The drops of a track, as returned by `GET /api/tracks/{id}/drops`, most
+/// marked first
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct TrackDrops {
+    /// The Spotify ID of the track.
+    pub track_id: String,
+    pub drops: Vec<TrackDrop>,
+}
+
+/// The query of `GET /api/stats`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct StatsQuery {
+    /// The time zone to count days and hours in, as minutes east of UTC.
+    pub utc_offset: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn stats() {
+        let stats = round_trip::<Stats>(
+            r#"{
+                "tracks": [{
+                    "track_id": "0DiWol3AO6WpXZgp0goxAV",
+                    "hits": {
+                        "count": 2,
+                        "first_hit": 1657843200000,
+                        "last_hit": 1657929600000,
+                        "longest_streak": 2,
+                        "current_streak": 0,
+                        "hour_of_day": [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                        "day_of_week": [0, 0, 0, 0, 1, 1, 0],
+                        "typical_progress_ms": 96000
+                    }
+                }],
+                "artists": []
+            }"#,
+        );
+
+        assert_eq!(stats.tracks[0].hits.day_of_week[4], 1);
+    }
+
+    #[test]
+    fn drops() {
+        let drops = round_trip::<TrackDrops>(
+            r#"{
+                "track_id": "0DiWol3AO6WpXZgp0goxAV",
+                "drops": [
+                    { "progress_ms": 96000, "hits": 3 },
+                    { "progress_ms": 212000, "hits": 2 }
+                ]
+            }"#,
+        );
+
+        assert_eq!(drops.drops[0].hits, 3);
+    }
+}
@@ -0,0 +1,257 @@
+use alloc::{string::String, vec::Vec};
+
+use monostate::MustBe;
+use serde::{Deserialize, Serialize};
+
+use crate::{ExternalUrls, Image};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Artist {
+    /// Known external URLs for this artist.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint providing full details of the artist.
+    pub href: Option<String>,
+    /// The Spotify ID for the artist. null for artists of local files.
+    pub id: Option<String>,
+    /// The name of the artist.
+    pub name: String,
+    /// The object type: "artist".
+    #[serde(rename = "type")]
+    ty: MustBe!("artist"),
+    /// The Spotify URI for the artist.
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumType {
+    Album,
+    Single,
+    Compilation,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Album {
+    /// The type of the album. null for albums of local files.
+    pub album_type: Option<AlbumType>,
+    /// The artists of the album.
+    pub artists: Vec<Artist>,
+    /// Known external URLs for this album.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint providing full details of the album.
+    pub href: Option<String>,
+    /// The Spotify ID for the album. null for albums of local files.
+    pub id: Option<String>,
+    /// The cover art for the album in various sizes, widest first.
+    pub images: Vec<Image>,
+    /// The name of the album.
+    pub name: String,
+    /// The date the album was first released, as precise as it is known.
+    pub release_date: Option<String>,
+    /// The object type: "album".
+    #[serde(rename = "type")]
+    ty: MustBe!("album"),
+    /// The Spotify URI for the album.
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Track {
+    /// The album on which the track appears.
+    pub album: Album,
+    /// The artists who performed the track.
+    pub artists: Vec<Artist>,
+    /// The track length in milliseconds.
+    pub duration_ms: u64,
+    /// Whether or not the track has explicit lyrics.
+    pub explicit: bool,
+    /// Known external URLs for this track.
+    pub external_urls: ExternalUrls,
+    /// A link to the Web API endpoint providing full details of the track.
+    pub href: Option<String>,
+    /// The Spotify ID for the track. null for local files.
+    pub id: Option<String>,
+    /// Whether or not the track is from a local file.
+    pub is_local: bool,
+    /// The name of the track.
+    pub name: String,
+    /// A link to a 30 second preview of the track.
+    pub preview_url: Option<String>,
+    /// The Spotify URI for the track.
+    pub uri: String,
+}
+
+/// The [audio features][endpoint] of a track
+///
+/// [endpoint]: https://developer.spotify.com/documentation/web-api/reference/#/operations/get-several-audio-features
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AudioFeatures {
+    /// A confidence measure from 0.0 to 1.0 of whether the track is acoustic.
+    pub acousticness: f64,
+    /// A URL to access the full audio analysis of this track.
+    pub analysis_url: String,
+    /// How suitable a track is for dancing, from 0.0 to 1.0.
+    pub danceability: f64,
+    /// The duration of the track in milliseconds.
+    pub duration_ms: u64,
+    /// A perceptual measure of intensity and activity, from 0.0 to 1.0.
+    pub energy: f64,
+    /// The Spotify ID for the track.
+    pub id: String,
+    /// Predicts whether a track contains no vocals, from 0.0 to 1.0.
+    pub instrumentalness: f64,
+    /// The key the track is in, in pitch class notation. -1 if no key was detected.
+    pub key: i8,
+    /// Detects the presence of an audience in the recording, from 0.0 to 1.0.
+    pub liveness: f64,
+    /// The overall loudness of a track in decibels.
+    pub loudness: f64,
+    /// The modality of the track, 1 for major and 0 for minor.
+    pub mode: u8,
+    /// Detects the presence of spoken words in a track, from 0.0 to 1.0.
+    pub speechiness: f64,
+    /// The overall estimated tempo of a track in beats per minute.
+    pub tempo: f64,
+    /// An estimated time signature, from 3 to 7 beats per bar.
+    pub time_signature: u8,
+    /// A link to the Web API endpoint providing full details of the track.
+    pub track_href: String,
+    /// The object type: "audio_features".
+    #[serde(rename = "type")]
+    ty: MustBe!("audio_features"),
+    /// The Spotify URI for the track.
+    pub uri: String,
+    /// The musical positiveness conveyed by a track, from 0.0 to 1.0.
+    pub valence: f64,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    pub const TRACK: &str = r#"{
+        "album": {
+            "album_type": "album",
+            "artists": [
+                {
+                    "external_urls": { "spotify": "https://open.spotify.com/artist/4tZwfgrHOc3mvqYlEYSvVi" },
+                    "href": "https://api.spotify.com/v1/artists/4tZwfgrHOc3mvqYlEYSvVi",
+                    "id": "4tZwfgrHOc3mvqYlEYSvVi",
+                    "name": "Daft Punk",
+                    "type": "artist",
+                    "uri": "spotify:artist:4tZwfgrHOc3mvqYlEYSvVi"
+                }
+            ],
+            "external_urls": { "spotify": "https://open.spotify.com/album/2noRn2Aes5aoNVsU6iWThc" },
+            "href": "https://api.spotify.com/v1/albums/2noRn2Aes5aoNVsU6iWThc",
+            "id": "2noRn2Aes5aoNVsU6iWThc",
+            "images": [
+                { "url": "https://i.scdn.co/image/ab67616d0000b273", "height": 640, "width": 640 }
+            ],
+            "name": "Discovery",
+            "release_date": "2001-03-12",
+            "type": "album",
+            "uri": "spotify:album:2noRn2Aes5aoNVsU6iWThc"
+        },
+        "artists": [
+            {
+                "external_urls": { "spotify": "https://open.spotify.com/artist/4tZwfgrHOc3mvqYlEYSvVi" },
+                "href": "https://api.spotify.com/v1/artists/4tZwfgrHOc3mvqYlEYSvVi",
+                "id": "4tZwfgrHOc3mvqYlEYSvVi",
+                "name": "Daft Punk",
+                "type": "artist",
+                "uri": "spotify:artist:4tZwfgrHOc3mvqYlEYSvVi"
+            }
+        ],
+        "duration_ms": 320357,
+        "explicit": false,
+        "external_urls": { "spotify": "https://open.spotify.com/track/0DiWol3AO6WpXZgp0goxAV" },
+        "href": "https://api.spotify.com/v1/tracks/0DiWol3AO6WpXZgp0goxAV",
+        "id": "0DiWol3AO6WpXZgp0goxAV",
+        "is_local": false,
+        "name": "One More Time",
+        "preview_url": null,
+        "uri": "spotify:track:0DiWol3AO6WpXZgp0goxAV"
+    }"#;
+
+    #[test]
+    fn track() {
+        let track = round_trip::<Track>(TRACK);
+
+        assert_eq!(track.id.as_deref(), Some("0DiWol3AO6WpXZgp0goxAV"));
+        assert_eq!(track.album.album_type, Some(AlbumType::Album));
+        assert_eq!(track.artists[0].name, "Daft Punk");
+    }
+
+    #[test]
+    fn local_track() {
+        let track = round_trip::<Track>(
+            r#"{
+                "album": {
+                    "album_type": null,
+                    "artists": [],
+                    "external_urls": { "spotify": "" },
+                    "href": null,
+                    "id": null,
+                    "images": [],
+                    "name": "",
+                    "release_date": null,
+                    "type": "album",
+                    "uri": null
+                },
+                "artists": [
+                    {
+                        "external_urls": { "spotify": "" },
+                        "href": null,
+                        "id": null,
+                        "name": "Unknown Artist",
+                        "type": "artist",
+                        "uri": null
+                    }
+                ],
+                "duration_ms": 183000,
+                "explicit": false,
+                "external_urls": { "spotify": "" },
+                "href": null,
+                "id": null,
+                "is_local": true,
+                "name": "demo",
+                "preview_url": null,
+                "uri": "spotify:local:Unknown+Artist::demo:183"
+            }"#,
+        );
+
+        assert!(track.is_local);
+        assert_eq!(track.id, None);
+    }
+
+    #[test]
+    fn audio_features() {
+        let features = round_trip::<AudioFeatures>(
+            r#"{
+                "acousticness": 0.0193,
+                "analysis_url": "https://api.spotify.com/v1/audio-analysis/0DiWol3AO6WpXZgp0goxAV",
+                "danceability": 0.613,
+                "duration_ms": 320357,
+                "energy": 0.697,
+                "id": "0DiWol3AO6WpXZgp0goxAV",
+                "instrumentalness": 0.00000156,
+                "key": 2,
+                "liveness": 0.332,
+                "loudness": -8.618,
+                "mode": 1,
+                "speechiness": 0.133,
+                "tempo": 122.752,
+                "time_signature": 4,
+                "track_href": "https://api.spotify.com/v1/tracks/0DiWol3AO6WpXZgp0goxAV",
+                "type": "audio_features",
+                "uri": "spotify:track:0DiWol3AO6WpXZgp0goxAV",
+                "valence": 0.476
+            }"#,
+        );
+
+        assert_eq!(features.key, 2);
+        assert_eq!(features.tempo, 122.752);
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::{string::String, vec::Vec};
+
+use monostate::MustBe;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Me {
+    /// The name displayed on the user's profile. null if not available.
+    pub display_name: Option<String>,
+    /// Known external URLs for this user.
+    pub external_urls: ExternalUrls,
+    /// Information about the followers of the user.
+    pub followers: Followers,
+    /// A link to the Web API endpoint for this user.
+    pub href: String,
+    /// The [Spotify user ID][user-id] for the user.
+    ///
+    /// [user-id]: https://developer.spotify.com/documentation/web-api/#spotify-uris-and-ids
+    pub id: String,
+    /// The user's profile image.
+    pub images: Vec<Image>,
+    /// The object type: "user".
+    #[serde(rename = "type")]
+    ty: MustBe!("user"),
+    /// The [Spotify URI][s-uri] for the user.
+    ///
+    /// [s-uri]: https://developer.spotify.com/documentation/web-api/#spotify-uris-and-ids
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct ExternalUrls {
+    /// The [Spotify URL][s-url] for the object.
+    ///
+    /// [s-url]: https://developer.spotify.com/documentation/web-api/#spotify-uris-and-ids
+    pub spotify: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Followers {
+    /// This will always be set to null, as the Web API does not support it at the moment.
+    pub href: Option<String>,
+    /// The total number of followers.
+    pub total: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Image {
+    /// The source URL of the image.
+    pub url: String,
+    /// The image height in pixels.
+    pub height: Option<u32>,
+    /// The image width in pixels.
+    pub width: Option<u32>,
+}
+
+/// Whether the backend listens along with the user, as the body of `PUT /api/listener`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ListenerSettings {
+    /// Poll what the user is playing even while no client is open.
+    pub enabled: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::round_trip;
+
+    #[test]
+    fn me() {
+        let me = round_trip::<Me>(
+            r#"{
+                "display_name": "DusterTheFirst",
+                "external_urls": { "spotify": "https://open.spotify.com/user/dusterthefirst" },
+                "followers": { "href": null, "total": 12 },
+                "href": "https://api.spotify.com/v1/users/dusterthefirst",
+                "id": "dusterthefirst",
+                "images": [
+                    { "url": "https://i.scdn.co/image/ab6775700000ee85", "height": null, "width": null }
+                ],
+                "type": "user",
+                "uri": "spotify:user:dusterthefirst"
+            }"#,
+        );
+
+        assert_eq!(me.id, "dusterthefirst");
+        assert_eq!(me.images[0].height, None);
+    }
+
+    #[test]
+    fn me_rejects_other_objects() {
+        let artist = r#"{
+            "display_name": null,
+            "external_urls": { "spotify": "https://open.spotify.com/artist/0" },
+            "followers": { "href": null, "total": 0 },
+            "href": "https://api.spotify.com/v1/artists/0",
+            "id": "0",
+            "images": [],
+            "type": "artist",
+            "uri": "spotify:artist:0"
+        }"#;
+
+        assert!(serde_json::from_str::<Me>(artist).is_err());
+    }
+
+    #[test]
+    fn listener_settings() {
+        let settings = round_trip::<ListenerSettings>(r#"{ "enabled": true }"#);
+
+        assert!(settings.enabled);
+    }
+}
@@ -0,0 +1,511 @@
+//! A typed client for the parts of the [Spotify Web API][web-api] that bangers
+//! need, over whichever HTTP transport fits the platform.
+//!
+//! [web-api]: https://developer.spotify.com/documentation/web-api/reference/
+
+use std::{
+    error,
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spotify_banger_model::{
+    Artist, AudioFeatures, ErrorResponse, Me, Page, Playback, Playlist, RecentlyPlayed, Track,
+};
+
+pub use self::{
+    timer::Timer,
+    transport::{
+        Method, Request, Response, Retry, RetryMetrics, RetryPolicy, Transport, TransportError,
+    },
+};
+
+#[cfg(feature = "reqwest")]
+pub use self::timer::Tokio;
+#[cfg(feature = "gloo-net")]
+pub use self::{timer::Gloo, transport::Fetch};
+
+mod timer;
+mod transport;
+
+pub const SPOTIFY_API_URL: &str = "https://api.spotify.com/v1";
+
+/// The most ids that spotify looks up in a single request
+pub const MAX_IDS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Transport(TransportError),
+    /// Spotify turned down the request, with the reason it gave
+    Api(spotify_banger_model::Error),
+    /// Spotify wants requests to slow down, for this long if it said so
+    RateLimited(Option<Duration>),
+    /// The response did not look like the documented one
+    Decode(String),
+}
+
+impl Error {
+    /// The status of the response spotify gave, if it gave one
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Api(error) => Some(error.status),
+            Error::RateLimited(_) => Some(429),
+            Error::Transport(_) | Error::Decode(_) => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(error) => write!(f, "failed to reach spotify: {error}"),
+            Error::Api(error) => write!(f, "spotify turned down the request: {error}"),
+            Error::RateLimited(Some(retry_after)) => {
+                write!(f, "rate limited by spotify for {}s", retry_after.as_secs())
+            }
+            Error::RateLimited(None) => write!(f, "rate limited by spotify"),
+            Error::Decode(error) => write!(f, "unexpected response from spotify: {error}"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<TransportError> for Error {
+    fn from(error: TransportError) -> Self {
+        Error::Transport(error)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Ids<'i> {
+    ids: &'i str,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tracks {
+    tracks: Vec<Option<Track>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artists {
+    artists: Vec<Option<Artist>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AudioFeaturesList {
+    audio_features: Vec<Option<AudioFeatures>>,
+}
+
+#[derive(Debug, Serialize)]
+struct PlaybackQuery {
+    additional_types: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct SeekQuery {
+    position_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct PageQuery {
+    limit: u32,
+    offset: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct RecentlyPlayedQuery {
+    limit: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<u64>,
+}
+
+/// Talks to spotify on behalf of the owner of an access token
+#[derive(Debug, Clone)]
+pub struct Spotify<T> {
+    transport: T,
+    base_url: String,
+    access_token: String,
+}
+
+impl<T: Transport> Spotify<T> {
+    pub fn new(transport: T, access_token: impl Into<String>) -> Self {
+        Self {
+            transport,
+            base_url: SPOTIFY_API_URL.into(),
+            access_token: access_token.into(),
+        }
+    }
+
+    /// Talk to something other than spotify itself, like a stand-in for tests
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// The profile of the user
+    pub async fn me(&self) -> Result<Me, Error> {
+        self.get("/me", &()).await
+    }
+
+    /// What the user is listening to, and where, if anything
+    pub async fn playback(&self) -> Result<Option<Playback>, Error> {
+        let query = PlaybackQuery {
+            additional_types: "episode",
+        };
+
+        match self.send(Method::Get, "/me/player", &query).await? {
+            Some(body) => decode(&body).map(Some),
+            // Nothing is playing, or playback is on a private session
+            None => Ok(None),
+        }
+    }
+
+    /// Jump to the position in the track that is playing
+    pub async fn seek(&self, position_ms: u64) -> Result<(), Error> {
+        self.send(Method::Put, "/me/player/seek", &SeekQuery { position_ms })
+            .await
+            .map(drop)
+    }
+
+    /// Look up tracks, up to [`MAX_IDS`] at once, with `None` for those that
+    /// do not exist
+    pub async fn tracks(&self, ids: &[&str]) -> Result<Vec<Option<Track>>, Error> {
+        let ids = ids.join(",");
+        let tracks: Tracks = self.get("/tracks", &Ids { ids: &ids }).await?;
+
+        Ok(tracks.tracks)
+    }
+
+    /// Look up artists, up to [`MAX_IDS`] at once, with `None` for those that
+    /// do not exist
+    pub async fn artists(&self, ids: &[&str]) -> Result<Vec<Option<Artist>>, Error> {
+        let ids = ids.join(",");
+        let artists: Artists = self.get("/artists", &Ids { ids: &ids }).await?;
+
+        Ok(artists.artists)
+    }
+
+    /// Look up the audio features of tracks, up to [`MAX_IDS`] at once
+    pub async fn audio_features(&self, ids: &[&str]) -> Result<Vec<Option<AudioFeatures>>, Error> {
+        let ids = ids.join(",");
+        let features: AudioFeaturesList = self.get("/audio-features", &Ids { ids: &ids }).await?;
+
+        Ok(features.audio_features)
+    }
+
+    /// The playlists the user owns or follows
+    pub async fn playlists(&self, limit: u32, offset: u32) -> Result<Page<Playlist>, Error> {
+        self.get("/me/playlists", &PageQuery { limit, offset })
+            .await
+    }
+
+    /// The tracks the user played most recently, only those played after the
+    /// unix millisecond timestamp if there is one
+    pub async fn recently_played(
+        &self,
+        after: Option<u64>,
+        limit: u32,
+    ) -> Result<RecentlyPlayed, Error> {
+        self.get(
+            "/me/player/recently-played",
+            &RecentlyPlayedQuery { limit, after },
+        )
+        .await
+    }
+
+    async fn get<R: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &impl Serialize,
+    ) -> Result<R, Error> {
+        match self.send(Method::Get, path, query).await? {
+            Some(body) => decode(&body),
+            None => Err(Error::Decode("no content".into())),
+        }
+    }
+
+    /// Send the request, giving back the body of the response unless there
+    /// was no content
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &impl Serialize,
+    ) -> Result<Option<String>, Error> {
+        let query =
+            serde_urlencoded::to_string(query).map_err(|error| Error::Decode(error.to_string()))?;
+
+        let mut url = format!("{}{path}", self.base_url);
+        if !query.is_empty() {
+            url = format!("{url}?{query}");
+        }
+
+        let response = self
+            .transport
+            .send(Request {
+                method,
+                url,
+                access_token: self.access_token.clone(),
+            })
+            .await?;
+
+        if response.status == 429 {
+            return Err(Error::RateLimited(response.retry_after));
+        }
+
+        if !response.is_success() {
+            return Err(Error::Api(
+                serde_json::from_str::<ErrorResponse>(&response.body)
+                    .map(|response| response.error)
+                    .unwrap_or_else(|_| spotify_banger_model::Error {
+                        status: response.status,
+                        message: response.body,
+                    }),
+            ));
+        }
+
+        if response.status == 204 || response.body.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(response.body))
+    }
+}
+
+fn decode<R: DeserializeOwned>(body: &str) -> Result<R, Error> {
+    serde_json::from_str(body).map_err(|error| Error::Decode(error.to_string()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::VecDeque,
+        future::{ready, Future},
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// Plays back canned responses, keeping the requests that were sent
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct Scripted {
+        responses: Arc<Mutex<VecDeque<Result<Response, TransportError>>>>,
+        pub(crate) requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl Scripted {
+        pub(crate) fn respond(&self, status: u16, body: &str) -> &Self {
+            self.respond_after(status, None, body)
+        }
+
+        pub(crate) fn respond_after(
+            &self,
+            status: u16,
+            retry_after: Option<Duration>,
+            body: &str,
+        ) -> &Self {
+            self.responses.lock().unwrap().push_back(Ok(Response {
+                status,
+                retry_after,
+                body: body.into(),
+            }));
+
+            self
+        }
+
+        pub(crate) fn fail(&self) -> &Self {
+            self.responses
+                .lock()
+                .unwrap()
+                .push_back(Err(TransportError("connection reset".into())));
+
+            self
+        }
+
+        fn urls(&self) -> Vec<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|request| request.url.clone())
+                .collect()
+        }
+    }
+
+    impl Transport for Scripted {
+        fn send(&self, request: Request) -> impl Future<Output = Result<Response, TransportError>> {
+            self.requests.lock().unwrap().push(request);
+
+            ready(
+                self.responses
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or_else(|| Err(TransportError("no response left".into()))),
+            )
+        }
+    }
+
+    fn spotify(transport: &Scripted) -> Spotify<Scripted> {
+        Spotify::new(transport.clone(), "access").with_base_url("http://spotify.test/v1")
+    }
+
+    #[tokio::test]
+    async fn me() {
+        let transport = Scripted::default();
+        transport.respond(
+            200,
+            r#"{
+                "display_name": null,
+                "external_urls": { "spotify": "https://open.spotify.com/user/dusterthefirst" },
+                "followers": { "href": null, "total": 12 },
+                "href": "https://api.spotify.com/v1/users/dusterthefirst",
+                "id": "dusterthefirst",
+                "images": [],
+                "type": "user",
+                "uri": "spotify:user:dusterthefirst"
+            }"#,
+        );
+
+        let me = spotify(&transport).me().await.unwrap();
+
+        assert_eq!(me.id, "dusterthefirst");
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].method, Method::Get);
+        assert_eq!(requests[0].url, "http://spotify.test/v1/me");
+        assert_eq!(requests[0].access_token, "access");
+    }
+
+    #[tokio::test]
+    async fn nothing_playing() {
+        let transport = Scripted::default();
+        transport.respond(204, "");
+
+        assert_eq!(spotify(&transport).playback().await, Ok(None));
+        assert_eq!(
+            transport.urls(),
+            ["http://spotify.test/v1/me/player?additional_types=episode"]
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_are_encoded() {
+        let transport = Scripted::default();
+        transport
+            .respond(200, r#"{ "tracks": [null] }"#)
+            .respond(200, r#"{ "audio_features": [null, null] }"#)
+            .respond(204, "");
+
+        let spotify = spotify(&transport);
+
+        assert_eq!(spotify.tracks(&["missing"]).await, Ok(vec![None]));
+        assert_eq!(
+            spotify.audio_features(&["first", "second"]).await,
+            Ok(vec![None, None])
+        );
+        assert_eq!(spotify.seek(96000).await, Ok(()));
+
+        assert_eq!(
+            transport.urls(),
+            [
+                "http://spotify.test/v1/tracks?ids=missing",
+                "http://spotify.test/v1/audio-features?ids=first%2Csecond",
+                "http://spotify.test/v1/me/player/seek?position_ms=96000",
+            ]
+        );
+        assert_eq!(transport.requests.lock().unwrap()[2].method, Method::Put);
+    }
+
+    #[tokio::test]
+    async fn recently_played_picks_up_after_cursor() {
+        let transport = Scripted::default();
+        let empty = r#"{
+            "href": "https://api.spotify.com/v1/me/player/recently-played",
+            "items": [],
+            "limit": 50,
+            "next": null,
+            "cursors": null
+        }"#;
+        transport.respond(200, empty).respond(200, empty);
+
+        let spotify = spotify(&transport);
+        spotify.recently_played(None, 50).await.unwrap();
+        spotify
+            .recently_played(Some(1657843200000), 50)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            transport.urls(),
+            [
+                "http://spotify.test/v1/me/player/recently-played?limit=50",
+                "http://spotify.test/v1/me/player/recently-played?limit=50&after=1657843200000",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn error_envelope_is_parsed() {
+        let transport = Scripted::default();
+        transport
+            .respond(
+                401,
+                r#"{ "error": { "status": 401, "message": "The access token expired" } }"#,
+            )
+            .respond(502, "Bad Gateway");
+
+        let spotify = spotify(&transport);
+
+        assert_eq!(
+            spotify.me().await,
+            Err(Error::Api(spotify_banger_model::Error {
+                status: 401,
+                message: "The access token expired".into()
+            }))
+        );
+        assert_eq!(
+            spotify.me().await,
+            Err(Error::Api(spotify_banger_model::Error {
+                status: 502,
+                message: "Bad Gateway".into()
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_passed_on() {
+        let transport = Scripted::default();
+        transport.respond_after(429, Some(Duration::from_secs(30)), "");
+
+        let error = spotify(&transport).playback().await.unwrap_err();
+
+        assert_eq!(error, Error::RateLimited(Some(Duration::from_secs(30))));
+        assert_eq!(error.status(), Some(429));
+    }
+
+    #[tokio::test]
+    async fn unexpected_responses_are_errors() {
+        let transport = Scripted::default();
+        transport.respond(200, r#"{ "id": "not quite a user" }"#);
+
+        assert!(matches!(
+            spotify(&transport).me().await,
+            Err(Error::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn retry_after_is_read_as_seconds() {
+        assert_eq!(
+            transport::retry_after(" 120"),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            transport::retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            None
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use std::{future::Future, time::Duration};
+
+use instant::Instant;
+
+/// Tells the time and waits out delays, using whatever timers the platform has
+pub trait Timer {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+/// The timers of the tokio runtime, to go with the reqwest transport
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+#[cfg(feature = "reqwest")]
+impl Timer for Tokio {
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// The timers of the browser, to go with the fetch transport
+#[cfg(feature = "gloo-net")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gloo;
+
+#[cfg(feature = "gloo-net")]
+impl Timer for Gloo {
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+        gloo_timers::future::sleep(duration)
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    error,
+    fmt::{self, Display},
+    future::Future,
+    time::Duration,
+};
+
+#[cfg(feature = "gloo-net")]
+pub use self::gloo::Fetch;
+pub use self::retry::{Retry, RetryMetrics, RetryPolicy};
+
+#[cfg(feature = "gloo-net")]
+mod gloo;
+#[cfg(feature = "reqwest")]
+mod reqwest;
+mod retry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Put,
+    Post,
+    Delete,
+}
+
+impl Method {
+    /// Whether sending the request twice does the same as sending it once
+    pub fn is_idempotent(self) -> bool {
+        !matches!(self, Method::Post)
+    }
+}
+
+/// A request to the Web API, on behalf of the owner of the access token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub access_token: String,
+}
+
+/// A response from the Web API, whether it succeeded or not
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    /// How long to wait before trying again, from the `Retry-After` header
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Spotify could not be reached, or its response could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportError(pub String);
+
+impl Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for TransportError {}
+
+/// Gets requests to spotify and responses back, using whatever HTTP client
+/// the platform has
+pub trait Transport {
+    fn send(&self, request: Request) -> impl Future<Output = Result<Response, TransportError>>;
+}
+
+/// Parse the seconds of a `Retry-After` header, ignoring the date form
+/// which spotify does not use
+#[cfg_attr(not(any(feature = "reqwest", feature = "gloo-net")), allow(dead_code))]
+pub(crate) fn retry_after(header: &str) -> Option<Duration> {
+    header.trim().parse().ok().map(Duration::from_secs)
+}
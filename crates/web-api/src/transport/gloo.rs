@@ -0,0 +1,47 @@
+use std::future::Future;
+
+use gloo_net::http;
+
+use super::{retry_after, Method, Request, Response, Transport, TransportError};
+
+/// Sends requests with the fetch API of the browser
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fetch;
+
+impl Transport for Fetch {
+    fn send(&self, request: Request) -> impl Future<Output = Result<Response, TransportError>> {
+        let method = match request.method {
+            Method::Get => http::Method::GET,
+            Method::Put => http::Method::PUT,
+            Method::Post => http::Method::POST,
+            Method::Delete => http::Method::DELETE,
+        };
+
+        let builder = http::Request::new(&request.url)
+            .method(method)
+            .header("Authorization", &format!("Bearer {}", request.access_token))
+            .header("Accept", "application/json");
+
+        async move {
+            let response = builder
+                .send()
+                .await
+                .map_err(|error| TransportError(error.to_string()))?;
+
+            let body = response
+                .text()
+                .await
+                .map_err(|error| TransportError(error.to_string()))?;
+
+            Ok(Response {
+                status: response.status(),
+                retry_after: response
+                    .headers()
+                    .get("Retry-After")
+                    .as_deref()
+                    .and_then(retry_after),
+                body,
+            })
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::future::Future;
+
+use reqwest::header;
+
+use super::{retry_after, Method, Request, Response, Transport, TransportError};
+
+impl Transport for reqwest::Client {
+    fn send(&self, request: Request) -> impl Future<Output = Result<Response, TransportError>> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Put => reqwest::Method::PUT,
+            Method::Post => reqwest::Method::POST,
+            Method::Delete => reqwest::Method::DELETE,
+        };
+
+        let builder = self
+            .request(method, request.url)
+            .bearer_auth(request.access_token);
+
+        async move {
+            let response = builder
+                .send()
+                .await
+                .map_err(|error| TransportError(error.to_string()))?;
+
+            let status = response.status().as_u16();
+            let retry_after = response
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|retry_after| retry_after.to_str().ok())
+                .and_then(retry_after);
+            let body = response
+                .text()
+                .await
+                .map_err(|error| TransportError(error.to_string()))?;
+
+            Ok(Response {
+                status,
+                retry_after,
+                body,
+            })
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use instant::Instant;
+use rand::Rng;
+use serde::Serialize;
+
+use super::{Request, Response, Transport, TransportError};
+use crate::timer::Timer;
+
+/// How hard to try to get a request through to spotify
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times a request is retried before its last response is passed on
+    pub max_retries: u32,
+    /// How long to back off before the first retry, doubling with every retry
+    pub base_delay: Duration,
+    /// The longest to wait before a retry, whether backing off or asked to by
+    /// spotify, anything longer is left to the caller
+    pub max_delay: Duration,
+    /// How many requests an access token can send at once
+    pub burst: u32,
+    /// How many requests an access token can send per second once its burst
+    /// is spent
+    pub per_second: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            burst: 10,
+            per_second: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to back off before the retry, with up to half of it left to
+    /// chance so that requests which failed together do not retry together
+    fn backoff(&self, retries: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max_delay);
+
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// What the retries have been up to since the transport was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RetryMetrics {
+    /// Requests sent on to spotify, retries included
+    pub sent: u64,
+    /// Requests sent again after a rate limit, a server error or not
+    /// reaching spotify at all
+    pub retries: u64,
+    /// Requests held back to keep their access token under the rate limit
+    pub throttled: u64,
+    /// Responses that were 429 Too Many Requests
+    pub rate_limited: u64,
+    /// Requests given up on, with their last response passed on as is
+    pub gave_up: u64,
+}
+
+/// The requests an access token has left, refilling over time
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// Spotify asked the access token to back off until then
+    paused_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(policy: &RetryPolicy, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(policy.burst),
+            updated_at: now,
+            paused_until: None,
+        }
+    }
+
+    fn tokens_at(&self, policy: &RetryPolicy, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+
+        (self.tokens + elapsed.as_secs_f64() * policy.per_second).min(f64::from(policy.burst))
+    }
+
+    /// Take a request out of the bucket, or find out how long until there is
+    /// one to take
+    fn take(&mut self, policy: &RetryPolicy, now: Instant) -> Result<(), Duration> {
+        if let Some(paused_until) = self.paused_until.filter(|&until| until > now) {
+            return Err(paused_until - now);
+        }
+
+        self.tokens = self.tokens_at(policy, now);
+        self.updated_at = self.updated_at.max(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / policy.per_second,
+            ))
+        }
+    }
+
+    /// Whether the bucket is no different from a new one
+    fn is_idle(&self, policy: &RetryPolicy, now: Instant) -> bool {
+        self.paused_until.is_none_or(|until| until <= now)
+            && self.tokens_at(policy, now) >= f64::from(policy.burst)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    buckets: HashMap<String, Bucket>,
+    metrics: RetryMetrics,
+}
+
+/// Sends requests through another transport, retrying those that spotify
+/// turned away for the time being and holding back access tokens that are
+/// about to hit the rate limit
+///
+/// Clones share their buckets and metrics
+#[derive(Debug, Clone)]
+pub struct Retry<T, C> {
+    transport: T,
+    timer: C,
+    policy: RetryPolicy,
+    state: Arc<Mutex<State>>,
+}
+
+impl<T, C> Retry<T, C> {
+    pub fn new(transport: T, timer: C) -> Self {
+        Self {
+            transport,
+            timer,
+            policy: RetryPolicy::default(),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn metrics(&self) -> RetryMetrics {
+        self.state.lock().unwrap().metrics
+    }
+}
+
+impl<T: Transport, C: Timer> Retry<T, C> {
+    /// Wait until the access token can send another request
+    async fn acquire(&self, access_token: &str) {
+        let mut throttled = false;
+
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = self.timer.now();
+                let policy = &self.policy;
+
+                state
+                    .buckets
+                    .retain(|_, bucket| !bucket.is_idle(policy, now));
+
+                let taken = state
+                    .buckets
+                    .entry(access_token.to_owned())
+                    .or_insert_with(|| Bucket::new(policy, now))
+                    .take(policy, now);
+
+                match taken {
+                    Ok(()) => return,
+                    Err(wait) => {
+                        if !throttled {
+                            throttled = true;
+                            state.metrics.throttled += 1;
+                        }
+
+                        wait
+                    }
+                }
+            };
+
+            self.timer.sleep(wait).await;
+        }
+    }
+
+    /// How long to wait before trying the request again, if it is worth
+    /// trying again at all
+    fn retry_delay(
+        &self,
+        request: &Request,
+        result: &Result<Response, TransportError>,
+        retries: u32,
+    ) -> Option<Duration> {
+        match result {
+            Ok(response) if response.status == 429 => Some(
+                response
+                    .retry_after
+                    .unwrap_or_else(|| self.policy.backoff(retries)),
+            ),
+            Ok(response)
+                if matches!(response.status, 500 | 502 | 503 | 504)
+                    && request.method.is_idempotent() =>
+            {
+                Some(
+                    response
+                        .retry_after
+                        .unwrap_or_else(|| self.policy.backoff(retries)),
+                )
+            }
+            Err(_) if request.method.is_idempotent() => Some(self.policy.backoff(retries)),
+            Ok(_) | Err(_) => None,
+        }
+    }
+}
+
+impl<T: Transport, C: Timer> Transport for Retry<T, C> {
+    async fn send(&self, request: Request) -> Result<Response, TransportError> {
+        let mut retries = 0;
+
+        loop {
+            self.acquire(&request.access_token).await;
+
+            let result = self.transport.send(request.clone()).await;
+            let delay = self.retry_delay(&request, &result, retries);
+            let rate_limited = matches!(&result, Ok(response) if response.status == 429);
+
+            let delay = {
+                let mut state = self.state.lock().unwrap();
+                state.metrics.sent += 1;
+
+                if rate_limited {
+                    state.metrics.rate_limited += 1;
+                }
+
+                let delay = match delay {
+                    Some(delay) => delay,
+                    None => return result,
+                };
+
+                // Everything else sent with the access token has to wait as
+                // well, all the more so when the wait is too long to retry
+                if rate_limited {
+                    let now = self.timer.now();
+
+                    state
+                        .buckets
+                        .entry(request.access_token.clone())
+                        .or_insert_with(|| Bucket::new(&self.policy, now))
+                        .paused_until = Some(now + delay);
+                }
+
+                if retries >= self.policy.max_retries || delay > self.policy.max_delay {
+                    state.metrics.gave_up += 1;
+
+                    return result;
+                }
+
+                state.metrics.retries += 1;
+
+                delay
+            };
+
+            retries += 1;
+            self.timer.sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{ready, Future};
+
+    use super::*;
+    use crate::{tests::Scripted, Method};
+
+    /// Time that only moves when something sleeps
+    #[derive(Debug, Clone)]
+    struct Manual {
+        now: Arc<Mutex<Instant>>,
+        slept: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl Manual {
+        fn new() -> Self {
+            Self {
+                now: Arc::new(Mutex::new(Instant::now())),
+                slept: Arc::default(),
+            }
+        }
+
+        fn slept(&self) -> Vec<Duration> {
+            self.slept.lock().unwrap().clone()
+        }
+    }
+
+    impl Timer for Manual {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) -> impl Future<Output = ()> {
+            *self.now.lock().unwrap() += duration;
+            self.slept.lock().unwrap().push(duration);
+
+            ready(())
+        }
+    }
+
+    fn retry(transport: &Scripted, timer: &Manual) -> Retry<Scripted, Manual> {
+        Retry::new(transport.clone(), timer.clone())
+    }
+
+    fn request(method: Method, access_token: &str) -> Request {
+        Request {
+            method,
+            url: "http://spotify.test/v1/me/player".into(),
+            access_token: access_token.into(),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter() {
+        let policy = RetryPolicy::default();
+
+        for retries in 0..3 {
+            let delay = policy.backoff(retries);
+            let full = policy.base_delay * 2u32.pow(retries);
+
+            assert!(delay >= full / 2 && delay <= full, "{delay:?}");
+        }
+
+        assert!(policy.backoff(40) <= policy.max_delay);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_waited_out() {
+        let transport = Scripted::default();
+        transport
+            .respond_after(429, Some(Duration::from_secs(2)), "")
+            .respond_after(429, Some(Duration::from_secs(1)), "")
+            .respond(200, "{}");
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        let response = retry.send(request(Method::Get, "access")).await.unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            timer.slept(),
+            [Duration::from_secs(2), Duration::from_secs(1)]
+        );
+        assert_eq!(
+            retry.metrics(),
+            RetryMetrics {
+                sent: 3,
+                retries: 2,
+                rate_limited: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn long_rate_limits_are_passed_on() {
+        let transport = Scripted::default();
+        transport.respond_after(429, Some(Duration::from_secs(120)), "");
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        let response = retry.send(request(Method::Get, "access")).await.unwrap();
+
+        assert_eq!(response.status, 429);
+        assert_eq!(response.retry_after, Some(Duration::from_secs(120)));
+        assert!(timer.slept().is_empty());
+        assert_eq!(retry.metrics().gave_up, 1);
+    }
+
+    #[tokio::test]
+    async fn long_rate_limits_still_hold_back_the_access_token() {
+        let transport = Scripted::default();
+        transport
+            .respond_after(429, Some(Duration::from_secs(120)), "")
+            .respond(204, "")
+            .respond(204, "");
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        retry.send(request(Method::Get, "access")).await.unwrap();
+        retry.send(request(Method::Get, "other")).await.unwrap();
+        assert!(timer.slept().is_empty());
+
+        retry.send(request(Method::Get, "access")).await.unwrap();
+        assert_eq!(timer.slept(), [Duration::from_secs(120)]);
+    }
+
+    #[tokio::test]
+    async fn server_errors_back_off() {
+        let transport = Scripted::default();
+        transport
+            .respond(503, "")
+            .fail()
+            .respond(502, "")
+            .respond(200, "{}");
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        let response = retry.send(request(Method::Put, "access")).await.unwrap();
+
+        assert_eq!(response.status, 200);
+
+        let policy = RetryPolicy::default();
+        let slept = timer.slept();
+        assert_eq!(slept.len(), 3);
+        for (retries, delay) in slept.into_iter().enumerate() {
+            let full = policy.base_delay * 2u32.pow(retries as u32);
+
+            assert!(delay >= full / 2 && delay <= full, "{delay:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_run_out() {
+        let transport = Scripted::default();
+        for _ in 0..5 {
+            transport.respond_after(503, Some(Duration::from_secs(1)), "");
+        }
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        let response = retry.send(request(Method::Get, "access")).await.unwrap();
+
+        assert_eq!(response.status, 503);
+        assert_eq!(transport.requests.lock().unwrap().len(), 4);
+        assert_eq!(
+            retry.metrics(),
+            RetryMetrics {
+                sent: 4,
+                retries: 3,
+                gave_up: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_are_only_retried_when_rate_limited() {
+        let transport = Scripted::default();
+        transport
+            .respond(503, "")
+            .respond_after(429, Some(Duration::ZERO), "")
+            .respond(201, "");
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        let response = retry.send(request(Method::Post, "access")).await.unwrap();
+        assert_eq!(response.status, 503);
+
+        let response = retry.send(request(Method::Post, "access")).await.unwrap();
+        assert_eq!(response.status, 201);
+
+        assert_eq!(retry.metrics().retries, 1);
+    }
+
+    #[tokio::test]
+    async fn bursts_are_throttled_per_access_token() {
+        let transport = Scripted::default();
+        for _ in 0..4 {
+            transport.respond(204, "");
+        }
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer).with_policy(RetryPolicy {
+            burst: 2,
+            per_second: 4.0,
+            ..Default::default()
+        });
+
+        for _ in 0..3 {
+            retry.send(request(Method::Get, "first")).await.unwrap();
+        }
+        assert_eq!(timer.slept(), [Duration::from_millis(250)]);
+
+        retry.send(request(Method::Get, "second")).await.unwrap();
+        assert_eq!(timer.slept().len(), 1);
+
+        assert_eq!(retry.metrics().throttled, 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_hold_back_the_access_token() {
+        let transport = Scripted::default();
+        let timer = Manual::new();
+        let retry = retry(&transport, &timer);
+
+        let now = timer.now();
+        retry.state.lock().unwrap().buckets.insert(
+            "access".into(),
+            Bucket {
+                paused_until: Some(now + Duration::from_secs(3)),
+                ..Bucket::new(&retry.policy, now)
+            },
+        );
+
+        transport.respond(204, "");
+        retry.send(request(Method::Get, "access")).await.unwrap();
+
+        assert_eq!(timer.slept(), [Duration::from_secs(3)]);
+        assert_eq!(retry.metrics().throttled, 1);
+    }
+}